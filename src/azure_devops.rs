@@ -0,0 +1,352 @@
+//! Pull requests through the Azure DevOps Git REST API.
+
+use crate::discovery::DiscoveredRepo;
+use crate::pull_request::{is_gavin_branch, NewPullRequest, PullRequest, PullRequestOutcome};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// API host for organizations on dev.azure.com, which also serves
+/// `*.visualstudio.com` organizations.
+pub const DEFAULT_API_URL: &str = "https://dev.azure.com";
+
+const API_VERSION: &str = "7.1";
+
+/// Pull requests per page when listing them.
+const PAGE_SIZE: usize = 100;
+
+/// The organization, project and repository named by a clone URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AzureRepo {
+    pub organization: String,
+    pub project: String,
+    pub repository: String,
+}
+
+impl AzureRepo {
+    /// Parses `https://dev.azure.com/org/project/_git/repo` (optionally with a
+    /// user before the host) or `https://org.visualstudio.com/project/_git/repo`.
+    pub fn parse(url: &str) -> Option<Self> {
+        let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+        let rest = rest.split_once('@').map_or(rest, |(_, host)| host);
+        let (host, path) = rest.split_once('/')?;
+        let segments: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+
+        let (organization, project, repository) = match (host, segments.as_slice()) {
+            ("dev.azure.com", [organization, project, "_git", repository]) => {
+                (*organization, *project, *repository)
+            }
+            (host, [project, "_git", repository]) if host.ends_with(".visualstudio.com") => (
+                host.trim_end_matches(".visualstudio.com"),
+                *project,
+                *repository,
+            ),
+            _ => return None,
+        };
+
+        Some(AzureRepo {
+            organization: organization.to_string(),
+            project: project.to_string(),
+            repository: repository.to_string(),
+        })
+    }
+}
+
+/// An organization, or one project in it, to discover repositories in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AzureScope {
+    pub organization: String,
+    pub project: Option<String>,
+}
+
+impl AzureScope {
+    /// Parses `org` or `org/project`.
+    pub fn parse(scope: &str) -> Option<Self> {
+        match scope.trim_matches('/').split('/').collect::<Vec<_>>()[..] {
+            [organization] if !organization.is_empty() => Some(AzureScope {
+                organization: organization.to_string(),
+                project: None,
+            }),
+            [organization, project] => Some(AzureScope {
+                organization: organization.to_string(),
+                project: Some(project.to_string()),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Whether a stored repository URL belongs to this scope.
+    pub fn contains(&self, url: &str) -> bool {
+        AzureRepo::parse(url).is_some_and(|repo| {
+            repo.organization.eq_ignore_ascii_case(&self.organization)
+                && self
+                    .project
+                    .as_ref()
+                    .is_none_or(|project| repo.project.eq_ignore_ascii_case(project))
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct RepositoryList {
+    value: Vec<RepositoryListing>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RepositoryListing {
+    name: String,
+    remote_url: String,
+    project: ProjectRef,
+    default_branch: Option<String>,
+    #[serde(default)]
+    size: u64,
+    #[serde(default)]
+    is_disabled: bool,
+}
+
+#[derive(Deserialize)]
+struct ProjectRef {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct RepositoryResponse {
+    #[serde(rename = "defaultBranch")]
+    default_branch: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PullRequestList {
+    value: Vec<PullRequestResponse>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PullRequestResponse {
+    pull_request_id: u64,
+    source_ref_name: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CreatePullRequest<'a> {
+    source_ref_name: String,
+    target_ref_name: &'a str,
+    title: &'a str,
+    description: &'a str,
+    reviewers: Vec<IdRef<'a>>,
+    work_item_refs: Vec<IdRef<'a>>,
+}
+
+#[derive(Serialize)]
+struct IdRef<'a> {
+    id: &'a str,
+}
+
+pub struct AzureDevOpsClient {
+    http: reqwest::Client,
+    base_url: String,
+    username: String,
+    token: String,
+}
+
+impl AzureDevOpsClient {
+    /// A client authenticating with a personal access token, as stored by
+    /// `--set-git-credentials`.
+    pub fn new(base_url: &str, username: &str, token: &str) -> Self {
+        AzureDevOpsClient {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            username: username.to_string(),
+            token: token.to_string(),
+        }
+    }
+
+    fn repository_url(&self, repo: &AzureRepo) -> String {
+        format!(
+            "{}/{}/{}/_apis/git/repositories/{}",
+            self.base_url, repo.organization, repo.project, repo.repository
+        )
+    }
+
+    fn web_url(&self, repo: &AzureRepo, id: u64) -> String {
+        format!(
+            "{}/{}/{}/_git/{}/pullrequest/{}",
+            self.base_url, repo.organization, repo.project, repo.repository, id
+        )
+    }
+
+    async fn send<T: serde::de::DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<T> {
+        let response = request
+            .basic_auth(&self.username, Some(&self.token))
+            .query(&[("api-version", API_VERSION)])
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!(
+                "Azure DevOps returned {}: {}",
+                status,
+                body
+            ));
+        }
+        response
+            .json()
+            .await
+            .context("Unexpected response from Azure DevOps")
+    }
+
+    /// Every repository in the organization or project, named `project/repo`.
+    pub async fn list_repositories(&self, scope: &AzureScope) -> Result<Vec<DiscoveredRepo>> {
+        let url = match &scope.project {
+            Some(project) => format!(
+                "{}/{}/{}/_apis/git/repositories",
+                self.base_url, scope.organization, project
+            ),
+            None => format!(
+                "{}/{}/_apis/git/repositories",
+                self.base_url, scope.organization
+            ),
+        };
+        let response: RepositoryList = self.send(self.http.get(url)).await?;
+
+        Ok(response
+            .value
+            .into_iter()
+            .map(|repo| DiscoveredRepo {
+                name: format!("{}/{}", repo.project.name, repo.name),
+                unusable: if repo.is_disabled {
+                    Some("disabled".to_string())
+                } else if repo.default_branch.is_none() || repo.size == 0 {
+                    Some("empty".to_string())
+                } else {
+                    None
+                },
+                clone_url: repo.remote_url,
+                archived: false,
+                fork: false,
+                visibility: None,
+                topics: Vec::new(),
+            })
+            .collect())
+    }
+
+    /// The branch pull requests target, e.g. `refs/heads/main`.
+    pub async fn default_branch(&self, repo: &AzureRepo) -> Result<String> {
+        let response: RepositoryResponse =
+            self.send(self.http.get(self.repository_url(repo))).await?;
+        response
+            .default_branch
+            .ok_or_else(|| anyhow::anyhow!("{} has no default branch", repo.repository))
+    }
+
+    /// Every active pull request in the repository.
+    pub async fn active_pull_requests(&self, repo: &AzureRepo) -> Result<Vec<PullRequest>> {
+        let url = format!("{}/pullrequests", self.repository_url(repo));
+        let mut pulls = Vec::new();
+        for page in 0.. {
+            let batch: PullRequestList = self
+                .send(self.http.get(&url).query(&[
+                    ("searchCriteria.status", "active".to_string()),
+                    ("$top", PAGE_SIZE.to_string()),
+                    ("$skip", (page * PAGE_SIZE).to_string()),
+                ]))
+                .await?;
+            let last = batch.value.len() < PAGE_SIZE;
+            pulls.extend(batch.value);
+            if last {
+                break;
+            }
+        }
+        Ok(self.pull_requests(repo, pulls))
+    }
+
+    /// The active pull requests from `branch`.
+    pub async fn active_pull_requests_from(
+        &self,
+        repo: &AzureRepo,
+        branch: &str,
+    ) -> Result<Vec<PullRequest>> {
+        let url = format!("{}/pullrequests", self.repository_url(repo));
+        let source_ref = format!("refs/heads/{}", branch);
+        let response: PullRequestList = self
+            .send(self.http.get(url).query(&[
+                ("searchCriteria.status", "active"),
+                ("searchCriteria.sourceRefName", &source_ref),
+            ]))
+            .await?;
+        Ok(self.pull_requests(repo, response.value))
+    }
+
+    fn pull_requests(&self, repo: &AzureRepo, pulls: Vec<PullRequestResponse>) -> Vec<PullRequest> {
+        pulls
+            .into_iter()
+            .map(|pr| PullRequest {
+                id: pr.pull_request_id,
+                source_branch: pr
+                    .source_ref_name
+                    .trim_start_matches("refs/heads/")
+                    .to_string(),
+                web_url: self.web_url(repo, pr.pull_request_id),
+            })
+            .collect()
+    }
+
+    pub async fn create_pull_request(
+        &self,
+        repo: &AzureRepo,
+        target_ref: &str,
+        pr: &NewPullRequest,
+    ) -> Result<PullRequest> {
+        let body = CreatePullRequest {
+            source_ref_name: format!("refs/heads/{}", pr.source_branch),
+            target_ref_name: target_ref,
+            title: &pr.title,
+            description: &pr.description,
+            reviewers: pr.reviewers.iter().map(|id| IdRef { id }).collect(),
+            work_item_refs: pr.work_items.iter().map(|id| IdRef { id }).collect(),
+        };
+        let url = format!("{}/pullrequests", self.repository_url(repo));
+        let response: PullRequestResponse = self.send(self.http.post(url).json(&body)).await?;
+
+        Ok(PullRequest {
+            id: response.pull_request_id,
+            source_branch: pr.source_branch.clone(),
+            web_url: self.web_url(repo, response.pull_request_id),
+        })
+    }
+
+    /// Opens a pull request against the default branch, unless one from a
+    /// branch starting with `gavin_prefix` is already open.
+    pub async fn open_fix_pull_request(
+        &self,
+        repo: &AzureRepo,
+        pr: &NewPullRequest,
+        gavin_prefix: &str,
+    ) -> Result<PullRequestOutcome> {
+        let mut existing = self
+            .active_pull_requests_from(repo, &pr.source_branch)
+            .await?
+            .into_iter()
+            .next();
+        if existing.is_none() && !gavin_prefix.is_empty() {
+            existing = self
+                .active_pull_requests(repo)
+                .await?
+                .into_iter()
+                .find(|open| is_gavin_branch(&open.source_branch, &pr.source_branch, gavin_prefix));
+        }
+        if let Some(existing) = existing {
+            return Ok(PullRequestOutcome::Existing(existing));
+        }
+
+        let target = self.default_branch(repo).await?;
+        let created = self.create_pull_request(repo, &target, pr).await?;
+        Ok(PullRequestOutcome::Created(created))
+    }
+}
@@ -0,0 +1,124 @@
+//! Rollout campaigns: one target state pushed to many repositories in waves,
+//! with each repository's progress kept in the database between runs.
+
+use crate::{SupportedTask, TaskValidState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CampaignStatus {
+    Active,
+    Cancelled,
+    /// Every repository is merged or skipped.
+    Completed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RepoStatus {
+    Pending,
+    BranchPushed,
+    PrOpen,
+    Merged,
+    Failed,
+    Skipped,
+}
+
+impl RepoStatus {
+    pub const ALL: [RepoStatus; 6] = [
+        RepoStatus::Pending,
+        RepoStatus::BranchPushed,
+        RepoStatus::PrOpen,
+        RepoStatus::Merged,
+        RepoStatus::Failed,
+        RepoStatus::Skipped,
+    ];
+
+    /// Whether the repository still needs its fix pushed.
+    pub fn needs_push(self) -> bool {
+        matches!(self, RepoStatus::Pending | RepoStatus::Failed)
+    }
+
+    /// Whether a fix was pushed and is waiting to be merged.
+    pub fn awaiting_merge(self) -> bool {
+        matches!(self, RepoStatus::BranchPushed | RepoStatus::PrOpen)
+    }
+}
+
+impl CampaignStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CampaignStatus::Active => "active",
+            CampaignStatus::Cancelled => "cancelled",
+            CampaignStatus::Completed => "completed",
+        }
+    }
+}
+
+impl RepoStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RepoStatus::Pending => "pending",
+            RepoStatus::BranchPushed => "branch pushed",
+            RepoStatus::PrOpen => "pr open",
+            RepoStatus::Merged => "merged",
+            RepoStatus::Failed => "failed",
+            RepoStatus::Skipped => "skipped",
+        }
+    }
+}
+
+impl std::str::FromStr for CampaignStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            CampaignStatus::Active,
+            CampaignStatus::Cancelled,
+            CampaignStatus::Completed,
+        ]
+        .into_iter()
+        .find(|status| status.as_str() == s)
+        .ok_or_else(|| anyhow::anyhow!("Unknown campaign status: {}", s))
+    }
+}
+
+impl std::str::FromStr for RepoStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RepoStatus::ALL
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .ok_or_else(|| anyhow::anyhow!("Unknown repository status: {}", s))
+    }
+}
+
+impl std::fmt::Display for CampaignStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::fmt::Display for RepoStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Campaign {
+    pub id: i64,
+    pub name: String,
+    pub task: SupportedTask,
+    pub target: TaskValidState,
+    pub status: CampaignStatus,
+    pub created_at: String,
+}
+
+/// Where one repository of a campaign is at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CampaignRepo {
+    pub repo_url: String,
+    pub status: RepoStatus,
+    /// The branch, pull request or error behind the status.
+    pub detail: String,
+    pub updated_at: String,
+}
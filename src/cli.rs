@@ -1,8 +1,13 @@
+use crate::scan::{ScanRef, TrendFormat};
 use crate::SupportedTask;
-use clap::Parser;
+use clap::{ArgGroup, Parser};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
+#[command(group(
+    ArgGroup::new("discover").args(["discover_azure", "discover_github", "discover_gitlab"])
+))]
+#[command(group(ArgGroup::new("publish").args(["push", "resume_campaign"]).multiple(true)))]
 pub struct Cli {
     /// Search for a string in pipeline files
     #[arg(short = 's', long = "search")]
@@ -32,6 +37,71 @@ pub struct Cli {
     #[arg(long = "no-update")]
     pub no_update: bool,
 
+    /// Set the owner, tags or groups of a repository (with --owner, --tags and --groups)
+    #[arg(long = "set-repo-metadata")]
+    pub set_repo_metadata: Option<String>,
+
+    /// Team owning the repository; empty to clear it (requires --set-repo-metadata)
+    #[arg(long = "owner", requires = "set_repo_metadata")]
+    pub owner: Option<String>,
+
+    /// Tags of the repository, comma separated, replacing its current ones (requires --set-repo-metadata)
+    #[arg(long = "tags", value_delimiter = ',', requires = "set_repo_metadata")]
+    pub tags: Option<Vec<String>>,
+
+    /// Groups the repository belongs to, comma separated, replacing its current ones (requires --set-repo-metadata)
+    #[arg(long = "groups", value_delimiter = ',', requires = "set_repo_metadata")]
+    pub groups: Option<Vec<String>>,
+
+    /// Import owners, tags and groups from a CSV (url,owner,tags,groups) or YAML file
+    #[arg(long = "import-metadata")]
+    pub import_metadata: Option<String>,
+
+    /// Only work on repositories in one of these groups, comma separated
+    #[arg(long = "group", value_delimiter = ',')]
+    pub group: Vec<String>,
+
+    /// Only work on repositories with one of these tags, comma separated
+    #[arg(long = "tag", value_delimiter = ',')]
+    pub tag: Vec<String>,
+
+    /// Add every repository in an Azure DevOps organization or project (org or org/project),
+    /// and remove stored ones from it that are gone
+    #[arg(long = "discover-azure")]
+    pub discover_azure: Option<String>,
+
+    /// Add every repository in a GitHub organization, and remove stored ones from it that are gone
+    #[arg(long = "discover-github")]
+    pub discover_github: Option<String>,
+
+    /// Add every project in a GitLab group and its subgroups, and remove stored ones from it that are gone
+    #[arg(long = "discover-gitlab")]
+    pub discover_gitlab: Option<String>,
+
+    /// Only discover repositories whose name matches this regex, e.g. "^Platform/" (requires a --discover-* option)
+    #[arg(long = "include-repos", requires = "discover")]
+    pub include_repos: Option<String>,
+
+    /// Leave out discovered repositories whose name matches this regex (requires a --discover-* option)
+    #[arg(long = "exclude-repos", requires = "discover")]
+    pub exclude_repos: Option<String>,
+
+    /// Also discover archived repositories (requires a --discover-* option)
+    #[arg(long = "include-archived", requires = "discover")]
+    pub include_archived: bool,
+
+    /// Also discover forks (requires a --discover-* option)
+    #[arg(long = "include-forks", requires = "discover")]
+    pub include_forks: bool,
+
+    /// Only discover repositories with one of these visibilities, comma separated: public, private, internal (requires a --discover-* option)
+    #[arg(long = "visibility", value_delimiter = ',', requires = "discover")]
+    pub visibility: Vec<String>,
+
+    /// Only discover repositories with at least one of these topics, comma separated (requires a --discover-* option)
+    #[arg(long = "topics", value_delimiter = ',', requires = "discover")]
+    pub topics: Vec<String>,
+
     /// Delete a repository from the database
     #[arg(long = "delete-repo")]
     pub delete_repo: Option<String>,
@@ -48,7 +118,8 @@ pub struct Cli {
     #[arg(long = "delete-task-state")]
     pub delete_task_state: Option<SupportedTask>,
 
-    /// State value for --add-task-state or --delete-task-state (e.g., "setup:3,execute:3,spec:6.0.3")
+    /// State value for --add-task-state, --delete-task-state or --start-campaign (e.g., "setup:3,execute:3,spec:6.0.3",
+    /// or "v4", "branch:main" or a commit SHA for github: actions)
     #[arg(long = "state-value")]
     pub state_value: Option<String>,
 
@@ -64,10 +135,120 @@ pub struct Cli {
     #[arg(long = "analyze-tasks")]
     pub analyze_tasks: bool,
 
-    /// Check all task implementations across repositories
+    /// Check all task implementations across repositories, recording the results as a scan
     #[arg(long = "check-tasks")]
     pub check_tasks: bool,
 
+    /// List the recorded scans with their compliance
+    #[arg(long = "list-scans")]
+    pub list_scans: bool,
+
+    /// Show the task occurrences a recorded scan found
+    #[arg(long = "show-scan")]
+    pub show_scan: Option<i64>,
+
+    /// Compare a recorded scan, given by id or as a YYYY-MM-DD date for the last scan on or
+    /// before it, with a later one: newly invalid and fixed occurrences, added repositories and
+    /// changed task versions
+    #[arg(long = "compare-scans")]
+    pub compare_scans: Option<ScanRef>,
+
+    /// Scan to compare against, by id or date; defaults to the latest (requires --compare-scans)
+    #[arg(long = "to-scan", requires = "compare_scans")]
+    pub to_scan: Option<ScanRef>,
+
+    /// Print the compliance of every recorded scan as a table
+    #[arg(long = "compliance-trend")]
+    pub compliance_trend: bool,
+
+    /// Format of --compliance-trend: markdown or csv
+    #[arg(
+        long = "trend-format",
+        default_value = "markdown",
+        requires = "compliance_trend"
+    )]
+    pub trend_format: TrendFormat,
+
+    /// Rewrite out-of-standard task versions in the local checkouts to a valid state
+    #[arg(long = "fix")]
+    pub fix: bool,
+
+    /// Commit the fixes on a new branch in each repository and push it to origin (requires --fix)
+    #[arg(long = "push", requires = "fix")]
+    pub push: bool,
+
+    /// Name of the branch fixes are pushed to; {repo} and {date} are filled in (requires --push or --resume-campaign)
+    #[arg(long = "branch-template", requires = "publish")]
+    pub branch_template: Option<String>,
+
+    /// Open a pull request (merge request on GitLab) for each pushed branch (requires --push or --resume-campaign)
+    #[arg(long = "create-pr", requires = "publish")]
+    pub create_pr: bool,
+
+    /// Reviewers to add to pull requests, comma separated: identity IDs on Azure DevOps, logins on GitHub, user IDs on GitLab (requires --create-pr)
+    #[arg(long = "reviewers", value_delimiter = ',', requires = "create_pr")]
+    pub reviewers: Vec<String>,
+
+    /// Work item IDs to link to pull requests, comma separated (requires --create-pr)
+    #[arg(long = "work-items", value_delimiter = ',', requires = "create_pr")]
+    pub work_items: Vec<String>,
+
+    /// Base URL of the Azure DevOps API
+    #[arg(long = "azure-api-url", default_value = crate::azure_devops::DEFAULT_API_URL)]
+    pub azure_api_url: String,
+
+    /// Base URL of the GitHub API, e.g. https://github.example.com/api/v3 for GitHub Enterprise
+    #[arg(long = "github-api-url", default_value = crate::github_api::DEFAULT_API_URL)]
+    pub github_api_url: String,
+
+    /// Base URL of the GitLab API, e.g. https://gitlab.example.com/api/v4 for self-hosted GitLab
+    #[arg(long = "gitlab-api-url", default_value = crate::gitlab_api::DEFAULT_API_URL)]
+    pub gitlab_api_url: String,
+
+    /// Start a campaign rolling one valid state out to many repositories (requires --campaign-task and --state-value)
+    #[arg(long = "start-campaign", requires_all = ["campaign_task", "state_value"])]
+    pub start_campaign: Option<String>,
+
+    /// Task the campaign updates (requires --start-campaign)
+    #[arg(long = "campaign-task", requires = "start_campaign")]
+    pub campaign_task: Option<SupportedTask>,
+
+    /// Repositories in the campaign, comma separated; defaults to every repository (requires --start-campaign)
+    #[arg(
+        long = "campaign-repos",
+        value_delimiter = ',',
+        requires = "start_campaign"
+    )]
+    pub campaign_repos: Vec<String>,
+
+    /// Push fixes for the next wave of a campaign's pending and failed repositories, and record merged ones
+    #[arg(long = "resume-campaign")]
+    pub resume_campaign: Option<String>,
+
+    /// Number of repositories to push fixes to in this wave (requires --resume-campaign)
+    #[arg(long = "wave-size", requires = "resume_campaign")]
+    pub wave_size: Option<usize>,
+
+    /// Show a campaign's progress per repository
+    #[arg(long = "campaign-status")]
+    pub campaign_status: Option<String>,
+
+    /// List all campaigns
+    #[arg(long = "list-campaigns")]
+    pub list_campaigns: bool,
+
+    /// Cancel a campaign; branches and pull requests already opened are left alone
+    #[arg(long = "cancel-campaign")]
+    pub cancel_campaign: Option<String>,
+
+    /// Show what would change without writing files, running git or saving to the database
+    #[arg(long = "dry-run")]
+    pub dry_run: bool,
+
+    /// Write the proposed fixes as one .patch file per repository to this directory (requires --dry-run)
+    #[arg(long = "patch-dir", requires = "dry_run")]
+    pub patch_dir: Option<String>,
+
     /// Output results as a markdown file (requires --check-tasks)
     #[arg(long = "output-markdown", requires = "check_tasks")]
     pub output_markdown: bool,
@@ -84,7 +265,38 @@ pub struct Cli {
     #[arg(long = "config")]
     pub config_path: Option<String>,
 
+    /// Directory holding the database, encryption key and checkouts (defaults to
+    /// $GAVIN_HOME, then data_dir in the config file, then the current directory)
+    #[arg(long = "data-dir")]
+    pub data_dir: Option<String>,
+
     /// Set git credentials (username:token format)
     #[arg(long = "set-git-credentials")]
     pub set_git_credentials: Option<String>,
+
+    /// Host, optionally with an organization or group path, that --set-git-credentials,
+    /// --set-ssh-key and --delete-credentials apply to (e.g. dev.azure.com/contoso).
+    /// Without it they apply to the default credentials
+    #[arg(long = "credential-scope")]
+    pub credential_scope: Option<String>,
+
+    /// Set the SSH private key used for git@ and ssh:// repository URLs
+    #[arg(long = "set-ssh-key")]
+    pub set_ssh_key: Option<String>,
+
+    /// List the stored git credentials and their scopes
+    #[arg(long = "list-credentials")]
+    pub list_credentials: bool,
+
+    /// Encrypt the git tokens stored by older versions, which are not read until then
+    #[arg(long = "encrypt-credentials")]
+    pub encrypt_credentials: bool,
+
+    /// Delete the stored git credentials of --credential-scope
+    #[arg(long = "delete-credentials")]
+    pub delete_credentials: bool,
+
+    /// Show where the git credentials in use come from, for a repository URL if given
+    #[arg(long = "credential-source", num_args = 0..=1, default_missing_value = "")]
+    pub credential_source: Option<String>,
 }
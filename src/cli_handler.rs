@@ -1,23 +1,46 @@
+use crate::azure_devops::{AzureDevOpsClient, AzureScope};
+use crate::campaign::{Campaign, CampaignRepo, CampaignStatus, RepoStatus};
+use crate::discovery::{self, DiscoverySync, RepoFilter};
+use crate::github_api::{GithubClient, GithubRepo};
+use crate::gitlab_api::{GitlabClient, GitlabProject};
+use crate::host::HostType;
+use crate::metadata::{self, RepoMetadata, RepoSelector};
+use crate::pull_request::{NewPullRequest, PullRequestClients, PullRequestOutcome};
+use crate::scan::{self, ScanRef, TaskOccurrence};
+use crate::secrets::{self, CredentialScope, Credentials};
 use crate::{
     check_all_task_implementations, cli::Cli, collect_task_usage, ensure_all_repos_exist,
-    find_pipeline_files, git_manager::GitManager, report::generate_markdown_report,
-    search_default_task, search_gitversion_tasks, search_in_pipelines_concurrent,
-    utils::sanitize_file_path, Config, Database, GitVersionState, SupportedTask, TaskValidState,
+    find_pipeline_files, fixer, git_manager::GitManager, platform, repo_id,
+    report::generate_markdown_report, search_default_task, search_gitversion_tasks,
+    search_in_pipelines_concurrent, utils::sanitize_file_path, Config, Database, RepoId,
+    SupportedTask, SyncMode,
 };
 use anyhow::Result;
 use clap::CommandFactory;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::fs;
 use tokio::sync::Semaphore;
 
 pub async fn handle_cli_args(cli: &Cli, db: &Database) -> Result<()> {
+    if cli.dry_run {
+        db.begin_dry_run()?;
+    }
+
     // Load config if needed
     if let Some(config) = load_config_if_needed(cli)? {
+        if cli.dry_run {
+            for change in db.config_state_changes(&config)? {
+                println!("{}", change);
+            }
+        }
         // Merge config states into database
         db.merge_config_states(&config)?;
     }
 
     // Check if any meaningful argument is provided
+    let sync_mode = SyncMode::from_flags(false, cli.dry_run);
     let has_args = cli.search_string.is_some()
         || cli.search_task.is_some()
         || cli.list_repos
@@ -25,13 +48,33 @@ pub async fn handle_cli_args(cli: &Cli, db: &Database) -> Result<()> {
         || cli.add_repo.is_some()
         || cli.add_multiple_repos.is_some()
         || cli.delete_repo.is_some()
+        || cli.set_repo_metadata.is_some()
+        || cli.import_metadata.is_some()
+        || cli.discover_azure.is_some()
+        || cli.discover_github.is_some()
+        || cli.discover_gitlab.is_some()
         || cli.add_task_state.is_some()
         || cli.delete_task_state.is_some()
         || cli.list_task_states.is_some()
         || cli.list_all_task_states
         || cli.analyze_tasks
         || cli.check_tasks
-        || cli.set_git_credentials.is_some();
+        || cli.list_scans
+        || cli.show_scan.is_some()
+        || cli.compare_scans.is_some()
+        || cli.compliance_trend
+        || cli.fix
+        || cli.start_campaign.is_some()
+        || cli.resume_campaign.is_some()
+        || cli.campaign_status.is_some()
+        || cli.list_campaigns
+        || cli.cancel_campaign.is_some()
+        || cli.set_git_credentials.is_some()
+        || cli.set_ssh_key.is_some()
+        || cli.list_credentials
+        || cli.encrypt_credentials
+        || cli.delete_credentials
+        || cli.credential_source.is_some();
 
     if !has_args {
         Cli::command().print_help()?;
@@ -39,9 +82,14 @@ pub async fn handle_cli_args(cli: &Cli, db: &Database) -> Result<()> {
     }
 
     // Validate state_value is only used with appropriate commands
-    if cli.state_value.is_some() && cli.add_task_state.is_none() && cli.delete_task_state.is_none()
+    if cli.state_value.is_some()
+        && cli.add_task_state.is_none()
+        && cli.delete_task_state.is_none()
+        && cli.start_campaign.is_none()
     {
-        println!("--state-value can only be used with --add-task-state or --delete-task-state");
+        println!(
+            "--state-value can only be used with --add-task-state, --delete-task-state or --start-campaign"
+        );
         std::process::exit(1);
     }
 
@@ -52,23 +100,25 @@ pub async fn handle_cli_args(cli: &Cli, db: &Database) -> Result<()> {
         cli.list_pipelines,
     ) {
         (Some(query), _, _, _) => {
-            let repos = db.list_repositories()?;
-            search_in_pipelines_concurrent(&repos, query).await?;
+            let repos = selected_repositories(cli, db)?;
+            search_in_pipelines_concurrent(db, &repos, query, sync_mode).await?;
         }
         (_, Some(task), _, _) => {
-            let repos = db.list_repositories()?;
+            let repos = selected_repositories(cli, db)?;
             match task.to_string().as_str() {
-                "gitversion" => search_gitversion_tasks(&repos, cli.verbose).await?,
-                task_name => search_default_task(&repos, task_name, cli.verbose).await?,
+                "gitversion" => search_gitversion_tasks(db, &repos, cli.verbose, sync_mode).await?,
+                task_name => {
+                    search_default_task(db, &repos, task_name, cli.verbose, sync_mode).await?
+                }
             }
         }
         (_, _, true, _) => {
-            for repo in db.list_repositories()? {
-                println!("{}", repo);
+            for repo in selected_repositories(cli, db)? {
+                println!("{}", describe_repository(&db.repository_metadata(&repo)?));
             }
         }
         (_, _, _, true) => {
-            for repo in db.list_repositories()? {
+            for repo in selected_repositories(cli, db)? {
                 println!("{}", repo);
                 let repo_path = db.get_local_path(&repo);
                 let pipeline_files = find_pipeline_files(&repo_path).await?;
@@ -82,12 +132,16 @@ pub async fn handle_cli_args(cli: &Cli, db: &Database) -> Result<()> {
         _ => handle_other_cli_args(cli, db).await?,
     }
 
+    if cli.dry_run {
+        println!("\nDry run: no changes were made");
+    }
     Ok(())
 }
 
 async fn handle_other_cli_args(cli: &Cli, db: &Database) -> Result<()> {
+    let sync_mode = SyncMode::from_flags(cli.no_update, cli.dry_run);
     if cli.list_repos {
-        let repos = db.list_repositories()?;
+        let repos = selected_repositories(cli, db)?;
         if repos.is_empty() {
             println!("No repositories found.");
         } else {
@@ -96,8 +150,9 @@ async fn handle_other_cli_args(cli: &Cli, db: &Database) -> Result<()> {
             }
         }
     } else if cli.list_pipelines {
-        ensure_all_repos_exist(db, cli.no_update).await?;
-        for repo_url in db.list_repositories()? {
+        let repos = selected_repositories(cli, db)?;
+        ensure_all_repos_exist(db, &repos, sync_mode).await?;
+        for repo_url in repos {
             println!("\n{}", repo_url);
             let repo_path = db.get_local_path(&repo_url);
             let pipeline_files = find_pipeline_files(&repo_path).await?;
@@ -108,18 +163,86 @@ async fn handle_other_cli_args(cli: &Cli, db: &Database) -> Result<()> {
             }
         }
     } else if let Some(credentials) = &cli.set_git_credentials {
-        db.set_git_credentials(credentials)?;
-        println!("Git credentials updated successfully");
+        let scope = credential_scope(cli);
+        let key_source = db.set_git_credentials(credentials, &scope)?;
+        println!(
+            "Git credentials for {} updated successfully, token encrypted with the key from {}",
+            scope, key_source
+        );
+        if let (None, Some(active)) = (
+            &scope.host,
+            secrets::from_environment(db.workspace().environment()),
+        ) {
+            println!(
+                "Note: {} is set and takes precedence over the stored default credentials",
+                active.source
+            );
+        }
+    } else if let Some(key_path) = &cli.set_ssh_key {
+        let scope = credential_scope(cli);
+        let key_path = db.set_ssh_key(key_path, &scope)?;
+        println!("SSH key for {} set to {}", scope, key_path.display());
+    } else if cli.list_credentials {
+        let credentials = db.list_git_credentials()?;
+        if credentials.is_empty() {
+            println!("No stored git credentials");
+        }
+        for credentials in credentials {
+            let mut details = Vec::new();
+            if !credentials.token.is_empty() {
+                details.push(format!("token for {}", credentials.username));
+            }
+            if let Some(key) = &credentials.ssh_key {
+                details.push(format!("SSH key {}", key.display()));
+            }
+            println!("{}: {}", credentials.scope, details.join(", "));
+        }
+        if let Some(active) = secrets::from_environment(db.workspace().environment()) {
+            println!(
+                "{} is set and is used where no host credentials apply",
+                active.source
+            );
+        }
+    } else if cli.encrypt_credentials {
+        match db.encrypt_legacy_tokens()? {
+            Some((count, key_source)) => println!(
+                "Encrypted {} stored git token(s) with the key from {}",
+                count, key_source
+            ),
+            None => println!("All stored git tokens are already encrypted"),
+        }
+    } else if cli.delete_credentials {
+        let scope = credential_scope(cli);
+        if db.delete_git_credentials(&scope)? {
+            println!("Deleted git credentials for {}", scope);
+        } else {
+            println!("No git credentials stored for {}", scope);
+        }
+    } else if let Some(url) = &cli.credential_source {
+        match db.credentials_for(url)? {
+            Some(credentials) => println!(
+                "Using the {} git credentials from the {}",
+                credentials.scope, credentials.source
+            ),
+            None if repo_id::is_ssh_url(url) => {
+                println!("No git credentials apply, using the SSH agent and config")
+            }
+            None => {
+                println!("No git credentials found. Set GAVIN_TOKEN or use --set-git-credentials")
+            }
+        }
     } else if let Some(repo_url) = &cli.add_repo {
-        db.add_repository(repo_url, cli.new).await?;
+        if cli.dry_run {
+            preview_add_repositories(db, &[repo_url.as_str()], cli.new).await?;
+        } else {
+            db.add_repository(repo_url, cli.new).await?;
+        }
         println!("Added repository: {}", repo_url);
+    } else if let (Some(repos), true) = (&cli.add_multiple_repos, cli.dry_run) {
+        let repo_urls: Vec<&str> = repos.split(',').map(str::trim).collect();
+        preview_add_repositories(db, &repo_urls, cli.new).await?;
     } else if let Some(repos) = &cli.add_multiple_repos {
         let repo_urls: Vec<&str> = repos.split(',').map(str::trim).collect();
-        let credentials = db.get_git_credentials()?.ok_or_else(|| {
-            anyhow::anyhow!(
-                "Git credentials not found. Please set them first with --set-git-credentials"
-            )
-        })?;
 
         // First, test all connections sequentially
         println!("Testing connections to all repositories...");
@@ -127,11 +250,15 @@ async fn handle_other_cli_args(cli: &Cli, db: &Database) -> Result<()> {
         let mut failed_repos = Vec::new();
 
         for repo_url in repo_urls {
-            let git_manager =
-                GitManager::new(credentials.0.clone(), credentials.1.clone(), repo_url);
+            let git_manager = GitManager::with_credentials(
+                db.workspace(),
+                &db.git_credentials(repo_url)?,
+                repo_url,
+                HostType::detect(repo_url),
+            );
             match git_manager.test_connection().await {
                 Ok(_) => {
-                    valid_repos.push(repo_url.to_string());
+                    valid_repos.push((repo_url.to_string(), git_manager));
                 }
                 Err(e) => {
                     println!("✗ Failed to connect to repository {}: {}", repo_url, e);
@@ -155,13 +282,11 @@ async fn handle_other_cli_args(cli: &Cli, db: &Database) -> Result<()> {
 
         let mut handles = Vec::new();
 
-        for repo_url in valid_repos {
+        for (repo_url, git_manager) in valid_repos {
             let permit = semaphore.clone().acquire_owned().await?;
-            let creds = (credentials.0.clone(), credentials.1.clone());
 
             let handle = tokio::spawn(async move {
                 let _permit = permit;
-                let git_manager = GitManager::new(creds.0, creds.1, &repo_url);
 
                 match if is_new {
                     git_manager.ensure_repo_exists_new().await
@@ -211,40 +336,87 @@ async fn handle_other_cli_args(cli: &Cli, db: &Database) -> Result<()> {
                 println!("✗ {}: {}", url, error);
             }
         }
+    } else if let Some(repo_url) = &cli.set_repo_metadata {
+        let mut metadata = db.repository_metadata(repo_url)?;
+        if let Some(owner) = &cli.owner {
+            metadata.owner = Some(owner.clone()).filter(|owner| !owner.is_empty());
+        }
+        if let Some(tags) = &cli.tags {
+            metadata.tags = tags
+                .iter()
+                .flat_map(|tag| metadata::split_list(tag))
+                .collect();
+        }
+        if let Some(groups) = &cli.groups {
+            metadata.groups = groups
+                .iter()
+                .flat_map(|group| metadata::split_list(group))
+                .collect();
+        }
+        db.set_repository_metadata(&metadata)?;
+        println!("{}", describe_repository(&metadata));
+    } else if let Some(path) = &cli.import_metadata {
+        let entries = metadata::load(&sanitize_file_path(path))?;
+        let mut imported = 0;
+        for entry in &entries {
+            match db.set_repository_metadata(entry) {
+                Ok(()) => {
+                    println!("✓ {}", describe_repository(entry));
+                    imported += 1;
+                }
+                Err(e) => println!("✗ {}", e),
+            }
+        }
+        println!(
+            "\nImported metadata for {} of {} repositories",
+            imported,
+            entries.len()
+        );
+    } else if let Some(scope) = &cli.discover_azure {
+        discover_azure_repositories(cli, db, scope).await?;
+    } else if let Some(org) = &cli.discover_github {
+        discover_github_repositories(cli, db, org).await?;
+    } else if let Some(group) = &cli.discover_gitlab {
+        discover_gitlab_repositories(cli, db, group).await?;
     } else if let Some(path) = &cli.delete_repo {
         db.delete_repository(path)?;
         println!("Deleted repository: {}", path);
     } else if let (Some(task_name), Some(state_str)) =
         (cli.add_task_state.clone(), &cli.state_value)
     {
+        let state =
+            platform::for_task(&task_name.to_string()).parse_state(&task_name, state_str)?;
+        db.add_valid_state(&task_name, &state)?;
         match &task_name {
-            SupportedTask::Gitversion => {
-                let state = GitVersionState::from_string(state_str)
-                    .map_err(|e| anyhow::anyhow!("Invalid state format: {}", e))?;
-                db.add_valid_state(&task_name, &TaskValidState::Gitversion(state))?;
-                println!("Added valid state for GitVersion");
-            }
-            SupportedTask::Default(name) => {
-                db.add_valid_state(&task_name, &TaskValidState::Default(state_str.to_string()))?;
-                println!("Added valid state for {}", name);
-            }
+            SupportedTask::Gitversion => println!("Added valid state for GitVersion"),
+            other => println!("Added valid state for {}", other),
         }
     } else if let Some(task) = &cli.list_task_states {
         list_task_states(db, task)?;
     } else if cli.list_all_task_states {
         handle_list_all_task_states(db).await?;
     } else if cli.analyze_tasks {
-        let repos = db.list_repositories()?;
+        let repos = selected_repositories(cli, db)?;
         // Ensure repos exist before analyzing
-        ensure_all_repos_exist(db, cli.no_update).await?;
-        collect_task_usage(&repos).await?;
+        ensure_all_repos_exist(db, &repos, sync_mode).await?;
+        collect_task_usage(db, &repos).await?;
     } else if cli.check_tasks {
-        let repos = db.list_repositories()?;
+        let repos = selected_repositories(cli, db)?;
         // Ensure repos exist before checking tasks
-        ensure_all_repos_exist(db, cli.no_update).await?;
+        ensure_all_repos_exist(db, &repos, sync_mode).await?;
+
+        let issues = check_all_task_implementations(db, &repos, None, sync_mode).await?;
+        if !cli.dry_run {
+            let scan = scan::record(db, &repos, &issues).await?;
+            println!(
+                "\nRecorded scan {}: {} of {} task occurrences valid",
+                scan.id,
+                scan.occurrences - scan.invalid,
+                scan.occurrences
+            );
+        }
 
         if cli.output_markdown {
-            let issues = check_all_task_implementations(&repos, None, cli.no_update).await?;
             let report = generate_markdown_report(&repos, db, &issues).await?;
             let report_path = cli.report_path.as_deref().unwrap_or("report.md");
 
@@ -252,18 +424,54 @@ async fn handle_other_cli_args(cli: &Cli, db: &Database) -> Result<()> {
             let safe_path = sanitize_file_path(report_path);
             fs::write(&safe_path, report).await?;
             println!("Generated markdown report: {}", safe_path.display());
-        } else {
-            let _issues = check_all_task_implementations(&repos, None, cli.no_update).await?;
         }
+    } else if cli.list_scans {
+        let scans = db.list_scans()?;
+        if scans.is_empty() {
+            println!("No scans recorded yet. Run --check-tasks to record one");
+        }
+        for scan in scans {
+            println!(
+                "{} at {}: {} repositories, {} of {} task occurrences valid",
+                scan.id,
+                scan.created_at,
+                scan.repositories,
+                scan.occurrences - scan.invalid,
+                scan.occurrences
+            );
+        }
+    } else if let Some(id) = cli.show_scan {
+        print_scan(db, id)?;
+    } else if let Some(from) = cli.compare_scans {
+        compare_scans(db, from, cli.to_scan)?;
+    } else if cli.compliance_trend {
+        print!("{}", scan::trend_table(&db.list_scans()?, cli.trend_format));
+    } else if let (Some(name), Some(task), Some(state_value)) =
+        (&cli.start_campaign, &cli.campaign_task, &cli.state_value)
+    {
+        start_campaign(cli, db, name, task, state_value)?;
+    } else if let Some(name) = &cli.resume_campaign {
+        resume_campaign(cli, db, name).await?;
+    } else if let Some(name) = &cli.campaign_status {
+        print_campaign_status(db, &find_campaign(db, name)?)?;
+    } else if cli.list_campaigns {
+        for campaign in db.list_campaigns()? {
+            println!(
+                "{} ({}): {} {} since {}",
+                campaign.name, campaign.status, campaign.task, campaign.target, campaign.created_at
+            );
+        }
+    } else if let Some(name) = &cli.cancel_campaign {
+        let campaign = find_campaign(db, name)?;
+        db.set_campaign_status(&campaign, CampaignStatus::Cancelled)?;
+        println!("Cancelled campaign {}", campaign.name);
+    } else if cli.fix {
+        let repos = selected_repositories(cli, db)?;
+        let issues = check_all_task_implementations(db, &repos, None, sync_mode).await?;
+        handle_fix(cli, db, &issues).await?;
     } else if let Some(task) = &cli.delete_task_state {
         if let Some(state_value) = &cli.state_value {
-            let state = match task {
-                SupportedTask::Gitversion => TaskValidState::Gitversion(
-                    GitVersionState::from_string(state_value)
-                        .map_err(|e| anyhow::anyhow!("Invalid GitVersion state format: {}", e))?,
-                ),
-                SupportedTask::Default(_) => TaskValidState::Default(state_value.to_string()),
-            };
+            let state = platform::for_task(&task.to_string()).parse_state(task, state_value)?;
             db.delete_valid_state(task, &state)?;
             println!("Deleted task state for {}: {}", task, state_value);
         } else {
@@ -278,6 +486,638 @@ async fn handle_other_cli_args(cli: &Cli, db: &Database) -> Result<()> {
     Ok(())
 }
 
+async fn handle_fix(cli: &Cli, db: &Database, issues: &crate::TaskIssues) -> Result<()> {
+    let plan = fixer::plan(issues, db)?;
+
+    println!("\nFixes:");
+    for fix in &plan.fixes {
+        println!(
+            "✓ {} {} ({}:{})",
+            fix.repo_name,
+            fix,
+            fix.path.display(),
+            fix.location.line
+        );
+    }
+    for (fix, reason) in &plan.skipped {
+        println!(
+            "✗ {} {} ({}:{}) skipped: {}",
+            fix.repo_name,
+            fix,
+            fix.path.display(),
+            fix.location.line,
+            reason
+        );
+    }
+
+    let (repo_urls, repo_paths) = repo_locations(db, selected_repositories(cli, db)?);
+    if !cli.dry_run {
+        let written = fixer::apply(&plan)?;
+        println!("\nUpdated {} file(s)", written.len());
+        if cli.push {
+            push_fixes(cli, db, &plan, &written, &repo_urls, &repo_paths).await?;
+        }
+        return Ok(());
+    }
+
+    let patches = fixer::patches(&plan, &repo_paths)?;
+    print_patches(cli, &patches, cli.push);
+
+    if let Some(patch_dir) = &cli.patch_dir {
+        let patch_dir = sanitize_file_path(patch_dir);
+        fs::create_dir_all(&patch_dir).await?;
+        for (repo, patch) in &patches {
+            let patch_path = patch_dir.join(sanitize_file_path(&format!("{}.patch", repo)));
+            fs::write(&patch_path, patch).await?;
+            println!("Wrote patch for {}: {}", repo, patch_path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Repository names mapped to their URLs and to their local checkouts.
+fn repo_locations(
+    db: &Database,
+    urls: Vec<String>,
+) -> (BTreeMap<String, String>, BTreeMap<String, PathBuf>) {
+    let repo_urls: BTreeMap<String, String> = urls
+        .into_iter()
+        .map(|url| (RepoId::parse(&url).full_name(), url))
+        .collect();
+    let repo_paths = repo_urls
+        .iter()
+        .map(|(name, url)| (name.clone(), db.get_local_path(url)))
+        .collect();
+    (repo_urls, repo_paths)
+}
+
+/// Prints the dry run's patches and what would be pushed for them.
+fn print_patches(cli: &Cli, patches: &BTreeMap<String, String>, push: bool) {
+    let branch_template = cli
+        .branch_template
+        .as_deref()
+        .unwrap_or(fixer::DEFAULT_BRANCH_TEMPLATE);
+
+    for (repo, patch) in patches {
+        println!("\nRepository {}:", repo);
+        print!("{}", patch);
+        if push {
+            println!(
+                "Would push branch {} to origin",
+                fixer::branch_name(branch_template, repo)
+            );
+        }
+        if cli.create_pr {
+            println!("Would open a pull request unless an open gavin one exists");
+        }
+    }
+}
+
+/// Commits each repository's rewritten files on a fix branch and pushes it,
+/// opening a pull request when asked to. Returns where each repository ended
+/// up, with the branch, pull request or error behind it.
+async fn push_fixes(
+    cli: &Cli,
+    db: &Database,
+    plan: &fixer::FixPlan,
+    written: &[PathBuf],
+    repo_urls: &BTreeMap<String, String>,
+    repo_paths: &BTreeMap<String, PathBuf>,
+) -> Result<BTreeMap<String, (RepoStatus, String)>> {
+    let branch_template = cli
+        .branch_template
+        .as_deref()
+        .unwrap_or(fixer::DEFAULT_BRANCH_TEMPLATE);
+
+    let mut outcomes = BTreeMap::new();
+    println!("\nPushing fixes:");
+    for (repo, files) in fixer::files_by_repo(written, repo_paths) {
+        let fixes: Vec<&fixer::Fix> = plan
+            .fixes
+            .iter()
+            .filter(|fix| files.contains(&fix.path))
+            .collect();
+        let branch = fixer::branch_name(branch_template, &repo);
+        let repo_url = &repo_urls[&repo];
+        let credentials = db.git_credentials(repo_url)?;
+        let git_manager = GitManager::with_credentials(
+            db.workspace(),
+            &credentials,
+            repo_url,
+            db.repository_host(repo_url)?,
+        );
+
+        if let Err(e) = git_manager
+            .commit_and_push(&branch, &files, &fixer::commit_message(&fixes))
+            .await
+        {
+            println!("✗ {} failed to push {}: {}", repo, branch, e);
+            outcomes.insert(repo, (RepoStatus::Failed, e.to_string()));
+            continue;
+        }
+        println!("✓ {} pushed {}", repo, branch);
+
+        if !cli.create_pr {
+            outcomes.insert(repo, (RepoStatus::BranchPushed, branch));
+            continue;
+        }
+        let pr = NewPullRequest {
+            source_branch: branch.clone(),
+            title: fixer::pull_request_title(&fixes),
+            description: fixer::pull_request_description(&fixes, &repo_paths[&repo]),
+            reviewers: cli.reviewers.clone(),
+            work_items: cli.work_items.clone(),
+        };
+        let clients = PullRequestClients {
+            azure: AzureDevOpsClient::new(
+                &cli.azure_api_url,
+                &credentials.username,
+                &credentials.token,
+            ),
+            github: GithubClient::new(&cli.github_api_url, &credentials.token),
+            gitlab: GitlabClient::new(&cli.gitlab_api_url, &credentials.token),
+        };
+        match clients
+            .open_fix_pull_request(repo_url, &pr, fixer::branch_prefix(branch_template))
+            .await
+        {
+            Ok(PullRequestOutcome::Created(pr)) => {
+                println!("✓ {} opened pull request {}", repo, pr.web_url);
+                outcomes.insert(repo, (RepoStatus::PrOpen, pr.web_url));
+            }
+            Ok(PullRequestOutcome::Existing(pr)) => {
+                println!(
+                    "✓ {} already has an open pull request {} from {}",
+                    repo, pr.web_url, pr.source_branch
+                );
+                outcomes.insert(repo, (RepoStatus::PrOpen, pr.web_url));
+            }
+            Err(e) => {
+                println!("✗ {} failed to open a pull request: {}", repo, e);
+                outcomes.insert(repo, (RepoStatus::Failed, e.to_string()));
+            }
+        }
+    }
+    Ok(outcomes)
+}
+
+/// A recorded scan's repositories and the task occurrences found in each.
+fn print_scan(db: &Database, id: i64) -> Result<()> {
+    let scan = db
+        .get_scan(id)?
+        .ok_or_else(|| anyhow::anyhow!("Scan {} not found", id))?;
+    println!("Scan {} at {}", scan.id, scan.created_at);
+
+    let occurrences = db.scan_occurrences(id)?;
+    let policy_findings = db.scan_policy_findings(id)?;
+    for repo in db.scan_repositories(id)? {
+        println!(
+            "\n{} @ {}",
+            RepoId::parse(&repo.url).full_name(),
+            repo.commit_sha.as_deref().unwrap_or("unknown commit")
+        );
+        for occurrence in occurrences.iter().filter(|o| o.repo_url == repo.url) {
+            let status = if occurrence.is_valid() { "✓" } else { "✗" };
+            println!(
+                "  {} {}@{} ({}:{})",
+                status, occurrence.task, occurrence.version, occurrence.file, occurrence.line
+            );
+            if let Some(issue) = &occurrence.issue {
+                println!("      {}", issue);
+            }
+        }
+        for finding in policy_findings.iter().filter(|f| f.repo_url == repo.url) {
+            println!("  ✗ {}: {}", finding.severity, finding.message);
+        }
+    }
+    Ok(())
+}
+
+/// What changed between two recorded scans, the later one defaulting to the latest.
+fn compare_scans(db: &Database, from: ScanRef, to: Option<ScanRef>) -> Result<()> {
+    let find = |scan: ScanRef| {
+        db.find_scan(scan)?.ok_or_else(|| match scan {
+            ScanRef::Id(id) => anyhow::anyhow!("Scan {} not found", id),
+            ScanRef::Date(date) => anyhow::anyhow!("No scan was recorded on or before {}", date),
+        })
+    };
+    let old = find(from)?;
+    let new = match to {
+        Some(to) => find(to)?,
+        None => db
+            .latest_scan()?
+            .ok_or_else(|| anyhow::anyhow!("No scans recorded yet"))?,
+    };
+
+    println!(
+        "Comparing scan {} at {} with scan {} at {}",
+        old.id, old.created_at, new.id, new.created_at
+    );
+    for scan in [&old, &new] {
+        println!(
+            "  Scan {}: {} of {} task occurrences valid",
+            scan.id,
+            scan.occurrences - scan.invalid,
+            scan.occurrences
+        );
+    }
+
+    let diff = scan::diff(&db.scan_results(old.id)?, &db.scan_results(new.id)?);
+    let describe = |o: &TaskOccurrence| {
+        format!(
+            "{}: {}@{} ({}:{})",
+            RepoId::parse(&o.repo_url).full_name(),
+            o.task,
+            o.version,
+            o.file,
+            o.line
+        )
+    };
+
+    println!("\nNewly invalid ({}):", diff.newly_invalid.len());
+    for occurrence in &diff.newly_invalid {
+        println!("  ✗ {}", describe(occurrence));
+        if let Some(issue) = &occurrence.issue {
+            println!("      {}", issue);
+        }
+    }
+    println!("\nFixed ({}):", diff.fixed.len());
+    for occurrence in &diff.fixed {
+        println!("  ✓ {}", describe(occurrence));
+    }
+    println!("\nChanged task versions ({}):", diff.version_changes.len());
+    for change in &diff.version_changes {
+        println!(
+            "  {}: {} in {} {} -> {}",
+            RepoId::parse(&change.repo_url).full_name(),
+            change.task,
+            change.file,
+            change.from.join(", "),
+            change.to.join(", ")
+        );
+    }
+    println!("\nNew repositories ({}):", diff.added_repositories.len());
+    for url in &diff.added_repositories {
+        println!("  + {}", url);
+    }
+    if !diff.removed_repositories.is_empty() {
+        println!(
+            "\nRepositories no longer scanned ({}):",
+            diff.removed_repositories.len()
+        );
+        for url in &diff.removed_repositories {
+            println!("  - {}", url);
+        }
+    }
+    Ok(())
+}
+
+/// The scope `--credential-scope` names, the default without it.
+fn credential_scope(cli: &Cli) -> CredentialScope {
+    cli.credential_scope
+        .as_deref()
+        .map(CredentialScope::parse)
+        .unwrap_or_default()
+}
+
+/// The repositories `--group` and `--tag` select, all of them without filters.
+fn selected_repositories(cli: &Cli, db: &Database) -> Result<Vec<String>> {
+    db.select_repositories(&RepoSelector {
+        groups: cli.group.clone(),
+        tags: cli.tag.clone(),
+    })
+}
+
+/// A repository with its owner, tags and groups, e.g. for `--list-repos`.
+fn describe_repository(metadata: &RepoMetadata) -> String {
+    let mut details = Vec::new();
+    if let Some(owner) = &metadata.owner {
+        details.push(format!("owner: {}", owner));
+    }
+    if !metadata.tags.is_empty() {
+        details.push(format!("tags: {}", metadata.tags.join(", ")));
+    }
+    if !metadata.groups.is_empty() {
+        details.push(format!("groups: {}", metadata.groups.join(", ")));
+    }
+
+    if details.is_empty() {
+        metadata.url.clone()
+    } else {
+        format!("{} ({})", metadata.url, details.join("; "))
+    }
+}
+
+fn find_campaign(db: &Database, name: &str) -> Result<Campaign> {
+    db.get_campaign(name)?
+        .ok_or_else(|| anyhow::anyhow!("Campaign '{}' not found", name))
+}
+
+fn start_campaign(
+    cli: &Cli,
+    db: &Database,
+    name: &str,
+    task: &SupportedTask,
+    state_value: &str,
+) -> Result<()> {
+    let target = platform::for_task(&task.to_string()).parse_state(task, state_value)?;
+    if target.has_range() {
+        return Err(anyhow::anyhow!(
+            "A campaign moves {} to one version, not a range like {}",
+            task,
+            state_value
+        ));
+    }
+    let repos = if cli.campaign_repos.is_empty() {
+        selected_repositories(cli, db)?
+    } else {
+        cli.campaign_repos
+            .iter()
+            .map(|url| url.trim().to_string())
+            .collect()
+    };
+
+    let campaign = db.create_campaign(name, task, &target, &repos)?;
+    println!(
+        "Started campaign {} moving {} to {} in {} repositories",
+        campaign.name,
+        campaign.task,
+        campaign.target,
+        repos.len()
+    );
+    Ok(())
+}
+
+/// Runs the next wave of a campaign: repositories whose fix was merged are
+/// recorded as such, and fixes are pushed to the next pending or failed ones.
+async fn resume_campaign(cli: &Cli, db: &Database, name: &str) -> Result<()> {
+    let sync_mode = SyncMode::from_flags(cli.no_update, cli.dry_run);
+    let campaign = find_campaign(db, name)?;
+    match campaign.status {
+        CampaignStatus::Cancelled => {
+            return Err(anyhow::anyhow!("Campaign '{}' was cancelled", name));
+        }
+        CampaignStatus::Completed => {
+            println!("Campaign {} is already completed", name);
+            return Ok(());
+        }
+        CampaignStatus::Active => {}
+    }
+
+    let repos = db.campaign_repos(&campaign)?;
+    let wave: Vec<&CampaignRepo> = repos
+        .iter()
+        .filter(|repo| repo.status.needs_push())
+        .take(cli.wave_size.unwrap_or(usize::MAX))
+        .chain(repos.iter().filter(|repo| repo.status.awaiting_merge()))
+        .collect();
+    if wave.is_empty() {
+        return complete_if_done(db, &campaign);
+    }
+    let urls: Vec<String> = wave.iter().map(|repo| repo.repo_url.clone()).collect();
+    let (repo_urls, repo_paths) = repo_locations(db, urls.clone());
+
+    let issues = check_all_task_implementations(db, &urls, None, sync_mode).await?;
+    let plan = fixer::plan_to(&issues, &campaign.task, &campaign.target);
+    let using_task = fixer::task_repos(&issues, &campaign.task);
+
+    println!("\nCampaign {}:", campaign.name);
+    let mut publish = Vec::new();
+    for repo in wave {
+        let repo_name = RepoId::parse(&repo.repo_url).full_name();
+        let repo_name = repo_name.as_str();
+        let needs_fix = plan.fixes.iter().any(|fix| fix.repo_name == repo_name);
+        let skipped = plan
+            .skipped
+            .iter()
+            .find(|(fix, _)| fix.repo_name == repo_name);
+
+        let (status, detail) = if !using_task.contains(repo_name) {
+            (
+                RepoStatus::Skipped,
+                format!("does not use {}", campaign.task),
+            )
+        } else if !needs_fix && skipped.is_none() {
+            if repo.status.awaiting_merge() {
+                (RepoStatus::Merged, repo.detail.clone())
+            } else {
+                (RepoStatus::Skipped, "already on target".to_string())
+            }
+        } else if repo.status.awaiting_merge() {
+            println!("  {} waiting on {}", repo_name, repo.detail);
+            continue;
+        } else if let (false, Some((fix, reason))) = (needs_fix, skipped) {
+            (RepoStatus::Failed, format!("{} skipped: {}", fix, reason))
+        } else {
+            publish.push(repo_name.to_string());
+            continue;
+        };
+        println!("  {} {}: {}", repo_name, status, detail);
+        db.set_campaign_repo_status(&campaign, &repo.repo_url, status, &detail)?;
+    }
+
+    let wave_plan = fixer::plan_edits(
+        plan.fixes
+            .into_iter()
+            .filter(|fix| publish.contains(&fix.repo_name))
+            .collect(),
+    );
+    if cli.dry_run {
+        print_patches(cli, &fixer::patches(&wave_plan, &repo_paths)?, true);
+        return Ok(());
+    }
+
+    let written = fixer::apply(&wave_plan)?;
+    let outcomes = push_fixes(cli, db, &wave_plan, &written, &repo_urls, &repo_paths).await?;
+    for repo_name in &publish {
+        let (status, detail) = outcomes
+            .get(repo_name)
+            .cloned()
+            .unwrap_or((RepoStatus::Failed, "no files changed".to_string()));
+        db.set_campaign_repo_status(&campaign, &repo_urls[repo_name], status, &detail)?;
+    }
+
+    complete_if_done(db, &campaign)
+}
+
+fn complete_if_done(db: &Database, campaign: &Campaign) -> Result<()> {
+    let done = db
+        .campaign_repos(campaign)?
+        .iter()
+        .all(|repo| matches!(repo.status, RepoStatus::Merged | RepoStatus::Skipped));
+    if done {
+        db.set_campaign_status(campaign, CampaignStatus::Completed)?;
+        println!("\nCampaign {} is completed", campaign.name);
+    }
+    Ok(())
+}
+
+fn print_campaign_status(db: &Database, campaign: &Campaign) -> Result<()> {
+    println!(
+        "Campaign {} ({}): {} {}",
+        campaign.name, campaign.status, campaign.task, campaign.target
+    );
+    let repos = db.campaign_repos(campaign)?;
+    for repo in &repos {
+        let mark = match repo.status {
+            RepoStatus::Failed => "✗",
+            RepoStatus::Merged | RepoStatus::Skipped => "✓",
+            _ => " ",
+        };
+        let detail = if repo.detail.is_empty() {
+            String::new()
+        } else {
+            format!(" {}", repo.detail)
+        };
+        println!(
+            "{} {} {}{} ({})",
+            mark, repo.repo_url, repo.status, detail, repo.updated_at
+        );
+    }
+
+    println!();
+    for status in RepoStatus::ALL {
+        let count = repos.iter().filter(|repo| repo.status == status).count();
+        if count > 0 {
+            println!("{}: {}", status, count);
+        }
+    }
+    Ok(())
+}
+
+async fn discover_azure_repositories(cli: &Cli, db: &Database, scope: &str) -> Result<()> {
+    let scope = AzureScope::parse(scope).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Invalid Azure DevOps scope '{}'. Expected 'org' or 'org/project'",
+            scope
+        )
+    })?;
+    let credentials =
+        discovery_credentials(db, &format!("https://dev.azure.com/{}", scope.organization))?;
+
+    let discovered = AzureDevOpsClient::new(
+        &cli.azure_api_url,
+        &credentials.username,
+        &credentials.token,
+    )
+    .list_repositories(&scope)
+    .await?;
+    let sync = discovery::plan_sync(
+        &discovered,
+        &discovery_filter(cli)?,
+        &db.list_repositories()?,
+        |url| scope.contains(url),
+    );
+    apply_discovery(db, discovered.len(), &sync, HostType::AzureDevOps)
+}
+
+async fn discover_github_repositories(cli: &Cli, db: &Database, org: &str) -> Result<()> {
+    let client = GithubClient::new(&cli.github_api_url, "");
+    let credentials =
+        discovery_credentials(db, &format!("https://{}/{}", client.clone_host(), org))?;
+    let client = GithubClient::new(&cli.github_api_url, &credentials.token);
+
+    let discovered = client.list_org_repositories(org).await?;
+    let sync = discovery::plan_sync(
+        &discovered,
+        &discovery_filter(cli)?,
+        &db.list_repositories()?,
+        |url| {
+            GithubRepo::parse(url, client.clone_host())
+                .is_some_and(|repo| repo.owner.eq_ignore_ascii_case(org))
+        },
+    );
+    apply_discovery(db, discovered.len(), &sync, HostType::GitHub)
+}
+
+async fn discover_gitlab_repositories(cli: &Cli, db: &Database, group: &str) -> Result<()> {
+    let client = GitlabClient::new(&cli.gitlab_api_url, "");
+    let credentials =
+        discovery_credentials(db, &format!("https://{}/{}", client.clone_host(), group))?;
+    let client = GitlabClient::new(&cli.gitlab_api_url, &credentials.token);
+
+    let discovered = client.list_group_projects(group).await?;
+    let group_prefix = format!("{}/", group.trim_matches('/').to_lowercase());
+    let sync = discovery::plan_sync(
+        &discovered,
+        &discovery_filter(cli)?,
+        &db.list_repositories()?,
+        |url| {
+            GitlabProject::parse(url, client.clone_host())
+                .is_some_and(|project| project.path.to_lowercase().starts_with(&group_prefix))
+        },
+    );
+    apply_discovery(db, discovered.len(), &sync, HostType::GitLab)
+}
+
+/// The credentials for the API of a host, picked as for a repository under
+/// `scope_url`. SSH keys are no use there, so a token is required.
+fn discovery_credentials(db: &Database, scope_url: &str) -> Result<Credentials> {
+    let credentials = db.git_credentials(scope_url)?;
+    if credentials.token.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No token for {}. Discovery needs one, set with --set-git-credentials",
+            scope_url
+        ));
+    }
+    Ok(credentials)
+}
+
+fn discovery_filter(cli: &Cli) -> Result<RepoFilter> {
+    Ok(RepoFilter {
+        include_archived: cli.include_archived,
+        include_forks: cli.include_forks,
+        visibility: cli.visibility.clone(),
+        topics: cli.topics.clone(),
+        ..RepoFilter::new(cli.include_repos.as_deref(), cli.exclude_repos.as_deref())?
+    })
+}
+
+/// Adds and removes the repositories a discovery found, reporting each.
+fn apply_discovery(
+    db: &Database,
+    found: usize,
+    sync: &DiscoverySync,
+    host: HostType,
+) -> Result<()> {
+    println!("Found {} repositories", found);
+    for (name, reason) in &sync.skipped {
+        println!("✗ Skipped {}: {}", name, reason);
+    }
+    for url in &sync.added {
+        db.add_repository_with_host(url, host)?;
+        println!("✓ Added {}", url);
+    }
+    for url in &sync.removed {
+        db.delete_repository(url)?;
+    }
+
+    println!(
+        "\nAdded {}, removed {}, skipped {} repositories",
+        sync.added.len(),
+        sync.removed.len(),
+        sync.skipped.len()
+    );
+    Ok(())
+}
+
+/// Reports the clones adding repositories would make. The repositories are
+/// still added to the database, inside the dry run's uncommitted transaction.
+async fn preview_add_repositories(db: &Database, repo_urls: &[&str], is_new: bool) -> Result<()> {
+    for repo_url in repo_urls {
+        GitManager::with_credentials(
+            db.workspace(),
+            &db.git_credentials(repo_url)?,
+            repo_url,
+            HostType::detect(repo_url),
+        )
+        .ensure_repo_exists_dry_run(is_new)
+        .await?;
+        db.add_repository_sync(repo_url)?;
+    }
+    Ok(())
+}
+
 async fn handle_list_all_task_states(db: &Database) -> Result<()> {
     let tasks = db.get_all_tasks()?;
 
@@ -298,7 +1138,9 @@ fn load_config_if_needed(cli: &Cli) -> Result<Option<Config>> {
         || cli.list_task_states.is_some()
         || cli.list_all_task_states
         || cli.analyze_tasks
-        || cli.check_tasks;
+        || cli.check_tasks
+        || cli.fix
+        || cli.resume_campaign.is_some();
 
     if needs_config {
         let config = Config::load(cli.config_path.as_deref())?;
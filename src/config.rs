@@ -1,4 +1,5 @@
-use crate::{GitVersionState, SupportedTask, TaskValidState};
+use crate::policy::Policies;
+use crate::{version_spec, GitRef, GitVersionState, SupportedTask, TaskValidState};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -8,6 +9,14 @@ use std::path::PathBuf;
 pub struct Config {
     #[serde(default)]
     pub task_states: TaskStates,
+    /// Where the database and checkouts live, unless `--data-dir` or
+    /// `GAVIN_HOME` say otherwise. Relative to the config file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_dir: Option<PathBuf>,
+    /// Forbidden, deprecated and required tasks. When present they replace
+    /// the policies stored in the database.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policies: Option<Policies>,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -17,6 +26,12 @@ pub struct TaskStates {
     // Add other task types here as needed
     #[serde(default)]
     pub other_tasks: HashMap<String, Vec<String>>,
+    /// GitHub actions keyed by `owner/repo`, with refs such as `v4`, `branch:main` or a commit SHA
+    #[serde(default)]
+    pub github_actions: HashMap<String, Vec<String>>,
+    /// GitLab references keyed like `image:node` or `project:group/templates`, with refs or tags
+    #[serde(default)]
+    pub gitlab: HashMap<String, Vec<String>>,
 }
 
 impl Config {
@@ -28,22 +43,56 @@ impl Config {
         if !path.exists() {
             return Ok(Config {
                 task_states: TaskStates::default(),
+                data_dir: None,
+                policies: None,
             });
         }
 
         let content = std::fs::read_to_string(&path)?;
-        let config: Config = serde_yaml::from_str(&content)
+        let mut config: Config = serde_yaml::from_str(&content)
             .map_err(|e| anyhow::anyhow!("Failed to parse config file: {}", e))?;
+        if let (Some(data_dir), Some(config_dir)) = (&config.data_dir, path.parent()) {
+            config.data_dir = Some(config_dir.join(data_dir));
+        }
 
         Ok(config)
     }
 
+    /// Every task the config defines at least one valid state for.
+    pub fn configured_tasks(&self) -> Vec<SupportedTask> {
+        let states = &self.task_states;
+        let gitversion = Some(SupportedTask::Gitversion).filter(|_| !states.gitversion.is_empty());
+        let named = |tasks: &HashMap<String, Vec<String>>, task: fn(String) -> SupportedTask| {
+            tasks
+                .iter()
+                .filter(|(_, versions)| !versions.is_empty())
+                .map(move |(name, _)| task(name.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        let mut tasks: Vec<SupportedTask> = gitversion
+            .into_iter()
+            .chain(named(&states.other_tasks, SupportedTask::Default))
+            .chain(named(&states.github_actions, SupportedTask::GithubAction))
+            .chain(named(&states.gitlab, SupportedTask::Gitlab))
+            .collect();
+        tasks.sort_by_key(|task| task.to_string());
+        tasks
+    }
+
     pub fn get_valid_states(&self, task: &SupportedTask) -> Vec<TaskValidState> {
         match task {
             SupportedTask::Gitversion => self
                 .task_states
                 .gitversion
                 .iter()
+                .filter(|state| match state.validate() {
+                    Ok(()) => true,
+                    Err(e) => {
+                        eprintln!("Ignoring state for {}: {}", task, e);
+                        false
+                    }
+                })
                 .cloned()
                 .map(TaskValidState::Gitversion)
                 .collect(),
@@ -54,12 +103,42 @@ impl Config {
                 .map(|versions| {
                     versions
                         .iter()
+                        .filter(|version| match version_spec::validate(version) {
+                            Ok(()) => true,
+                            Err(e) => {
+                                eprintln!("Ignoring state for {}: {}", task, e);
+                                false
+                            }
+                        })
                         .cloned()
                         .map(TaskValidState::Default)
                         .collect()
                 })
                 .unwrap_or_default(),
+            SupportedTask::GithubAction(name) => {
+                git_ref_states(task, &self.task_states.github_actions, name)
+            }
+            SupportedTask::Gitlab(name) => git_ref_states(task, &self.task_states.gitlab, name),
             // Add other task types here as needed
         }
     }
 }
+
+fn git_ref_states(
+    task: &SupportedTask,
+    states: &HashMap<String, Vec<String>>,
+    name: &str,
+) -> Vec<TaskValidState> {
+    states
+        .iter()
+        .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+        .flat_map(|(_, refs)| refs)
+        .filter_map(|git_ref| match GitRef::from_string(git_ref) {
+            Ok(git_ref) => Some(TaskValidState::GitRef(git_ref)),
+            Err(e) => {
+                eprintln!("Ignoring state {} for {}: {}", git_ref, task, e);
+                None
+            }
+        })
+        .collect()
+}
@@ -1,57 +1,88 @@
+use crate::campaign::{Campaign, CampaignRepo, CampaignStatus, RepoStatus};
 use crate::config::Config;
 use crate::git_manager::GitManager;
+use crate::host::HostType;
+use crate::metadata::{RepoMetadata, RepoSelector};
+use crate::migrations;
+use crate::parse_cache::{ParsedFile, ParsedRepository};
+use crate::parse_task_name;
+use crate::policy::Policies;
+use crate::repo_id::{self, RepoId};
+use crate::scan::{PolicyFinding, Scan, ScanRef, ScanResults, ScannedRepository, TaskOccurrence};
+use crate::secrets::{self, CredentialScope, CredentialSource, Credentials, KeySource};
+use crate::workspace::Workspace;
 use crate::SupportedTask;
 use crate::TaskValidState;
 use anyhow::Result;
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OpenFlags};
+use std::collections::HashMap;
 use std::path::PathBuf;
 
+/// Valid states a config merge adds to or removes from one task.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateChange {
+    pub task: SupportedTask,
+    pub added: Vec<TaskValidState>,
+    pub removed: Vec<TaskValidState>,
+}
+
+impl std::fmt::Display for StateChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Valid states for {}:", self.task)?;
+        for state in &self.removed {
+            write!(f, "\n- {}", state)?;
+        }
+        for state in &self.added {
+            write!(f, "\n+ {}", state)?;
+        }
+        Ok(())
+    }
+}
+
 pub struct Database {
     conn: Connection,
+    workspace: Workspace,
 }
 
-impl Database {
-    pub fn new() -> Result<Self> {
-        let db_path = std::env::current_dir()?.join("gavin.db");
-        let conn = Connection::open(db_path)?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS repositories (
-                id INTEGER PRIMARY KEY,
-                url TEXT NOT NULL UNIQUE
-            )",
-            [],
-        )?;
+/// A `git_credentials` row, with the token still encrypted.
+struct StoredCredentials {
+    id: i64,
+    username: String,
+    token: Vec<u8>,
+    scope: CredentialScope,
+    ssh_key: Option<PathBuf>,
+}
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS git_credentials (
-                id INTEGER PRIMARY KEY,
-                username TEXT NOT NULL,
-                token BLOB NOT NULL
-            )",
-            [],
-        )?;
+impl Database {
+    /// Opens the workspace's database, creating and migrating it as needed.
+    pub fn open(workspace: &Workspace) -> Result<Self> {
+        let mut conn = match workspace.memory_database() {
+            Some(name) => Connection::open_with_flags(
+                format!("file:{}?mode=memory&cache=shared", name),
+                OpenFlags::default() | OpenFlags::SQLITE_OPEN_URI,
+            )?,
+            None => Connection::open(workspace.database_path())?,
+        };
+        migrations::migrate(&mut conn)?;
+        Ok(Database {
+            conn,
+            workspace: workspace.clone(),
+        })
+    }
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS valid_states (
-                id INTEGER PRIMARY KEY,
-                task TEXT NOT NULL,
-                state_json TEXT NOT NULL
-            )",
-            [],
-        )?;
+    /// A database in memory for a workspace rooted at `root`, for tests.
+    pub fn in_memory(root: impl Into<PathBuf>) -> Result<Self> {
+        Self::open(&Workspace::in_memory(root))
+    }
 
-        Ok(Database { conn })
+    pub fn workspace(&self) -> &Workspace {
+        &self.workspace
     }
 
     pub async fn add_repository(&self, url: &str, is_new: bool) -> Result<()> {
-        let credentials = self.get_git_credentials()?.ok_or_else(|| {
-            anyhow::anyhow!(
-                "Git credentials not found. Please set them first with --set-git-credentials"
-            )
-        })?;
-
-        let git_manager = GitManager::new(credentials.0, credentials.1, url);
+        let credentials = self.git_credentials(url)?;
+        let git_manager =
+            GitManager::with_credentials(&self.workspace, &credentials, url, HostType::detect(url));
 
         if is_new {
             git_manager.ensure_repo_exists_new().await?;
@@ -67,6 +98,31 @@ impl Database {
         let rows_affected = self
             .conn
             .execute("DELETE FROM repositories WHERE url = ?1", params![url])?;
+        self.conn.execute(
+            "DELETE FROM repository_tags WHERE repo_url = ?1",
+            params![url],
+        )?;
+        self.conn.execute(
+            "DELETE FROM repository_groups WHERE repo_url = ?1",
+            params![url],
+        )?;
+        self.conn
+            .execute("DELETE FROM parsed_files WHERE repo_url = ?1", params![url])?;
+        self.conn.execute(
+            "DELETE FROM parsed_repositories WHERE repo_url = ?1",
+            params![url],
+        )?;
+        // Campaigns keep the repository in their history, but stop waiting for it
+        self.conn.execute(
+            "UPDATE campaign_repos SET status = ?1, detail = 'Repository removed',
+                 updated_at = CURRENT_TIMESTAMP
+             WHERE repo_url = ?2 AND status NOT IN (?1, ?3)",
+            params![
+                RepoStatus::Skipped.as_str(),
+                url,
+                RepoStatus::Merged.as_str()
+            ],
+        )?;
 
         if rows_affected > 0 {
             println!("Deleted repository: {}", url);
@@ -137,114 +193,777 @@ impl Database {
         Ok(())
     }
 
-    pub fn set_git_credentials(&self, credentials: &str) -> Result<()> {
-        let parts: Vec<&str> = credentials.split(':').collect();
-        if parts.len() != 2 {
-            return Err(anyhow::anyhow!(
-                "Invalid credentials format. Expected 'username:token'"
-            ));
+    /// Stores `username:token` for the repositories in `scope`, replacing the
+    /// token stored for it before. The token is encrypted with the key from
+    /// [`secrets::SecretKey::load`], creating a key file if there is no key yet.
+    pub fn set_git_credentials(
+        &self,
+        credentials: &str,
+        scope: &CredentialScope,
+    ) -> Result<KeySource> {
+        let (username, token) = credentials.split_once(':').ok_or_else(|| {
+            anyhow::anyhow!("Invalid credentials format. Expected 'username:token'")
+        })?;
+
+        let key = secrets::SecretKey::load(
+            &self.workspace.key_file(),
+            true,
+            self.workspace.environment(),
+        )?
+        .ok_or_else(|| anyhow::anyhow!("No encryption key available"))?;
+        let encrypted_token = key.encrypt(token)?;
+        let updated = self.conn.execute(
+            "UPDATE git_credentials SET username = ?1, token = ?2
+             WHERE host IS ?3 AND path_prefix IS ?4",
+            params![username, encrypted_token, scope.host, scope.path],
+        )?;
+        if updated == 0 {
+            self.conn.execute(
+                "INSERT INTO git_credentials (username, token, host, path_prefix)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![username, encrypted_token, scope.host, scope.path],
+            )?;
         }
+        Ok(key.source)
+    }
 
-        let username = parts[0];
-        let token = parts[1];
+    /// Encrypts every token stored by older versions, which only XORed it,
+    /// with the key from [`secrets::SecretKey::load`], creating a key file if
+    /// there is no key yet. Returns how many were encrypted, and with which key.
+    pub fn encrypt_legacy_tokens(&self) -> Result<Option<(usize, KeySource)>> {
+        let legacy: Vec<(i64, Vec<u8>)> = self
+            .stored_credentials()?
+            .into_iter()
+            .filter(|stored| !stored.token.is_empty() && !secrets::is_encrypted(&stored.token))
+            .map(|stored| (stored.id, stored.token))
+            .collect();
+        if legacy.is_empty() {
+            return Ok(None);
+        }
 
-        let encrypted_token = token
-            .as_bytes()
+        let key = secrets::SecretKey::load(
+            &self.workspace.key_file(),
+            true,
+            self.workspace.environment(),
+        )?
+        .ok_or_else(|| anyhow::anyhow!("No encryption key available"))?;
+        self.conn.execute_batch("SAVEPOINT encrypt_tokens")?;
+        let result = legacy.iter().try_for_each(|(id, token)| -> Result<()> {
+            let token = secrets::decode_legacy(token)?;
+            self.conn.execute(
+                "UPDATE git_credentials SET token = ?1 WHERE id = ?2",
+                params![key.encrypt(&token)?, id],
+            )?;
+            Ok(())
+        });
+        match result {
+            Ok(_) => self.conn.execute_batch("RELEASE encrypt_tokens")?,
+            Err(_) => self
+                .conn
+                .execute_batch("ROLLBACK TO encrypt_tokens; RELEASE encrypt_tokens")?,
+        }
+        result?;
+        Ok(Some((legacy.len(), key.source)))
+    }
+
+    /// Sets the private key git uses for SSH URLs in `scope`.
+    pub fn set_ssh_key(&self, key_path: &str, scope: &CredentialScope) -> Result<PathBuf> {
+        let key_path = std::fs::canonicalize(key_path)
+            .map_err(|e| anyhow::anyhow!("SSH key {} not found: {}", key_path, e))?;
+        let key = key_path.to_string_lossy();
+
+        let updated = self.conn.execute(
+            "UPDATE git_credentials SET ssh_key = ?1 WHERE host IS ?2 AND path_prefix IS ?3",
+            params![key, scope.host, scope.path],
+        )?;
+        if updated == 0 {
+            self.conn.execute(
+                "INSERT INTO git_credentials (username, token, host, path_prefix, ssh_key)
+                 VALUES ('', x'', ?1, ?2, ?3)",
+                params![scope.host, scope.path, key],
+            )?;
+        }
+        Ok(key_path)
+    }
+
+    pub fn delete_git_credentials(&self, scope: &CredentialScope) -> Result<bool> {
+        let deleted = self.conn.execute(
+            "DELETE FROM git_credentials WHERE host IS ?1 AND path_prefix IS ?2",
+            params![scope.host, scope.path],
+        )?;
+        Ok(deleted > 0)
+    }
+
+    /// Every stored set of credentials, with tokens decrypted.
+    pub fn list_git_credentials(&self) -> Result<Vec<Credentials>> {
+        let mut credentials = self
+            .stored_credentials()?
+            .iter()
+            .map(|stored| self.unlock(stored))
+            .collect::<Result<Vec<_>>>()?;
+        credentials.sort_by_key(|c| c.scope.to_string());
+        Ok(credentials)
+    }
+
+    /// The credentials for `url`. Stored credentials scoped to its host win,
+    /// the most specific first; then a token from the environment; then the
+    /// stored default.
+    pub fn credentials_for(&self, url: &str) -> Result<Option<Credentials>> {
+        let repo = RepoId::parse(url);
+        let stored = self.stored_credentials()?;
+        let best = stored
             .iter()
-            .map(|b| b ^ 0xFF)
-            .collect::<Vec<u8>>();
+            .filter_map(|stored| Some((stored.scope.specificity(&repo)?, stored)))
+            .max_by_key(|(specificity, _)| *specificity);
 
-        self.conn.execute("DELETE FROM git_credentials", [])?;
+        match best {
+            Some((specificity, stored)) if specificity > 0 => self.unlock(stored).map(Some),
+            _ => match secrets::from_environment(self.workspace.environment()) {
+                Some(credentials) => Ok(Some(credentials)),
+                None => best.map(|(_, stored)| self.unlock(stored)).transpose(),
+            },
+        }
+    }
 
-        self.conn.execute(
-            "INSERT INTO git_credentials (username, token) VALUES (?1, ?2)",
-            params![username, encrypted_token],
+    /// Like [`Database::credentials_for`], but failing when there are none,
+    /// except for SSH URLs, which can rely on the user's SSH setup.
+    pub fn git_credentials(&self, url: &str) -> Result<Credentials> {
+        match self.credentials_for(url)? {
+            Some(credentials) => Ok(credentials),
+            None if repo_id::is_ssh_url(url) => Ok(Credentials::ssh_defaults()),
+            None => Err(anyhow::anyhow!(
+                "Git credentials for {} not found. Set {} or use --set-git-credentials",
+                url,
+                secrets::TOKEN_VARS[0].0
+            )),
+        }
+    }
+
+    fn stored_credentials(&self) -> Result<Vec<StoredCredentials>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, username, token, host, path_prefix, ssh_key FROM git_credentials",
         )?;
+        let stored = stmt
+            .query_map([], |row| {
+                Ok(StoredCredentials {
+                    id: row.get(0)?,
+                    username: row.get(1)?,
+                    token: row.get(2)?,
+                    scope: CredentialScope {
+                        host: row.get(3)?,
+                        path: row.get(4)?,
+                    },
+                    ssh_key: row.get::<_, Option<String>>(5)?.map(PathBuf::from),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(stored)
+    }
 
-        Ok(())
+    /// Decrypts a stored token. Ones stored by older versions have to be
+    /// encrypted with [`Database::encrypt_legacy_tokens`] first.
+    fn unlock(&self, stored: &StoredCredentials) -> Result<Credentials> {
+        let (token, key_source) = if stored.token.is_empty() {
+            (String::new(), None)
+        } else if secrets::is_encrypted(&stored.token) {
+            let key = secrets::SecretKey::load(
+                &self.workspace.key_file(),
+                false,
+                self.workspace.environment(),
+            )?
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "The stored git token is encrypted but no key was found. Set {} or {}",
+                    secrets::KEY_VAR,
+                    secrets::KEY_FILE_VAR
+                )
+            })?;
+            (key.decrypt(&stored.token)?, Some(key.source))
+        } else {
+            return Err(anyhow::anyhow!(
+                "The git token stored for {} was saved unencrypted by an older version. \
+                 Run gavin --encrypt-credentials to encrypt it",
+                stored.scope
+            ));
+        };
+
+        Ok(Credentials {
+            username: stored.username.clone(),
+            token,
+            ssh_key: stored.ssh_key.clone(),
+            scope: stored.scope.clone(),
+            source: CredentialSource::Database(key_source),
+        })
     }
 
-    pub fn get_git_credentials(&self) -> Result<Option<(String, String)>> {
-        let result = self.conn.query_row(
-            "SELECT username, token FROM git_credentials LIMIT 1",
-            [],
-            |row| {
-                let username: String = row.get(0)?;
-                let encrypted_token: Vec<u8> = row.get(1)?;
+    pub fn get_local_path(&self, repo_url: &str) -> PathBuf {
+        self.workspace.checkout_dir(repo_url)
+    }
 
-                let token = encrypted_token
-                    .iter()
-                    .map(|b| b ^ 0xFF)
-                    .collect::<Vec<u8>>();
+    pub fn add_repository_sync(&self, url: &str) -> Result<()> {
+        self.add_repository_with_host(url, HostType::detect(url))
+    }
+
+    /// Adds a repository on a host its URL does not reveal, such as a
+    /// self-hosted GitLab found through discovery.
+    pub fn add_repository_with_host(&self, url: &str, host: HostType) -> Result<()> {
+        let canonical = RepoId::parse(url).canonical();
+        if let Some(existing) = self.find_repository(url)? {
+            if existing != url {
+                return Err(anyhow::anyhow!(
+                    "{} is already added as {}",
+                    canonical,
+                    existing
+                ));
+            }
+        }
 
-                let token = String::from_utf8(token)
-                    .map_err(|e| rusqlite::Error::InvalidParameterName(e.to_string()))?;
+        // Upsert rather than replace, which would drop the owner
+        self.conn.execute(
+            "INSERT INTO repositories (url, host, canonical) VALUES (?1, ?2, ?3)
+             ON CONFLICT(url) DO UPDATE SET host = excluded.host",
+            params![url, host.as_str(), canonical],
+        )?;
+        Ok(())
+    }
 
-                Ok((username, token))
-            },
+    /// The stored URL of the repository `url` points at, however either is written.
+    pub fn find_repository(&self, url: &str) -> Result<Option<String>> {
+        let result = self.conn.query_row(
+            "SELECT url FROM repositories WHERE LOWER(canonical) = LOWER(?1)",
+            params![RepoId::parse(url).canonical()],
+            |row| row.get::<_, String>(0),
         );
 
         match result {
-            Ok(creds) => Ok(Some(creds)),
+            Ok(url) => Ok(Some(url)),
             Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
             Err(e) => Err(e.into()),
         }
     }
 
-    pub fn get_local_path(&self, repo_url: &str) -> PathBuf {
-        let repo_name = repo_url.split('/').last().unwrap_or("repo");
-
-        std::env::current_dir()
-            .expect("Failed to get current directory")
-            .join("temp_repos")
-            .join(repo_name)
-    }
+    /// Stores the owner, tags and groups of a known repository, replacing
+    /// what was stored before.
+    pub fn set_repository_metadata(&self, metadata: &RepoMetadata) -> Result<()> {
+        let rows_affected = self.conn.execute(
+            "UPDATE repositories SET owner = ?1 WHERE url = ?2",
+            params![metadata.owner, metadata.url],
+        )?;
+        if rows_affected == 0 {
+            return Err(anyhow::anyhow!("Repository not found: {}", metadata.url));
+        }
 
-    pub fn add_repository_sync(&self, url: &str) -> Result<()> {
         self.conn.execute(
-            "INSERT OR REPLACE INTO repositories (url) VALUES (?1)",
-            params![url],
+            "DELETE FROM repository_tags WHERE repo_url = ?1",
+            params![metadata.url],
         )?;
+        for tag in &metadata.tags {
+            self.conn.execute(
+                "INSERT OR IGNORE INTO repository_tags (repo_url, tag) VALUES (?1, ?2)",
+                params![metadata.url, tag],
+            )?;
+        }
+        self.conn.execute(
+            "DELETE FROM repository_groups WHERE repo_url = ?1",
+            params![metadata.url],
+        )?;
+        for group in &metadata.groups {
+            self.conn.execute(
+                "INSERT OR IGNORE INTO repository_groups (repo_url, group_name) VALUES (?1, ?2)",
+                params![metadata.url, group],
+            )?;
+        }
         Ok(())
     }
 
+    pub fn repository_metadata(&self, url: &str) -> Result<RepoMetadata> {
+        let owner = self
+            .conn
+            .query_row(
+                "SELECT owner FROM repositories WHERE url = ?1",
+                params![url],
+                |row| row.get::<_, Option<String>>(0),
+            )
+            .map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => {
+                    anyhow::anyhow!("Repository not found: {}", url)
+                }
+                e => e.into(),
+            })?;
+
+        let mut stmt = self.prepare_statement(
+            "SELECT tag FROM repository_tags WHERE repo_url = ?1 ORDER BY tag",
+        )?;
+        let tags = stmt
+            .query_map(params![url], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        let mut stmt = self.prepare_statement(
+            "SELECT group_name FROM repository_groups WHERE repo_url = ?1 ORDER BY group_name",
+        )?;
+        let groups = stmt
+            .query_map(params![url], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+
+        Ok(RepoMetadata {
+            url: url.to_string(),
+            owner,
+            tags,
+            groups,
+        })
+    }
+
+    /// The repositories `selector` picks, all of them when it is empty.
+    pub fn select_repositories(&self, selector: &RepoSelector) -> Result<Vec<String>> {
+        let mut selected = Vec::new();
+        for url in self.list_repositories()? {
+            if selector.is_empty() || selector.matches(&self.repository_metadata(&url)?) {
+                selected.push(url);
+            }
+        }
+        Ok(selected)
+    }
+
+    /// Every repository with the host it lives on.
+    pub fn list_repository_hosts(&self) -> Result<Vec<(String, HostType)>> {
+        let mut stmt = self.conn.prepare("SELECT url, host FROM repositories")?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(|(url, host)| {
+                let host = match host {
+                    Some(host) => host.parse()?,
+                    None => HostType::detect(&url),
+                };
+                Ok((url, host))
+            })
+            .collect()
+    }
+
+    pub fn repository_host(&self, url: &str) -> Result<HostType> {
+        let result = self.conn.query_row(
+            "SELECT host FROM repositories WHERE url = ?1",
+            params![url],
+            |row| row.get::<_, Option<String>>(0),
+        );
+
+        match result {
+            Ok(Some(host)) => host.parse(),
+            Ok(None) | Err(rusqlite::Error::QueryReturnedNoRows) => Ok(HostType::detect(url)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     pub fn merge_config_states(&self, config: &Config) -> Result<()> {
-        // Only merge states if they exist in the config
-        if !config.task_states.gitversion.is_empty() {
-            // Clear existing gitversion states
+        // Tasks the config defines states for replace whatever the database holds
+        for task in config.configured_tasks() {
             self.conn.execute(
-                "DELETE FROM valid_states WHERE LOWER(task) = 'gitversion'",
-                [],
+                "DELETE FROM valid_states WHERE LOWER(task) = LOWER(?1)",
+                params![task.to_string()],
             )?;
-
-            // Add gitversion states
-            let task = SupportedTask::Gitversion;
             for state in config.get_valid_states(&task) {
                 self.add_valid_state(&task, &state)?;
             }
         }
+        // Policies in the config replace the stored ones as a whole
+        if let Some(policies) = &config.policies {
+            self.replace_task_policies(policies)?;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the stored task policies.
+    pub fn replace_task_policies(&self, policies: &Policies) -> Result<()> {
+        policies.validate()?;
+        self.conn.execute("DELETE FROM task_policies", [])?;
+        let insert = |kind: &str, task: &str, json: String| -> Result<()> {
+            self.conn.execute(
+                "INSERT INTO task_policies (kind, task, policy_json) VALUES (?1, ?2, ?3)",
+                params![kind, task, json],
+            )?;
+            Ok(())
+        };
+        for policy in &policies.forbidden {
+            insert("forbidden", &policy.task, serde_json::to_string(policy)?)?;
+        }
+        for policy in &policies.deprecated {
+            insert("deprecated", &policy.task, serde_json::to_string(policy)?)?;
+        }
+        for policy in &policies.required {
+            insert("required", &policy.task, serde_json::to_string(policy)?)?;
+        }
+        Ok(())
+    }
+
+    pub fn task_policies(&self) -> Result<Policies> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT kind, policy_json FROM task_policies ORDER BY id")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+
+        let mut policies = Policies::default();
+        for row in rows {
+            let (kind, json) = row?;
+            match kind.as_str() {
+                "forbidden" => policies.forbidden.push(serde_json::from_str(&json)?),
+                "deprecated" => policies.deprecated.push(serde_json::from_str(&json)?),
+                "required" => policies.required.push(serde_json::from_str(&json)?),
+                other => eprintln!("Ignoring task policy of unknown kind {}", other),
+            }
+        }
+        Ok(policies)
+    }
 
-        // Handle other tasks from config
-        for (task_name, versions) in &config.task_states.other_tasks {
-            if !versions.is_empty() {
-                let task = SupportedTask::Default(task_name.clone());
-                // Clear existing states for this task
+    /// The changes [`Database::merge_config_states`] would make, without making them.
+    pub fn config_state_changes(&self, config: &Config) -> Result<Vec<StateChange>> {
+        let mut changes = Vec::new();
+        for task in config.configured_tasks() {
+            let current = self.list_valid_states(&task)?;
+            let configured = config.get_valid_states(&task);
+            let change = StateChange {
+                added: configured
+                    .iter()
+                    .filter(|state| !current.contains(state))
+                    .cloned()
+                    .collect(),
+                removed: current
+                    .iter()
+                    .filter(|state| !configured.contains(state))
+                    .cloned()
+                    .collect(),
+                task,
+            };
+            if !change.added.is_empty() || !change.removed.is_empty() {
+                changes.push(change);
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Stores a scan with its repositories and occurrences, a finding for
+    /// each invalid occurrence and one for each policy violation.
+    pub fn record_scan(
+        &self,
+        repositories: &[ScannedRepository],
+        occurrences: &[TaskOccurrence],
+        policy_findings: &[PolicyFinding],
+    ) -> Result<Scan> {
+        // A savepoint also works inside the transaction of a dry run
+        self.conn.execute_batch("SAVEPOINT record_scan")?;
+        let result = self.insert_scan(repositories, occurrences, policy_findings);
+        match result {
+            Ok(_) => self.conn.execute_batch("RELEASE record_scan")?,
+            Err(_) => self
+                .conn
+                .execute_batch("ROLLBACK TO record_scan; RELEASE record_scan")?,
+        }
+        let id = result?;
+        self.get_scan(id)?
+            .ok_or_else(|| anyhow::anyhow!("Scan {} was not stored", id))
+    }
+
+    fn insert_scan(
+        &self,
+        repositories: &[ScannedRepository],
+        occurrences: &[TaskOccurrence],
+        policy_findings: &[PolicyFinding],
+    ) -> Result<i64> {
+        self.conn.execute("INSERT INTO scans DEFAULT VALUES", [])?;
+        let scan_id = self.conn.last_insert_rowid();
+
+        for repo in repositories {
+            self.conn.execute(
+                "INSERT OR IGNORE INTO scan_repositories (scan_id, repo_url, commit_sha)
+                 VALUES (?1, ?2, ?3)",
+                params![scan_id, repo.url, repo.commit_sha],
+            )?;
+        }
+        for occurrence in occurrences {
+            self.conn.execute(
+                "INSERT INTO task_occurrences
+                 (scan_id, repo_url, task, version, pipeline, file, line, valid)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    scan_id,
+                    occurrence.repo_url,
+                    occurrence.task,
+                    occurrence.version,
+                    occurrence.pipeline,
+                    occurrence.file,
+                    occurrence.line as i64,
+                    occurrence.is_valid()
+                ],
+            )?;
+            if let Some(issue) = &occurrence.issue {
                 self.conn.execute(
-                    "DELETE FROM valid_states WHERE LOWER(task) = LOWER(?1)",
-                    params![task_name],
+                    "INSERT INTO findings (scan_id, occurrence_id, repo_url, task, severity, message)
+                     VALUES (?1, ?2, ?3, ?4, 'error', ?5)",
+                    params![
+                        scan_id,
+                        self.conn.last_insert_rowid(),
+                        occurrence.repo_url,
+                        occurrence.task,
+                        issue
+                    ],
                 )?;
-
-                // Add new states
-                for state in config.get_valid_states(&task) {
-                    self.add_valid_state(&task, &state)?;
-                }
             }
         }
+        for finding in policy_findings {
+            self.conn.execute(
+                "INSERT INTO findings (scan_id, occurrence_id, repo_url, task, severity, message)
+                 VALUES (?1, NULL, ?2, ?3, ?4, ?5)",
+                params![
+                    scan_id,
+                    finding.repo_url,
+                    finding.task,
+                    finding.severity.to_string(),
+                    finding.message
+                ],
+            )?;
+        }
+        Ok(scan_id)
+    }
+
+    const SCAN_COLUMNS: &'static str = "SELECT s.id, s.created_at,
+            (SELECT COUNT(*) FROM scan_repositories r WHERE r.scan_id = s.id),
+            (SELECT COUNT(*) FROM task_occurrences o WHERE o.scan_id = s.id),
+            (SELECT COUNT(*) FROM task_occurrences o WHERE o.scan_id = s.id AND NOT o.valid)
+         FROM scans s";
+
+    fn scan_from_row(row: &rusqlite::Row) -> rusqlite::Result<Scan> {
+        Ok(Scan {
+            id: row.get(0)?,
+            created_at: row.get(1)?,
+            repositories: row.get::<_, i64>(2)? as usize,
+            occurrences: row.get::<_, i64>(3)? as usize,
+            invalid: row.get::<_, i64>(4)? as usize,
+        })
+    }
+
+    pub fn get_scan(&self, id: i64) -> Result<Option<Scan>> {
+        self.optional_scan(
+            &format!("{} WHERE s.id = ?1", Self::SCAN_COLUMNS),
+            params![id],
+        )
+    }
+
+    /// Every scan, oldest first.
+    pub fn list_scans(&self) -> Result<Vec<Scan>> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!("{} ORDER BY s.id", Self::SCAN_COLUMNS))?;
+        let scans = stmt
+            .query_map([], Self::scan_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(scans)
+    }
+
+    pub fn latest_scan(&self) -> Result<Option<Scan>> {
+        self.optional_scan(
+            &format!("{} ORDER BY s.id DESC LIMIT 1", Self::SCAN_COLUMNS),
+            [],
+        )
+    }
+
+    /// The scan with an id, or the last one taken on or before a date.
+    pub fn find_scan(&self, scan: ScanRef) -> Result<Option<Scan>> {
+        match scan {
+            ScanRef::Id(id) => self.get_scan(id),
+            ScanRef::Date(date) => self.optional_scan(
+                &format!(
+                    "{} WHERE s.created_at < date(?1, '+1 day') ORDER BY s.id DESC LIMIT 1",
+                    Self::SCAN_COLUMNS
+                ),
+                params![date.format("%Y-%m-%d").to_string()],
+            ),
+        }
+    }
+
+    fn optional_scan(&self, sql: &str, params: impl rusqlite::Params) -> Result<Option<Scan>> {
+        match self.conn.query_row(sql, params, Self::scan_from_row) {
+            Ok(scan) => Ok(Some(scan)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn scan_results(&self, scan_id: i64) -> Result<ScanResults> {
+        Ok(ScanResults {
+            repositories: self.scan_repositories(scan_id)?,
+            occurrences: self.scan_occurrences(scan_id)?,
+        })
+    }
+
+    pub fn scan_repositories(&self, scan_id: i64) -> Result<Vec<ScannedRepository>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT repo_url, commit_sha FROM scan_repositories WHERE scan_id = ?1 ORDER BY repo_url",
+        )?;
+        let repositories = stmt
+            .query_map(params![scan_id], |row| {
+                Ok(ScannedRepository {
+                    url: row.get(0)?,
+                    commit_sha: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(repositories)
+    }
+
+    /// The occurrences a scan found, with the finding of each invalid one.
+    pub fn scan_occurrences(&self, scan_id: i64) -> Result<Vec<TaskOccurrence>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT o.repo_url, o.task, o.version, o.pipeline, o.file, o.line, f.message
+             FROM task_occurrences o LEFT JOIN findings f ON f.occurrence_id = o.id
+             WHERE o.scan_id = ?1
+             ORDER BY o.repo_url, o.task, o.version, o.pipeline, o.file, o.line",
+        )?;
+        let occurrences = stmt
+            .query_map(params![scan_id], |row| {
+                Ok(TaskOccurrence {
+                    repo_url: row.get(0)?,
+                    task: row.get(1)?,
+                    version: row.get(2)?,
+                    pipeline: row.get(3)?,
+                    file: row.get(4)?,
+                    line: row.get::<_, i64>(5)? as usize,
+                    issue: row.get(6)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(occurrences)
+    }
+
+    /// The policy violations a scan found, errors first.
+    pub fn scan_policy_findings(&self, scan_id: i64) -> Result<Vec<PolicyFinding>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT repo_url, task, severity, message FROM findings
+             WHERE scan_id = ?1 AND occurrence_id IS NULL
+             ORDER BY repo_url, message",
+        )?;
+        let rows = stmt.query_map(params![scan_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?;
+        let mut findings = Vec::new();
+        for row in rows {
+            let (repo_url, task, severity, message) = row?;
+            findings.push(PolicyFinding {
+                repo_url,
+                task,
+                severity: severity.parse().map_err(anyhow::Error::msg)?,
+                message,
+            });
+        }
+        findings.sort_by_key(|finding| std::cmp::Reverse(finding.severity));
+        Ok(findings)
+    }
+
+    /// The last parse of each repository, for [`crate::parse_cache::ParseCache`].
+    pub fn parsed_repositories(&self) -> Result<HashMap<String, ParsedRepository>> {
+        let mut files: HashMap<String, Vec<ParsedFile>> = HashMap::new();
+        let mut stmt = self.conn.prepare(
+            "SELECT repo_url, platform, path, blob_sha, dependencies_json, pipelines_json
+             FROM parsed_files ORDER BY id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, String>(5)?,
+            ))
+        })?;
+        for row in rows {
+            let (url, platform, path, blob_sha, dependencies, pipelines) = row?;
+            files.entry(url).or_default().push(ParsedFile {
+                platform,
+                path: PathBuf::from(path),
+                blob_sha,
+                dependencies: serde_json::from_str(&dependencies)?,
+                pipelines: serde_json::from_str(&pipelines)?,
+            });
+        }
+
+        let mut stmt = self
+            .conn
+            .prepare("SELECT repo_url, head_sha, context FROM parsed_repositories")?;
+        let parsed = stmt
+            .query_map([], |row| {
+                let url: String = row.get(0)?;
+                Ok((
+                    url.clone(),
+                    ParsedRepository {
+                        head_sha: row.get(1)?,
+                        context: row.get(2)?,
+                        files: files.remove(&url).unwrap_or_default(),
+                    },
+                ))
+            })?
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        Ok(parsed)
+    }
+
+    /// Replaces the stored parses of these repositories.
+    pub fn save_parsed_repositories(&self, parsed: &[(String, ParsedRepository)]) -> Result<()> {
+        self.conn.execute_batch("SAVEPOINT save_parsed")?;
+        let result = parsed
+            .iter()
+            .try_for_each(|(url, parsed)| self.replace_parsed_repository(url, parsed));
+        match result {
+            Ok(_) => self.conn.execute_batch("RELEASE save_parsed")?,
+            Err(_) => self
+                .conn
+                .execute_batch("ROLLBACK TO save_parsed; RELEASE save_parsed")?,
+        }
+        result
+    }
 
+    fn replace_parsed_repository(&self, url: &str, parsed: &ParsedRepository) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM parsed_files WHERE repo_url = ?1", params![url])?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO parsed_repositories (repo_url, head_sha, context)
+             VALUES (?1, ?2, ?3)",
+            params![url, parsed.head_sha, parsed.context],
+        )?;
+        for file in &parsed.files {
+            self.conn.execute(
+                "INSERT INTO parsed_files
+                 (repo_url, platform, path, blob_sha, dependencies_json, pipelines_json)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    url,
+                    file.platform,
+                    file.path.to_string_lossy(),
+                    file.blob_sha,
+                    serde_json::to_string(&file.dependencies)?,
+                    serde_json::to_string(&file.pipelines)?
+                ],
+            )?;
+        }
         Ok(())
     }
 
-    pub fn prepare_statement(&self, sql: &str) -> Result<rusqlite::Statement> {
+    /// Starts a transaction that is never committed, so every change made
+    /// through this connection is rolled back when it closes.
+    pub fn begin_dry_run(&self) -> Result<()> {
+        self.conn.execute_batch("BEGIN")?;
+        Ok(())
+    }
+
+    pub fn prepare_statement(&self, sql: &str) -> Result<rusqlite::Statement<'_>> {
         Ok(self.conn.prepare(sql)?)
     }
 
@@ -261,10 +980,144 @@ impl Database {
         for task_result in task_iter {
             let task_str = task_result?;
             if task_str.to_lowercase() != "gitversion" {
-                tasks.push(SupportedTask::Default(task_str));
+                tasks.push(parse_task_name(&task_str)?);
             }
         }
 
         Ok(tasks)
     }
+
+    /// Records a campaign rolling `target` out to `repo_urls`, all pending.
+    pub fn create_campaign(
+        &self,
+        name: &str,
+        task: &SupportedTask,
+        target: &TaskValidState,
+        repo_urls: &[String],
+    ) -> Result<Campaign> {
+        if self.get_campaign(name)?.is_some() {
+            return Err(anyhow::anyhow!("Campaign '{}' already exists", name));
+        }
+        self.conn.execute(
+            "INSERT INTO campaigns (name, task, target_state_json, status) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                name,
+                task.to_string(),
+                Self::serialize_state(target)?,
+                CampaignStatus::Active.as_str()
+            ],
+        )?;
+        let id = self.conn.last_insert_rowid();
+        for url in repo_urls {
+            self.conn.execute(
+                "INSERT OR IGNORE INTO campaign_repos (campaign_id, repo_url, status) VALUES (?1, ?2, ?3)",
+                params![id, url, RepoStatus::Pending.as_str()],
+            )?;
+        }
+
+        self.get_campaign(name)?
+            .ok_or_else(|| anyhow::anyhow!("Campaign '{}' was not stored", name))
+    }
+
+    pub fn get_campaign(&self, name: &str) -> Result<Option<Campaign>> {
+        let result = self.conn.query_row(
+            "SELECT id, name, task, target_state_json, status, created_at FROM campaigns WHERE name = ?1",
+            params![name],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, String>(5)?,
+                ))
+            },
+        );
+
+        let (id, name, task, target_json, status, created_at) = match result {
+            Ok(row) => row,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(Campaign {
+            id,
+            name,
+            task: parse_task_name(&task)?,
+            target: Self::deserialize_state(&target_json)?,
+            status: status.parse()?,
+            created_at,
+        }))
+    }
+
+    pub fn list_campaigns(&self) -> Result<Vec<Campaign>> {
+        let mut stmt = self.prepare_statement("SELECT name FROM campaigns ORDER BY id")?;
+        let names = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+
+        let mut campaigns = Vec::new();
+        for name in names {
+            campaigns.extend(self.get_campaign(&name)?);
+        }
+        Ok(campaigns)
+    }
+
+    pub fn set_campaign_status(&self, campaign: &Campaign, status: CampaignStatus) -> Result<()> {
+        self.conn.execute(
+            "UPDATE campaigns SET status = ?1 WHERE id = ?2",
+            params![status.as_str(), campaign.id],
+        )?;
+        Ok(())
+    }
+
+    pub fn campaign_repos(&self, campaign: &Campaign) -> Result<Vec<CampaignRepo>> {
+        let mut stmt = self.prepare_statement(
+            "SELECT repo_url, status, detail, updated_at FROM campaign_repos
+             WHERE campaign_id = ?1 ORDER BY repo_url",
+        )?;
+        let rows = stmt
+            .query_map(params![campaign.id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(|(repo_url, status, detail, updated_at)| {
+                Ok(CampaignRepo {
+                    repo_url,
+                    status: status.parse()?,
+                    detail,
+                    updated_at,
+                })
+            })
+            .collect()
+    }
+
+    pub fn set_campaign_repo_status(
+        &self,
+        campaign: &Campaign,
+        repo_url: &str,
+        status: RepoStatus,
+        detail: &str,
+    ) -> Result<()> {
+        let rows_affected = self.conn.execute(
+            "UPDATE campaign_repos SET status = ?1, detail = ?2, updated_at = CURRENT_TIMESTAMP
+             WHERE campaign_id = ?3 AND repo_url = ?4",
+            params![status.as_str(), detail, campaign.id, repo_url],
+        )?;
+        if rows_affected == 0 {
+            return Err(anyhow::anyhow!(
+                "{} is not part of campaign '{}'",
+                repo_url,
+                campaign.name
+            ));
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,132 @@
+//! Finding repositories through a host's API and syncing them into the
+//! `repositories` table.
+
+use crate::repo_id::RepoId;
+use regex::Regex;
+
+/// A repository listed by a host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredRepo {
+    /// The name filters match against, including the project or owner, e.g. `Platform/api`.
+    pub name: String,
+    pub clone_url: String,
+    /// Why the repository cannot be scanned, such as being disabled or empty.
+    pub unusable: Option<String>,
+    pub archived: bool,
+    pub fork: bool,
+    /// `public`, `private` or `internal`, where the host reports it.
+    pub visibility: Option<String>,
+    pub topics: Vec<String>,
+}
+
+/// Which discovered repositories to keep: include and exclude patterns on
+/// names, plus the host's own attributes. Archived repositories and forks are
+/// left out unless asked for.
+#[derive(Debug, Clone, Default)]
+pub struct RepoFilter {
+    pub include: Option<Regex>,
+    pub exclude: Option<Regex>,
+    pub include_archived: bool,
+    pub include_forks: bool,
+    /// Visibilities to keep; any when empty. Hosts that do not report one pass.
+    pub visibility: Vec<String>,
+    /// Topics of which a repository needs at least one; any when empty.
+    pub topics: Vec<String>,
+}
+
+impl RepoFilter {
+    pub fn new(include: Option<&str>, exclude: Option<&str>) -> anyhow::Result<Self> {
+        Ok(RepoFilter {
+            include: include.map(Regex::new).transpose()?,
+            exclude: exclude.map(Regex::new).transpose()?,
+            ..Default::default()
+        })
+    }
+
+    pub fn matches(&self, repo: &DiscoveredRepo) -> bool {
+        self.include
+            .as_ref()
+            .is_none_or(|re| re.is_match(&repo.name))
+            && !self
+                .exclude
+                .as_ref()
+                .is_some_and(|re| re.is_match(&repo.name))
+            && (self.include_archived || !repo.archived)
+            && (self.include_forks || !repo.fork)
+            && (self.visibility.is_empty()
+                || repo.visibility.as_ref().is_none_or(|visibility| {
+                    self.visibility
+                        .iter()
+                        .any(|wanted| wanted.eq_ignore_ascii_case(visibility))
+                }))
+            && (self.topics.is_empty()
+                || self
+                    .topics
+                    .iter()
+                    .any(|topic| repo.topics.iter().any(|t| t.eq_ignore_ascii_case(topic))))
+    }
+}
+
+/// How the `repositories` table changes to match a discovery.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiscoverySync {
+    pub added: Vec<String>,
+    /// Stored repositories in the discovered scope that the host no longer lists.
+    /// Ones the filter leaves out are kept.
+    pub removed: Vec<String>,
+    pub skipped: Vec<(String, String)>,
+}
+
+/// Works out the repositories to add and remove. `in_scope` tells which of the
+/// `existing` repositories the discovery covers; the rest are left alone.
+pub fn plan_sync(
+    discovered: &[DiscoveredRepo],
+    filter: &RepoFilter,
+    existing: &[String],
+    in_scope: impl Fn(&str) -> bool,
+) -> DiscoverySync {
+    let mut sync = DiscoverySync::default();
+
+    for repo in discovered {
+        if !filter.matches(repo) {
+            continue;
+        }
+        if let Some(reason) = &repo.unusable {
+            sync.skipped.push((repo.name.clone(), reason.clone()));
+            continue;
+        }
+        let url = without_userinfo(&repo.clone_url);
+        if !existing.iter().any(|stored| same_repository(stored, &url)) {
+            sync.added.push(url);
+        }
+    }
+
+    sync.removed = existing
+        .iter()
+        .filter(|stored| in_scope(stored))
+        .filter(|stored| {
+            !discovered
+                .iter()
+                .any(|repo| same_repository(stored, &without_userinfo(&repo.clone_url)))
+        })
+        .cloned()
+        .collect();
+    sync
+}
+
+/// Whether two clone URLs name the same repository, ignoring any user in them.
+fn same_repository(a: &str, b: &str) -> bool {
+    RepoId::parse(a).same_as(&RepoId::parse(b))
+}
+
+/// The URL without a `user@` before the host, which hosts add to clone URLs.
+pub fn without_userinfo(url: &str) -> String {
+    match url.split_once("://") {
+        Some((scheme, rest)) => {
+            let authority_end = rest.find('/').unwrap_or(rest.len());
+            let host_start = rest[..authority_end].rfind('@').map_or(0, |i| i + 1);
+            format!("{}://{}", scheme, &rest[host_start..])
+        }
+        None => url.to_string(),
+    }
+}
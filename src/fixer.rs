@@ -0,0 +1,466 @@
+//! Rewrites out-of-standard versions in the local checkouts. Only the version
+//! token is replaced, so comments, indentation and quoting are left untouched.
+
+use crate::pipeline::Location;
+use crate::{
+    gitversion, parse_task_name, platform, Database, SupportedTask, TaskImplementation, TaskIssues,
+    TaskValidState,
+};
+use anyhow::Result;
+use itertools::Itertools;
+use similar::TextDiff;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Branch fixes are pushed to unless `--branch-template` says otherwise.
+pub const DEFAULT_BRANCH_TEMPLATE: &str = "gavin/update-task-versions-{date}";
+
+/// One value to bring up to standard: a task version, or one of its inputs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fix {
+    pub task_name: String,
+    pub repo_name: String,
+    /// The file the value is written in.
+    pub path: PathBuf,
+    pub location: Location,
+    pub current: String,
+    pub target: String,
+    /// The input to rewrite, such as `versionSpec`, instead of the task version.
+    pub input: Option<String>,
+}
+
+impl Fix {
+    pub(crate) fn version(
+        task_name: &str,
+        implementation: &TaskImplementation,
+        target: &str,
+    ) -> Self {
+        Fix {
+            task_name: task_name.to_string(),
+            repo_name: implementation.repo_name.clone(),
+            path: implementation.source_path.clone(),
+            location: implementation.location,
+            current: implementation.version.clone(),
+            target: target.to_string(),
+            input: None,
+        }
+    }
+
+    pub(crate) fn input(
+        task_name: &str,
+        implementation: &TaskImplementation,
+        input: &str,
+        target: &str,
+    ) -> Self {
+        Fix {
+            task_name: task_name.to_string(),
+            repo_name: implementation.repo_name.clone(),
+            path: implementation.source_path.clone(),
+            location: implementation
+                .input_locations
+                .get(input)
+                .copied()
+                .unwrap_or_default(),
+            current: implementation
+                .inputs
+                .get(input)
+                .cloned()
+                .unwrap_or_default(),
+            target: target.to_string(),
+            input: Some(input.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for Fix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.input {
+            Some(input) => write!(
+                f,
+                "{} {}: {} → {}",
+                self.task_name, input, self.current, self.target
+            ),
+            None => write!(f, "{} @{} → @{}", self.task_name, self.current, self.target),
+        }
+    }
+}
+
+/// A replacement of `old` at a byte offset.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Edit {
+    pub offset: usize,
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Debug, Default)]
+pub struct FixPlan {
+    pub fixes: Vec<Fix>,
+    /// Edits per file, without duplicates from templates shared by several pipelines.
+    pub edits: BTreeMap<PathBuf, Vec<Edit>>,
+    /// Fixes that cannot be applied, with the reason.
+    pub skipped: Vec<(Fix, String)>,
+}
+
+/// Works out the fixes for every invalid implementation that has a valid state to move to.
+pub fn plan(issues: &TaskIssues, db: &Database) -> Result<FixPlan> {
+    let mut fixes = Vec::new();
+
+    for task_name in issues.invalid_states.keys().sorted() {
+        let platform = platform::for_task(task_name);
+        if let Some(grouped) = platform.grouped_fixes(task_name, issues, db)? {
+            fixes.extend(grouped);
+            continue;
+        }
+
+        let states = db.list_valid_states(&parse_task_name(task_name)?)?;
+        let Some(target) = platform.target_version(&states) else {
+            continue;
+        };
+
+        let repos = &issues.invalid_states[task_name];
+        for repo_name in repos.keys().sorted() {
+            fixes.extend(
+                repos[repo_name]
+                    .iter()
+                    .filter(|implementation| implementation.version != target)
+                    .map(|implementation| Fix::version(task_name, implementation, &target)),
+            );
+        }
+    }
+
+    Ok(plan_edits(fixes))
+}
+
+/// Works out the fixes moving every implementation of `task` to `target`,
+/// whether or not its current version is valid. Campaigns use this to roll out
+/// one chosen state.
+pub fn plan_to(issues: &TaskIssues, task: &SupportedTask, target: &TaskValidState) -> FixPlan {
+    let fixes = match target {
+        TaskValidState::Gitversion(state) => gitversion::fixes_to(issues, state, |_| true),
+        _ => {
+            let task_name = task.to_string();
+            let platform = platform::for_task(&task_name);
+            let Some(version) = platform.target_version(std::slice::from_ref(target)) else {
+                return FixPlan::default();
+            };
+            task_implementations(issues, task)
+                .filter(|implementation| !platform.version_matches(target, &implementation.version))
+                .map(|implementation| Fix::version(&task_name, implementation, &version))
+                .collect()
+        }
+    };
+    plan_edits(fixes)
+}
+
+/// The repositories that use `task` at all.
+pub fn task_repos(issues: &TaskIssues, task: &SupportedTask) -> BTreeSet<String> {
+    task_implementations(issues, task)
+        .map(|implementation| implementation.repo_name.clone())
+        .collect()
+}
+
+fn task_implementations<'a>(
+    issues: &'a TaskIssues,
+    task: &SupportedTask,
+) -> impl Iterator<Item = &'a TaskImplementation> {
+    let names = match task {
+        SupportedTask::Gitversion => vec![
+            gitversion::SETUP_TASK.to_string(),
+            gitversion::EXECUTE_TASK.to_string(),
+        ],
+        task => vec![task.to_string()],
+    };
+    issues
+        .all_implementations
+        .iter()
+        .filter(move |(name, _)| names.iter().any(|n| n.eq_ignore_ascii_case(name)))
+        .flat_map(|(_, implementations)| implementations)
+}
+
+/// Locates each fix in its file and turns it into an edit.
+pub fn plan_edits(fixes: Vec<Fix>) -> FixPlan {
+    let mut plan = FixPlan::default();
+    let mut contents: HashMap<PathBuf, Option<String>> = HashMap::new();
+
+    for fix in fixes {
+        let content = contents
+            .entry(fix.path.clone())
+            .or_insert_with(|| std::fs::read_to_string(&fix.path).ok());
+        let Some(content) = content else {
+            plan.skipped
+                .push((fix, "file could not be read".to_string()));
+            continue;
+        };
+
+        let edit = match &fix.input {
+            Some(_) => value_edit(content, fix.location, &fix.current, &fix.target),
+            None => version_edit(content, fix.location, &fix.current, &fix.target),
+        };
+        let edit = match edit {
+            Ok(edit) => edit,
+            Err(reason) => {
+                plan.skipped.push((fix, reason));
+                continue;
+            }
+        };
+
+        let edits = plan.edits.entry(fix.path.clone()).or_default();
+        match edits.iter().find(|existing| existing.offset == edit.offset) {
+            Some(existing) if *existing == edit => {}
+            Some(existing) => {
+                let reason = format!("conflicts with a fix to {}", existing.new);
+                plan.skipped.push((fix, reason));
+                continue;
+            }
+            None => edits.push(edit),
+        }
+        plan.fixes.push(fix);
+    }
+
+    plan
+}
+
+/// Writes the planned edits and returns the files that changed.
+pub fn apply(plan: &FixPlan) -> Result<Vec<PathBuf>> {
+    let mut written = Vec::new();
+    for (path, edits) in &plan.edits {
+        let content = std::fs::read_to_string(path)?;
+        let updated = apply_edits(&content, edits)?;
+        if updated != content {
+            std::fs::write(path, updated)?;
+            written.push(path.clone());
+        }
+    }
+    Ok(written)
+}
+
+/// The planned edits as unified diffs, one patch per repository. `repo_paths`
+/// maps repository names to their checkouts; a file belongs to the repository
+/// whose checkout contains it, and its diff uses the path relative to it.
+pub fn patches(
+    plan: &FixPlan,
+    repo_paths: &BTreeMap<String, PathBuf>,
+) -> Result<BTreeMap<String, String>> {
+    let mut patches: BTreeMap<String, String> = BTreeMap::new();
+    for (path, edits) in &plan.edits {
+        let content = std::fs::read_to_string(path)?;
+        let updated = apply_edits(&content, edits)?;
+
+        let (repo, relative) = match owning_repo(path, repo_paths) {
+            Some((repo, relative)) => (repo.clone(), relative),
+            None => match plan.fixes.iter().find(|fix| &fix.path == path) {
+                Some(fix) => (fix.repo_name.clone(), path.as_path()),
+                None => continue,
+            },
+        };
+        patches
+            .entry(repo)
+            .or_default()
+            .push_str(&unified_diff(relative, &content, &updated));
+    }
+    Ok(patches)
+}
+
+/// The repository whose checkout contains `path`, and the path relative to it.
+fn owning_repo<'a>(
+    path: &'a Path,
+    repo_paths: &'a BTreeMap<String, PathBuf>,
+) -> Option<(&'a String, &'a Path)> {
+    repo_paths
+        .iter()
+        .find_map(|(repo, root)| Some((repo, path.strip_prefix(root).ok()?)))
+}
+
+/// Groups written files by the repository that holds them.
+pub fn files_by_repo(
+    files: &[PathBuf],
+    repo_paths: &BTreeMap<String, PathBuf>,
+) -> BTreeMap<String, Vec<PathBuf>> {
+    let mut by_repo: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for file in files {
+        if let Some((repo, _)) = owning_repo(file, repo_paths) {
+            by_repo.entry(repo.clone()).or_default().push(file.clone());
+        }
+    }
+    by_repo
+}
+
+/// Fills `{repo}` and `{date}` in a branch template, e.g.
+/// `gavin/update-task-versions-{date}` becomes `gavin/update-task-versions-20240131`.
+/// `{repo}` is the repository name without its namespace.
+pub fn branch_name(template: &str, repo: &str) -> String {
+    let date = chrono::Local::now().format("%Y%m%d").to_string();
+    let name = repo.rsplit('/').next().unwrap_or(repo);
+    template
+        .replace("{repo}", name)
+        .replace("{date}", &date)
+        .split_whitespace()
+        .join("-")
+}
+
+/// The fixed part of a branch template, which every gavin branch starts with.
+pub fn branch_prefix(template: &str) -> &str {
+    template.split('{').next().unwrap_or_default()
+}
+
+pub fn pull_request_title(fixes: &[&Fix]) -> String {
+    let tasks = fixes.iter().map(|fix| &fix.task_name).unique().count();
+    match tasks {
+        1 => format!("Update {} to the standard version", fixes[0].task_name),
+        n => format!("Update {} pipeline tasks to standard versions", n),
+    }
+}
+
+/// A markdown description listing every fix with the file it is in.
+pub fn pull_request_description(fixes: &[&Fix], repo_path: &Path) -> String {
+    let lines = fixes
+        .iter()
+        .map(|fix| {
+            let file = fix.path.strip_prefix(repo_path).unwrap_or(&fix.path);
+            format!("- `{}` in `{}`", fix, file.display())
+        })
+        .unique()
+        .join("\n");
+    format!(
+        "gavin found pipeline tasks that are not on a valid state and updated them:\n\n{}\n",
+        lines
+    )
+}
+
+/// A commit message listing each upgraded task once.
+pub fn commit_message(fixes: &[&Fix]) -> String {
+    let lines: Vec<String> = fixes
+        .iter()
+        .map(|fix| format!("- {}", fix))
+        .sorted()
+        .dedup()
+        .collect();
+    format!(
+        "Update pipeline tasks to standard versions\n\n{}\n",
+        lines.join("\n")
+    )
+}
+
+/// A `git apply` compatible diff of one file, empty when nothing changed.
+pub fn unified_diff(path: &Path, old: &str, new: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+    let name = path
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy())
+        .join("/");
+    let diff = TextDiff::from_lines(old, new);
+    format!(
+        "diff --git a/{0} b/{0}\n{1}",
+        name,
+        diff.unified_diff()
+            .context_radius(3)
+            .header(&format!("a/{}", name), &format!("b/{}", name))
+    )
+}
+
+/// Applies edits to `content`, checking that each one still finds the text it replaces.
+pub fn apply_edits(content: &str, edits: &[Edit]) -> Result<String> {
+    let mut updated = content.to_string();
+    for edit in edits.iter().sorted().rev() {
+        let range = edit.offset..edit.offset + edit.old.len();
+        if updated.get(range.clone()) != Some(edit.old.as_str()) {
+            return Err(anyhow::anyhow!(
+                "Expected '{}' at byte {}, the file has changed",
+                edit.old,
+                edit.offset
+            ));
+        }
+        updated.replace_range(range, &edit.new);
+    }
+    Ok(updated)
+}
+
+/// An edit replacing the version at the end of the scalar at `location`, after
+/// its `@` or `:` (as in `PowerShell@2` or `node:20`), or the whole scalar.
+pub fn version_edit(
+    content: &str,
+    location: Location,
+    current: &str,
+    target: &str,
+) -> Result<Edit, String> {
+    let range = scalar_range(content, location)?;
+    let text = &content[range.clone()];
+    if current.is_empty() || !text.ends_with(current) {
+        return Err(format!(
+            "'{}' does not end with the version, it may come from a template parameter",
+            text
+        ));
+    }
+
+    let start = range.end - current.len();
+    let separated =
+        start == range.start || matches!(text.as_bytes()[start - range.start - 1], b'@' | b':');
+    if !separated {
+        return Err(format!("'{}' does not end with a version", text));
+    }
+
+    Ok(Edit {
+        offset: start,
+        old: current.to_string(),
+        new: target.to_string(),
+    })
+}
+
+/// An edit replacing the whole scalar at `location`, such as an input value.
+pub fn value_edit(
+    content: &str,
+    location: Location,
+    current: &str,
+    target: &str,
+) -> Result<Edit, String> {
+    if current.is_empty() {
+        return Err("there is no existing value to replace".to_string());
+    }
+    let range = scalar_range(content, location)?;
+    let text = &content[range.clone()];
+    if text != current {
+        return Err(format!(
+            "'{}' is not written literally, it may come from a template parameter",
+            current
+        ));
+    }
+
+    Ok(Edit {
+        offset: range.start,
+        old: current.to_string(),
+        new: target.to_string(),
+    })
+}
+
+/// The text of the single-line scalar at `location`, without quotes.
+fn scalar_range(content: &str, location: Location) -> Result<Range<usize>, String> {
+    let start = location.offset;
+    let rest = content
+        .get(start..)
+        .filter(|_| location.line > 0)
+        .ok_or("the value has no known location")?;
+    let line = rest.split(['\n', '\r']).next().unwrap_or_default();
+
+    let (quote_len, len) = match line.chars().next() {
+        Some(quote @ ('"' | '\'')) => {
+            let len = line[1..]
+                .find(quote)
+                .ok_or("the value spans several lines")?;
+            (1, len)
+        }
+        _ => {
+            let end = [" #", "\t#", ",", "]", "}"]
+                .iter()
+                .filter_map(|stop| line.find(stop))
+                .min()
+                .unwrap_or(line.len());
+            (0, line[..end].trim_end().len())
+        }
+    };
+
+    Ok(start + quote_len..start + quote_len + len)
+}
@@ -1,34 +1,182 @@
+use crate::discovery;
+use crate::host::HostType;
+use crate::platform;
+use crate::repo_id::{self, RepoId};
+use crate::secrets::Credentials;
+use crate::utils::{scrub_secrets, shell_quote};
+use crate::workspace::Workspace;
 use anyhow::Result;
-use std::path::PathBuf;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use tokio::process::Command;
 
+/// How local checkouts are brought up to date before they are read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncMode {
+    /// Clone missing repositories and pull existing ones.
+    Update,
+    /// Clone missing repositories but leave existing ones as they are.
+    NoUpdate,
+    /// Run no git commands, only report what would be cloned.
+    DryRun,
+}
+
+impl SyncMode {
+    pub fn from_flags(no_update: bool, dry_run: bool) -> Self {
+        match (no_update, dry_run) {
+            (_, true) => SyncMode::DryRun,
+            (true, false) => SyncMode::NoUpdate,
+            (false, false) => SyncMode::Update,
+        }
+    }
+}
+
 pub struct GitManager {
     repo_url: String,
     repo_dir: PathBuf,
+    /// The `Authorization` header git sends, kept out of the remote URL.
+    auth_header: Option<String>,
+    /// Values scrubbed from any git output shown to the user.
+    secrets: Vec<String>,
+    /// The private key for SSH URLs; the user's SSH setup is used without one.
+    ssh_key: Option<PathBuf>,
 }
 
 impl GitManager {
-    pub fn new(username: String, token: String, repo_url: &str) -> Self {
-        let repo_name = repo_url.split('/').last().unwrap_or("repo").to_string();
+    /// A manager for `repo_url`, authenticating the way its host expects.
+    pub fn new(
+        workspace: &Workspace,
+        username: String,
+        token: String,
+        repo_url: &str,
+        host: HostType,
+    ) -> Self {
+        let repo_dir = workspace.checkout_dir(repo_url);
+        let ssh = repo_id::is_ssh_url(repo_url);
+        // Git wants spaces in repository names escaped
+        let repo_url = &repo_url.replace(' ', "%20");
+
+        // Any credentials already in the URL are replaced by ours. SSH URLs
+        // keep their user, which names the account on the server.
+        let repo_url = if repo_url.starts_with("file://") || ssh {
+            repo_url.to_string()
+        } else {
+            discovery::without_userinfo(repo_url)
+        };
 
-        let repo_url = if repo_url.contains("@") {
-            let parts: Vec<&str> = repo_url.splitn(2, '@').collect();
-            format!("https://{}:{}@{}", username, token, parts[1])
+        let (auth_header, secrets) = if token.is_empty() || ssh {
+            (None, Vec::new())
         } else {
-            format!(
-                "https://{}:{}@{}",
-                username,
-                token,
-                repo_url.trim_start_matches("https://")
+            let basic = BASE64.encode(format!("{}:{}", host.git_username(&username), token));
+            (
+                Some(format!("Authorization: Basic {}", basic)),
+                vec![token, basic],
             )
         };
 
-        let repo_dir = std::env::current_dir()
-            .expect("Failed to get current directory")
-            .join("temp_repos")
-            .join(repo_name);
+        Self {
+            repo_url,
+            repo_dir,
+            auth_header,
+            secrets,
+            ssh_key: None,
+        }
+    }
+
+    /// A manager using the token or SSH key of `credentials`.
+    pub fn with_credentials(
+        workspace: &Workspace,
+        credentials: &Credentials,
+        repo_url: &str,
+        host: HostType,
+    ) -> Self {
+        Self {
+            ssh_key: credentials.ssh_key.clone(),
+            ..Self::new(
+                workspace,
+                credentials.username.clone(),
+                credentials.token.clone(),
+                repo_url,
+                host,
+            )
+        }
+    }
+
+    /// A git command in `dir` that authenticates with the header, passed
+    /// through the environment so it shows neither in the process list nor in
+    /// `.git/config`.
+    fn command(&self, dir: &Path) -> Command {
+        let mut command = Command::new("git");
+        command.current_dir(dir).env("GIT_TERMINAL_PROMPT", "0");
+        if let Some(header) = &self.auth_header {
+            let existing = std::env::var("GIT_CONFIG_COUNT").ok();
+            command.envs(auth_header_config(
+                &self.repo_url,
+                header,
+                existing.as_deref(),
+            ));
+        }
+        if let Some(key) = &self.ssh_key {
+            command.env(
+                "GIT_SSH_COMMAND",
+                format!(
+                    "ssh -i {} -o IdentitiesOnly=yes -o BatchMode=yes",
+                    shell_quote(&key.to_string_lossy())
+                ),
+            );
+        }
+        command
+    }
 
-        Self { repo_url, repo_dir }
+    /// Git's stderr with the token and any credentials in URLs masked.
+    fn stderr(&self, output: &std::process::Output) -> String {
+        scrub_secrets(&String::from_utf8_lossy(&output.stderr), &self.secrets)
+    }
+
+    /// Points `origin` at the URL without credentials, for checkouts cloned
+    /// when the token was written into it.
+    async fn clean_remote(&self) -> Result<()> {
+        let Ok(current) = self.git(&["remote", "get-url", "origin"]).await else {
+            return Ok(());
+        };
+        if current != self.repo_url {
+            self.git(&["remote", "set-url", "origin", &self.repo_url])
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Moves a checkout left in `legacy_dir` by an older layout to where this
+    /// repository's checkout now lives, provided its origin is this repository.
+    pub async fn adopt_legacy_checkout(&self, legacy_dir: &Path) -> Result<bool> {
+        if self.repo_dir.exists() || !legacy_dir.join(".git").exists() {
+            return Ok(false);
+        }
+
+        let output = self
+            .command(legacy_dir)
+            .args(["remote", "get-url", "origin"])
+            .output()
+            .await?;
+        let origin = String::from_utf8_lossy(&output.stdout);
+        if !output.status.success()
+            || !RepoId::parse(origin.trim()).same_as(&RepoId::parse(&self.repo_url))
+        {
+            return Ok(false);
+        }
+
+        if let Some(parent) = self.repo_dir.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::rename(legacy_dir, &self.repo_dir).await?;
+        println!(
+            "Moved checkout {} to {}",
+            legacy_dir.display(),
+            self.repo_dir.display()
+        );
+        Ok(true)
     }
 
     pub async fn test_connection(&self) -> Result<()> {
@@ -40,7 +188,8 @@ impl GitManager {
 
         println!("Testing Git connection for {}...", repo_name);
 
-        let output = Command::new("git")
+        let output = self
+            .command(&std::env::current_dir()?)
             .arg("ls-remote")
             .arg("--heads")
             .arg(&self.repo_url)
@@ -48,7 +197,7 @@ impl GitManager {
             .await?;
 
         if !output.status.success() {
-            let error = String::from_utf8_lossy(&output.stderr);
+            let error = self.stderr(&output);
             println!("✗ Failed to connect to repository {}", repo_name);
             println!("Error: {}", error);
             return Err(anyhow::anyhow!(
@@ -89,20 +238,16 @@ impl GitManager {
         tokio::fs::create_dir_all(&self.repo_dir).await?;
 
         // Initialize empty repo
-        let output = Command::new("git")
-            .args(["init"])
-            .current_dir(&self.repo_dir)
-            .output()
-            .await?;
+        let output = self.command(&self.repo_dir).args(["init"]).output().await?;
 
         if !output.status.success() {
             return Err(anyhow::anyhow!("Failed to initialize repository"));
         }
 
         // Configure sparse checkout
-        let output = Command::new("git")
+        let output = self
+            .command(&self.repo_dir)
             .args(["config", "core.sparseCheckout", "true"])
-            .current_dir(&self.repo_dir)
             .output()
             .await?;
 
@@ -111,17 +256,7 @@ impl GitManager {
         }
 
         // Create sparse-checkout file with pipeline patterns
-        let sparse_patterns = [
-            "*.yml",
-            "*.yaml",
-            "**/azure-pipelines.yml",
-            "**/azure-pipelines.yaml",
-            "**/*.pipeline.yml",
-            "**/*.pipeline.yaml",
-            ".github/workflows/*.yml",
-            ".github/workflows/*.yaml",
-            ".gitlab-ci.yml",
-        ];
+        let sparse_patterns = platform::sparse_patterns();
 
         let sparse_checkout_dir = self.repo_dir.join(".git").join("info");
         tokio::fs::create_dir_all(&sparse_checkout_dir).await?;
@@ -130,9 +265,9 @@ impl GitManager {
         tokio::fs::write(&sparse_checkout_file, sparse_patterns.join("\n")).await?;
 
         // Add remote
-        let output = Command::new("git")
+        let output = self
+            .command(&self.repo_dir)
             .args(["remote", "add", "origin", &self.repo_url])
-            .current_dir(&self.repo_dir)
             .output()
             .await?;
 
@@ -141,11 +276,11 @@ impl GitManager {
         }
 
         // Try branches in order: develop, main, master
-        let default_branch = match Self::try_fetch_branch(&self.repo_dir, "develop").await {
+        let default_branch = match self.try_fetch_branch("develop").await {
             Ok(branch) => branch,
-            Err(_) => match Self::try_fetch_branch(&self.repo_dir, "main").await {
+            Err(_) => match self.try_fetch_branch("main").await {
                 Ok(branch) => branch,
-                Err(_) => match Self::try_fetch_branch(&self.repo_dir, "master").await {
+                Err(_) => match self.try_fetch_branch("master").await {
                     Ok(branch) => branch,
                     Err(_) => {
                         return Err(anyhow::anyhow!(
@@ -157,14 +292,13 @@ impl GitManager {
         };
 
         // Create and checkout the branch properly
-        Command::new("git")
+        self.command(&self.repo_dir)
             .args([
                 "checkout",
                 "-b",
                 &default_branch,
                 &format!("origin/{}", default_branch),
             ])
-            .current_dir(&self.repo_dir)
             .output()
             .await?;
 
@@ -176,10 +310,10 @@ impl GitManager {
     }
 
     // Helper function to try fetching a specific branch
-    async fn try_fetch_branch(repo_dir: &PathBuf, branch_name: &str) -> Result<String> {
-        let output = Command::new("git")
+    async fn try_fetch_branch(&self, branch_name: &str) -> Result<String> {
+        let output = self
+            .command(&self.repo_dir)
             .args(["fetch", "--depth=1", "origin", branch_name])
-            .current_dir(repo_dir)
             .output()
             .await?;
 
@@ -198,11 +332,12 @@ impl GitManager {
             .unwrap_or("unknown");
 
         println!("Repository {} exists, updating...", repo_name);
+        self.clean_remote().await?;
 
         // Get current branch name
-        let branch_output = Command::new("git")
+        let branch_output = self
+            .command(&self.repo_dir)
             .args(["rev-parse", "--abbrev-ref", "HEAD"])
-            .current_dir(&self.repo_dir)
             .output()
             .await?;
 
@@ -212,11 +347,11 @@ impl GitManager {
 
         // If we're in detached HEAD state, try branches in order
         if current_branch == "HEAD" {
-            let checkout_result = match Self::try_checkout_branch(&self.repo_dir, "develop").await {
+            let checkout_result = match self.try_checkout_branch("develop").await {
                 Ok(_) => Ok(()),
-                Err(_) => match Self::try_checkout_branch(&self.repo_dir, "main").await {
+                Err(_) => match self.try_checkout_branch("main").await {
                     Ok(_) => Ok(()),
-                    Err(_) => Self::try_checkout_branch(&self.repo_dir, "master").await,
+                    Err(_) => self.try_checkout_branch("master").await,
                 },
             };
 
@@ -229,9 +364,9 @@ impl GitManager {
         }
 
         // Ensure sparse checkout is enabled
-        let output = Command::new("git")
+        let output = self
+            .command(&self.repo_dir)
             .args(["config", "core.sparseCheckout", "true"])
-            .current_dir(&self.repo_dir)
             .output()
             .await?;
 
@@ -240,26 +375,16 @@ impl GitManager {
         }
 
         // Update sparse-checkout patterns if needed
-        let sparse_patterns = [
-            "*.yml",
-            "*.yaml",
-            "**/azure-pipelines.yml",
-            "**/azure-pipelines.yaml",
-            "**/*.pipeline.yml",
-            "**/*.pipeline.yaml",
-            ".github/workflows/*.yml",
-            ".github/workflows/*.yaml",
-            ".gitlab-ci.yml",
-        ];
+        let sparse_patterns = platform::sparse_patterns();
 
         let sparse_checkout_dir = self.repo_dir.join(".git").join("info");
         let sparse_checkout_file = sparse_checkout_dir.join("sparse-checkout");
         tokio::fs::write(&sparse_checkout_file, sparse_patterns.join("\n")).await?;
 
         // Reset any local changes
-        let reset_output = Command::new("git")
+        let reset_output = self
+            .command(&self.repo_dir)
             .args(["reset", "--hard", "HEAD"])
-            .current_dir(&self.repo_dir)
             .output()
             .await?;
 
@@ -269,14 +394,14 @@ impl GitManager {
         }
 
         // Pull latest changes
-        let pull_output = Command::new("git")
+        let pull_output = self
+            .command(&self.repo_dir)
             .args(["pull", "--force"])
-            .current_dir(&self.repo_dir)
             .output()
             .await?;
 
         if !pull_output.status.success() {
-            let error = String::from_utf8_lossy(&pull_output.stderr);
+            let error = self.stderr(&pull_output);
             println!("✗ Failed to update repository {}", repo_name);
             println!("Error: {}", error);
             return Err(anyhow::anyhow!("Failed to update repository {}", repo_name));
@@ -287,10 +412,10 @@ impl GitManager {
     }
 
     // Helper function to try checking out a specific branch
-    async fn try_checkout_branch(repo_dir: &PathBuf, branch_name: &str) -> Result<()> {
-        let output = Command::new("git")
+    async fn try_checkout_branch(&self, branch_name: &str) -> Result<()> {
+        let output = self
+            .command(&self.repo_dir)
             .args(["checkout", branch_name])
-            .current_dir(repo_dir)
             .output()
             .await?;
 
@@ -301,11 +426,194 @@ impl GitManager {
         }
     }
 
+    /// Commits `files` on `branch`, starting from the checked-out branch, and
+    /// pushes it to `origin`. The checkout is switched back afterwards so later
+    /// updates pull the base branch. Only the new commit is pushed, so the
+    /// shallow, sparse clones from [`GitManager::ensure_repo_exists`] are enough.
+    pub async fn commit_and_push(
+        &self,
+        branch: &str,
+        files: &[PathBuf],
+        message: &str,
+    ) -> Result<()> {
+        self.clean_remote().await?;
+        let mut base = self.git(&["rev-parse", "--abbrev-ref", "HEAD"]).await?;
+        if base == "HEAD" {
+            // Detached, come back to the same commit
+            base = self.git(&["rev-parse", "HEAD"]).await?;
+        }
+
+        self.git(&["checkout", "-B", branch]).await?;
+        let result = self.commit_and_push_files(branch, files, message).await;
+        self.git(&["checkout", &base]).await?;
+        result
+    }
+
+    async fn commit_and_push_files(
+        &self,
+        branch: &str,
+        files: &[PathBuf],
+        message: &str,
+    ) -> Result<()> {
+        let relative: Vec<String> = files
+            .iter()
+            .map(|file| {
+                file.strip_prefix(&self.repo_dir)
+                    .unwrap_or(file)
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        let mut add = vec!["add", "--"];
+        add.extend(relative.iter().map(String::as_str));
+        self.git(&add).await?;
+
+        // Fall back to a gavin identity on machines without one configured
+        let mut commit = vec!["commit", "-m", message];
+        if self.git(&["config", "user.email"]).await.is_err() {
+            commit.splice(
+                0..0,
+                ["-c", "user.name=gavin", "-c", "user.email=gavin@localhost"],
+            );
+        }
+        self.git(&commit).await?;
+
+        // The branch belongs to gavin and is rebuilt from the base on every run
+        let refspec = format!("HEAD:refs/heads/{}", branch);
+        self.git(&["push", "--force", "origin", &refspec]).await?;
+        Ok(())
+    }
+
+    /// Runs git in the checkout and returns its trimmed output.
+    async fn git(&self, args: &[&str]) -> Result<String> {
+        let output = self.command(&self.repo_dir).args(args).output().await?;
+
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "git {} failed in {}: {}",
+                args.iter()
+                    .find(|arg| !arg.starts_with('-') && !arg.contains('='))
+                    .unwrap_or(&"command"),
+                self.repo_dir.display(),
+                self.stderr(&output).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    // Used with --dry-run, where no git commands are run
+    pub async fn ensure_repo_exists_dry_run(&self, is_new: bool) -> Result<()> {
+        let repo_name = self
+            .repo_dir
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("unknown");
+
+        if is_new || !self.repo_dir.exists() {
+            println!(
+                "Would clone repository {} with sparse checkout into {}",
+                repo_name,
+                self.repo_dir.display()
+            );
+        }
+        Ok(())
+    }
+
     pub async fn ensure_repo_exists_no_update(&self) -> Result<()> {
         if self.repo_dir.exists() {
-            Ok(()) // Skip update, just verify it exists
+            self.clean_remote().await // Skip update, only drop old credentials
         } else {
             self.clone_repo().await
         }
     }
 }
+
+/// The `GIT_CONFIG_*` variables that send `header` only to the host of
+/// `repo_url`, numbered after the `existing` count the user already set.
+pub fn auth_header_config(
+    repo_url: &str,
+    header: &str,
+    existing: Option<&str>,
+) -> Vec<(String, String)> {
+    let index: usize = existing.and_then(|count| count.parse().ok()).unwrap_or(0);
+    let base_url = match repo_url.split_once("://") {
+        Some((scheme, rest)) => format!("{}://{}/", scheme, rest.split('/').next().unwrap_or(rest)),
+        None => repo_url.to_string(),
+    };
+    vec![
+        ("GIT_CONFIG_COUNT".to_string(), (index + 1).to_string()),
+        (
+            format!("GIT_CONFIG_KEY_{}", index),
+            format!("http.{}.extraHeader", base_url),
+        ),
+        (format!("GIT_CONFIG_VALUE_{}", index), header.to_string()),
+    ]
+}
+
+/// The commit checked out in `repo_dir`, or `None` if it is not a checkout.
+pub async fn head_commit(repo_dir: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .current_dir(repo_dir)
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .await
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The blob id git stores for every file in the index of `repo_dir`, keyed by
+/// path under `repo_dir`.
+pub async fn index_blobs(repo_dir: &Path) -> Option<HashMap<PathBuf, String>> {
+    let output = git_stdout(repo_dir, &["ls-files", "--stage", "-z"]).await?;
+    Some(
+        output
+            .split('\0')
+            .filter_map(|entry| {
+                // <mode> <blob> <stage>\t<path>
+                let (info, path) = entry.split_once('\t')?;
+                let blob = info.split(' ').nth(1)?;
+                Some((repo_dir.join(path), blob.to_string()))
+            })
+            .collect(),
+    )
+}
+
+/// Files whose content differs from HEAD or from the index, untracked files
+/// included, keyed by path under `repo_dir`.
+pub async fn changed_files(repo_dir: &Path) -> Option<Vec<PathBuf>> {
+    let output = git_stdout(
+        repo_dir,
+        &["status", "--porcelain", "-z", "--untracked-files=all"],
+    )
+    .await?;
+    let mut files = Vec::new();
+    let mut entries = output.split('\0');
+    while let Some(entry) = entries.next() {
+        // XY <path>
+        let (Some(status), Some(path)) = (entry.get(..2), entry.get(3..)) else {
+            continue;
+        };
+        // Renames and copies are followed by the path they came from
+        if status.contains(['R', 'C']) {
+            entries.next();
+        }
+        files.push(repo_dir.join(path));
+    }
+    Some(files)
+}
+
+async fn git_stdout(repo_dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .current_dir(repo_dir)
+        .args(args)
+        .output()
+        .await
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
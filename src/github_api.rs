@@ -0,0 +1,312 @@
+//! Pull requests through the GitHub REST API, on github.com or GitHub Enterprise.
+
+use crate::discovery::DiscoveredRepo;
+use crate::pull_request::{
+    is_gavin_branch, url_host, NewPullRequest, PullRequest, PullRequestOutcome,
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_API_URL: &str = "https://api.github.com";
+
+/// The owner and name of a repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GithubRepo {
+    pub owner: String,
+    pub name: String,
+}
+
+impl GithubRepo {
+    /// Parses `https://<clone_host>/owner/name[.git]`.
+    pub fn parse(url: &str, clone_host: &str) -> Option<Self> {
+        if url_host(url) != clone_host {
+            return None;
+        }
+        let path = url.split_once("://")?.1.split_once('/')?.1;
+        match path.trim_end_matches('/').split('/').collect::<Vec<_>>()[..] {
+            [owner, name] => Some(GithubRepo {
+                owner: owner.to_string(),
+                name: name.trim_end_matches(".git").to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RepositoryResponse {
+    default_branch: String,
+}
+
+#[derive(Deserialize)]
+struct RepositoryListing {
+    full_name: String,
+    html_url: String,
+    #[serde(default)]
+    archived: bool,
+    #[serde(default)]
+    disabled: bool,
+    #[serde(default)]
+    fork: bool,
+    #[serde(default)]
+    size: u64,
+    visibility: Option<String>,
+    #[serde(default)]
+    topics: Vec<String>,
+}
+
+/// Repositories per page when listing, the most GitHub allows.
+const PAGE_SIZE: usize = 100;
+
+#[derive(Deserialize)]
+struct PullRequestResponse {
+    number: u64,
+    html_url: String,
+    head: BranchRef,
+}
+
+#[derive(Deserialize)]
+struct BranchRef {
+    #[serde(rename = "ref")]
+    name: String,
+    /// The repository the branch is in, `None` once a fork is deleted.
+    repo: Option<BranchRepository>,
+}
+
+#[derive(Deserialize)]
+struct BranchRepository {
+    full_name: String,
+}
+
+#[derive(Serialize)]
+struct CreatePullRequest<'a> {
+    title: &'a str,
+    head: &'a str,
+    base: &'a str,
+    body: &'a str,
+}
+
+#[derive(Serialize)]
+struct RequestReviewers<'a> {
+    reviewers: &'a [String],
+}
+
+pub struct GithubClient {
+    http: reqwest::Client,
+    base_url: String,
+    token: String,
+}
+
+impl GithubClient {
+    /// A client for the API at `base_url`, such as `https://ghe.example.com/api/v3`.
+    pub fn new(base_url: &str, token: &str) -> Self {
+        GithubClient {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+        }
+    }
+
+    /// The host repositories served by this API are cloned from.
+    pub fn clone_host(&self) -> &str {
+        match url_host(&self.base_url) {
+            "api.github.com" => "github.com",
+            host => host,
+        }
+    }
+
+    fn repository_url(&self, repo: &GithubRepo) -> String {
+        format!("{}/repos/{}/{}", self.base_url, repo.owner, repo.name)
+    }
+
+    async fn send<T: serde::de::DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<T> {
+        let response = request
+            .bearer_auth(&self.token)
+            .header("Accept", "application/vnd.github+json")
+            .header("User-Agent", "gavin")
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("GitHub returned {}: {}", status, body));
+        }
+        response
+            .json()
+            .await
+            .context("Unexpected response from GitHub")
+    }
+
+    /// Every repository in the organization, named `owner/name`.
+    pub async fn list_org_repositories(&self, org: &str) -> Result<Vec<DiscoveredRepo>> {
+        let url = format!("{}/orgs/{}/repos", self.base_url, org);
+        let mut listings = Vec::new();
+        for page in 1.. {
+            let batch: Vec<RepositoryListing> = self
+                .send(self.http.get(&url).query(&[
+                    ("type", "all".to_string()),
+                    ("per_page", PAGE_SIZE.to_string()),
+                    ("page", page.to_string()),
+                ]))
+                .await?;
+            let last = batch.len() < PAGE_SIZE;
+            listings.extend(batch);
+            if last {
+                break;
+            }
+        }
+
+        Ok(listings
+            .into_iter()
+            .map(|repo| DiscoveredRepo {
+                name: repo.full_name,
+                clone_url: repo.html_url,
+                unusable: if repo.disabled {
+                    Some("disabled".to_string())
+                } else if repo.size == 0 {
+                    Some("empty".to_string())
+                } else {
+                    None
+                },
+                archived: repo.archived,
+                fork: repo.fork,
+                visibility: repo.visibility,
+                topics: repo.topics,
+            })
+            .collect())
+    }
+
+    pub async fn default_branch(&self, repo: &GithubRepo) -> Result<String> {
+        let response: RepositoryResponse =
+            self.send(self.http.get(self.repository_url(repo))).await?;
+        Ok(response.default_branch)
+    }
+
+    /// Every open pull request from a branch of `repo` itself, leaving out
+    /// those from forks.
+    pub async fn open_pull_requests(&self, repo: &GithubRepo) -> Result<Vec<PullRequest>> {
+        let url = format!("{}/pulls", self.repository_url(repo));
+        let mut pulls = Vec::new();
+        for page in 1.. {
+            let batch: Vec<PullRequestResponse> = self
+                .send(self.http.get(&url).query(&[
+                    ("state", "open".to_string()),
+                    ("per_page", PAGE_SIZE.to_string()),
+                    ("page", page.to_string()),
+                ]))
+                .await?;
+            let last = batch.len() < PAGE_SIZE;
+            pulls.extend(batch);
+            if last {
+                break;
+            }
+        }
+        Ok(own_pull_requests(repo, pulls))
+    }
+
+    /// The open pull requests from `branch` of `repo` itself.
+    pub async fn open_pull_requests_from(
+        &self,
+        repo: &GithubRepo,
+        branch: &str,
+    ) -> Result<Vec<PullRequest>> {
+        let url = format!("{}/pulls", self.repository_url(repo));
+        let head = format!("{}:{}", repo.owner, branch);
+        let pulls: Vec<PullRequestResponse> = self
+            .send(
+                self.http
+                    .get(url)
+                    .query(&[("state", "open"), ("head", &head)]),
+            )
+            .await?;
+        Ok(own_pull_requests(repo, pulls))
+    }
+
+    pub async fn create_pull_request(
+        &self,
+        repo: &GithubRepo,
+        base: &str,
+        pr: &NewPullRequest,
+    ) -> Result<PullRequest> {
+        let body = pr.description_with_issues();
+        let request = CreatePullRequest {
+            title: &pr.title,
+            head: &pr.source_branch,
+            base,
+            body: &body,
+        };
+        let url = format!("{}/pulls", self.repository_url(repo));
+        let response: PullRequestResponse = self.send(self.http.post(url).json(&request)).await?;
+
+        if !pr.reviewers.is_empty() {
+            let url = format!(
+                "{}/pulls/{}/requested_reviewers",
+                self.repository_url(repo),
+                response.number
+            );
+            let reviewers = RequestReviewers {
+                reviewers: &pr.reviewers,
+            };
+            self.send::<serde_json::Value>(self.http.post(url).json(&reviewers))
+                .await?;
+        }
+
+        Ok(PullRequest {
+            id: response.number,
+            source_branch: response.head.name,
+            web_url: response.html_url,
+        })
+    }
+
+    /// Opens a pull request against the default branch, unless one from a
+    /// branch starting with `gavin_prefix` is already open.
+    pub async fn open_fix_pull_request(
+        &self,
+        repo: &GithubRepo,
+        pr: &NewPullRequest,
+        gavin_prefix: &str,
+    ) -> Result<PullRequestOutcome> {
+        let mut existing = self
+            .open_pull_requests_from(repo, &pr.source_branch)
+            .await?
+            .into_iter()
+            .next();
+        if existing.is_none() && !gavin_prefix.is_empty() {
+            existing = self
+                .open_pull_requests(repo)
+                .await?
+                .into_iter()
+                .find(|open| is_gavin_branch(&open.source_branch, &pr.source_branch, gavin_prefix));
+        }
+        if let Some(existing) = existing {
+            return Ok(PullRequestOutcome::Existing(existing));
+        }
+
+        let base = self.default_branch(repo).await?;
+        let created = self.create_pull_request(repo, &base, pr).await?;
+        Ok(PullRequestOutcome::Created(created))
+    }
+}
+
+/// The pull requests whose branch is in `repo`, not in a fork of it.
+fn own_pull_requests(repo: &GithubRepo, pulls: Vec<PullRequestResponse>) -> Vec<PullRequest> {
+    let full_name = format!("{}/{}", repo.owner, repo.name);
+    pulls
+        .into_iter()
+        .filter(|pr| {
+            pr.head
+                .repo
+                .as_ref()
+                .is_some_and(|head| head.full_name.eq_ignore_ascii_case(&full_name))
+        })
+        .map(|pr| PullRequest {
+            id: pr.number,
+            source_branch: pr.head.name,
+            web_url: pr.html_url,
+        })
+        .collect()
+}
@@ -0,0 +1,275 @@
+//! GitLab CI configuration: `include:` entries pinned to a project ref or component
+//! version, and the container images used by `image:` and `services:`.
+
+use crate::pipeline::{escape_key, Location, SpanIndex, TaskReference};
+use crate::template::ResolvedTask;
+use anyhow::{Context, Result};
+use serde_yaml::{Mapping, Value};
+use std::path::{Path, PathBuf};
+
+/// The pipeline definition GitLab reads by default.
+pub const CI_FILE: &str = ".gitlab-ci.yml";
+
+/// Prefix that keeps GitLab references apart from other platforms, e.g. `gitlab:image:node`.
+pub const TASK_PREFIX: &str = "gitlab:";
+
+/// GitLab stops processing configurations with more nested includes than this.
+const MAX_INCLUDE_DEPTH: usize = 150;
+
+/// Top-level keys that are global settings rather than jobs.
+const GLOBAL_KEYS: [&str; 5] = ["include", "stages", "variables", "workflow", "spec"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceKind {
+    /// `include: project:` pinned with `ref:`.
+    Project,
+    /// `include: component:` pinned with `@version`.
+    Component,
+    /// `include: remote:`. The URL itself is the version.
+    Remote,
+    /// `image:` or a `services:` entry, pinned with its tag or digest.
+    Image,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GitlabReference {
+    pub kind: ReferenceKind,
+    pub name: String,
+    pub version: String,
+    /// Points at the value holding the version: the `ref:`, the component or
+    /// remote URL, or the image name.
+    pub location: Location,
+}
+
+impl GitlabReference {
+    /// Name as checked against valid states, e.g. `gitlab:project:group/templates`.
+    pub fn task_name(&self) -> String {
+        match self.kind {
+            ReferenceKind::Project => format!("{}project:{}", TASK_PREFIX, self.name),
+            ReferenceKind::Component => format!("{}component:{}", TASK_PREFIX, self.name),
+            ReferenceKind::Remote => format!("{}remote", TASK_PREFIX),
+            ReferenceKind::Image => format!("{}image:{}", TASK_PREFIX, self.name),
+        }
+    }
+
+    pub fn into_task_reference(self) -> TaskReference {
+        TaskReference {
+            name: self.task_name(),
+            version: self.version,
+            inputs: Default::default(),
+            location: self.location,
+            input_locations: Default::default(),
+        }
+    }
+}
+
+/// The references in a single file, plus the `local:` includes it pulls in.
+#[derive(Debug, Clone, Default)]
+pub struct GitlabFile {
+    pub references: Vec<GitlabReference>,
+    pub local_includes: Vec<String>,
+}
+
+/// The CI file of a checkout, if it has one.
+pub fn find_ci_file(repo_path: &Path) -> Option<PathBuf> {
+    Some(repo_path.join(CI_FILE)).filter(|path| path.is_file())
+}
+
+/// Parses one GitLab CI file without following its includes.
+pub fn parse_file(content: &str) -> Result<GitlabFile> {
+    let value: Value = serde_yaml::from_str(content).context("Invalid YAML")?;
+    let Value::Mapping(root) = value else {
+        return Ok(GitlabFile::default());
+    };
+    let spans = SpanIndex::build(content)?;
+    let locate = |path: &str| spans.get(path).unwrap_or_default();
+
+    let mut file = GitlabFile::default();
+
+    match root.get("include") {
+        Some(Value::Sequence(items)) => {
+            for (i, item) in items.iter().enumerate() {
+                read_include(item, &format!("/include/{}", i), &locate, &mut file);
+            }
+        }
+        Some(include) => read_include(include, "/include", &locate, &mut file),
+        None => {}
+    }
+
+    // Global `image:`/`services:` are deprecated but still honoured
+    read_images(&root, "", &locate, &mut file);
+    for (key, job) in &root {
+        let (Some(key), Value::Mapping(job)) = (key.as_str(), job) else {
+            continue;
+        };
+        if !GLOBAL_KEYS.contains(&key) {
+            read_images(job, &format!("/{}", escape_key(key)), &locate, &mut file);
+        }
+    }
+
+    Ok(file)
+}
+
+fn read_include(
+    include: &Value,
+    path: &str,
+    locate: &dyn Fn(&str) -> Location,
+    file: &mut GitlabFile,
+) {
+    let mut push = |kind, name: &str, version: &str, node: &str| {
+        file.references.push(GitlabReference {
+            kind,
+            name: name.to_string(),
+            version: version.to_string(),
+            location: locate(node),
+        });
+    };
+
+    let include = match include {
+        Value::String(s) if s.starts_with("http://") || s.starts_with("https://") => {
+            push(ReferenceKind::Remote, "", s, path);
+            return;
+        }
+        Value::String(s) => {
+            file.local_includes.push(s.clone());
+            return;
+        }
+        Value::Mapping(include) => include,
+        _ => return,
+    };
+    let get = |key: &str| include.get(key).and_then(Value::as_str);
+
+    if let Some(project) = get("project") {
+        match get("ref") {
+            Some(git_ref) => push(
+                ReferenceKind::Project,
+                project,
+                git_ref,
+                &format!("{}/ref", path),
+            ),
+            None => push(
+                ReferenceKind::Project,
+                project,
+                "",
+                &format!("{}/project", path),
+            ),
+        }
+    } else if let Some(component) = get("component") {
+        let (name, version) = component.rsplit_once('@').unwrap_or((component, ""));
+        push(
+            ReferenceKind::Component,
+            name,
+            version,
+            &format!("{}/component", path),
+        );
+    } else if let Some(remote) = get("remote") {
+        push(
+            ReferenceKind::Remote,
+            "",
+            remote,
+            &format!("{}/remote", path),
+        );
+    } else if let Some(local) = get("local") {
+        file.local_includes.push(local.to_string());
+    }
+}
+
+fn read_images(
+    job: &Mapping,
+    path: &str,
+    locate: &dyn Fn(&str) -> Location,
+    file: &mut GitlabFile,
+) {
+    let mut push = |image: &Value, path: String| {
+        let (image, path) = match image {
+            Value::String(image) => (image, path),
+            Value::Mapping(map) => match map.get("name") {
+                Some(Value::String(image)) => (image, format!("{}/name", path)),
+                _ => return,
+            },
+            _ => return,
+        };
+        let (name, version) = split_image(image);
+        file.references.push(GitlabReference {
+            kind: ReferenceKind::Image,
+            name: name.to_string(),
+            version: version.to_string(),
+            location: locate(&path),
+        });
+    };
+
+    if let Some(image) = job.get("image") {
+        push(image, format!("{}/image", path));
+    }
+    if let Some(Value::Sequence(services)) = job.get("services") {
+        for (i, service) in services.iter().enumerate() {
+            push(service, format!("{}/services/{}", path, i));
+        }
+    }
+}
+
+/// Splits `registry/name:tag@digest` into the image name and the tag and/or
+/// digest. Untagged images resolve to `latest`, as Docker does.
+pub fn split_image(image: &str) -> (&str, &str) {
+    let image = image.trim();
+    let (without_digest, digest) = match image.split_once('@') {
+        Some((rest, digest)) => (rest, Some(digest)),
+        None => (image, None),
+    };
+    let name_start = without_digest.rfind('/').map_or(0, |i| i + 1);
+    match without_digest[name_start..].find(':') {
+        Some(i) => (&image[..name_start + i], &image[name_start + i + 1..]),
+        None => match digest {
+            Some(digest) => (without_digest, digest),
+            None => (image, "latest"),
+        },
+    }
+}
+
+/// Reads a CI file and every local file it includes. Each reference carries the
+/// chain of local includes that led to it.
+pub fn expand(repo_root: &Path, ci_file: &Path) -> Result<Vec<ResolvedTask>> {
+    let mut tasks = Vec::new();
+    let mut stack = vec![ci_file.to_path_buf()];
+    expand_file(repo_root, &mut stack, &mut tasks)?;
+    Ok(tasks)
+}
+
+/// Expands the file on top of `stack`, which holds the CI file followed by the
+/// includes that led to it.
+fn expand_file(
+    repo_root: &Path,
+    stack: &mut Vec<PathBuf>,
+    tasks: &mut Vec<ResolvedTask>,
+) -> Result<()> {
+    let file = stack.last().cloned().unwrap_or_default();
+    let content = std::fs::read_to_string(&file)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+    let parsed = parse_file(&content)?;
+
+    tasks.extend(parsed.references.into_iter().map(|reference| ResolvedTask {
+        task: reference.into_task_reference(),
+        source: file.clone(),
+        include_chain: stack[1..].to_vec(),
+    }));
+
+    for local in parsed.local_includes {
+        if local.contains('*') {
+            eprintln!("Skipping wildcard include {} in {}", local, file.display());
+            continue;
+        }
+        let included = repo_root.join(local.trim_start_matches('/'));
+        if stack.contains(&included) || stack.len() > MAX_INCLUDE_DEPTH {
+            eprintln!("Skipping recursive include {} in {}", local, file.display());
+            continue;
+        }
+
+        stack.push(included);
+        if let Err(e) = expand_file(repo_root, stack, tasks) {
+            eprintln!("Skipping include {} in {}: {}", local, file.display(), e);
+        }
+        stack.pop();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,264 @@
+//! Merge requests through the GitLab REST API, on gitlab.com or a self-hosted instance.
+
+use crate::discovery::DiscoveredRepo;
+use crate::pull_request::{
+    is_gavin_branch, url_host, NewPullRequest, PullRequest, PullRequestOutcome,
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_API_URL: &str = "https://gitlab.com/api/v4";
+
+/// A project by its full path, such as `group/subgroup/name`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitlabProject {
+    pub path: String,
+}
+
+impl GitlabProject {
+    /// Parses `https://<clone_host>/group/[subgroup/...]name[.git]`.
+    pub fn parse(url: &str, clone_host: &str) -> Option<Self> {
+        if url_host(url) != clone_host {
+            return None;
+        }
+        let path = url.split_once("://")?.1.split_once('/')?.1;
+        let path = path.trim_end_matches('/').trim_end_matches(".git");
+        // Projects always live in a group or user namespace
+        path.contains('/').then(|| GitlabProject {
+            path: path.to_string(),
+        })
+    }
+
+    /// The path as a URL-encoded project ID.
+    fn id(&self) -> String {
+        self.path.replace('/', "%2F")
+    }
+}
+
+#[derive(Deserialize)]
+struct ProjectResponse {
+    default_branch: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ProjectListing {
+    path_with_namespace: String,
+    web_url: String,
+    #[serde(default)]
+    archived: bool,
+    #[serde(default)]
+    empty_repo: bool,
+    /// Only present on forks.
+    forked_from_project: Option<serde_json::Value>,
+    visibility: Option<String>,
+    #[serde(default)]
+    topics: Vec<String>,
+}
+
+/// Projects or merge requests per page when listing, the most GitLab allows.
+const PAGE_SIZE: usize = 100;
+
+#[derive(Deserialize)]
+struct MergeRequestResponse {
+    iid: u64,
+    source_branch: String,
+    web_url: String,
+}
+
+#[derive(Serialize)]
+struct CreateMergeRequest<'a> {
+    source_branch: &'a str,
+    target_branch: &'a str,
+    title: &'a str,
+    description: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    reviewer_ids: Vec<u64>,
+}
+
+pub struct GitlabClient {
+    http: reqwest::Client,
+    base_url: String,
+    token: String,
+}
+
+impl GitlabClient {
+    /// A client for the API at `base_url`, such as `https://gitlab.example.com/api/v4`.
+    pub fn new(base_url: &str, token: &str) -> Self {
+        GitlabClient {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+        }
+    }
+
+    /// The host repositories served by this API are cloned from.
+    pub fn clone_host(&self) -> &str {
+        url_host(&self.base_url)
+    }
+
+    fn project_url(&self, project: &GitlabProject) -> String {
+        format!("{}/projects/{}", self.base_url, project.id())
+    }
+
+    async fn send<T: serde::de::DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<T> {
+        let response = request.header("PRIVATE-TOKEN", &self.token).send().await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("GitLab returned {}: {}", status, body));
+        }
+        response
+            .json()
+            .await
+            .context("Unexpected response from GitLab")
+    }
+
+    /// Every project in the group and its subgroups, named by full path.
+    pub async fn list_group_projects(&self, group: &str) -> Result<Vec<DiscoveredRepo>> {
+        let url = format!(
+            "{}/groups/{}/projects",
+            self.base_url,
+            group.replace('/', "%2F")
+        );
+        let mut listings = Vec::new();
+        for page in 1.. {
+            let batch: Vec<ProjectListing> = self
+                .send(self.http.get(&url).query(&[
+                    ("include_subgroups", "true".to_string()),
+                    ("per_page", PAGE_SIZE.to_string()),
+                    ("page", page.to_string()),
+                ]))
+                .await?;
+            let last = batch.len() < PAGE_SIZE;
+            listings.extend(batch);
+            if last {
+                break;
+            }
+        }
+
+        Ok(listings
+            .into_iter()
+            .map(|project| DiscoveredRepo {
+                name: project.path_with_namespace,
+                clone_url: project.web_url,
+                unusable: project.empty_repo.then(|| "empty".to_string()),
+                archived: project.archived,
+                fork: project.forked_from_project.is_some(),
+                visibility: project.visibility,
+                topics: project.topics,
+            })
+            .collect())
+    }
+
+    pub async fn default_branch(&self, project: &GitlabProject) -> Result<String> {
+        let response: ProjectResponse = self.send(self.http.get(self.project_url(project))).await?;
+        response
+            .default_branch
+            .ok_or_else(|| anyhow::anyhow!("{} has no default branch", project.path))
+    }
+
+    pub async fn open_merge_requests(&self, project: &GitlabProject) -> Result<Vec<PullRequest>> {
+        let url = format!("{}/merge_requests", self.project_url(project));
+        let mut merge_requests = Vec::new();
+        for page in 1.. {
+            let batch: Vec<MergeRequestResponse> = self
+                .send(self.http.get(&url).query(&[
+                    ("state", "opened".to_string()),
+                    ("per_page", PAGE_SIZE.to_string()),
+                    ("page", page.to_string()),
+                ]))
+                .await?;
+            let last = batch.len() < PAGE_SIZE;
+            merge_requests.extend(batch);
+            if last {
+                break;
+            }
+        }
+        Ok(merge_requests.into_iter().map(pull_request).collect())
+    }
+
+    /// The open merge requests from `branch`.
+    pub async fn open_merge_requests_from(
+        &self,
+        project: &GitlabProject,
+        branch: &str,
+    ) -> Result<Vec<PullRequest>> {
+        let url = format!("{}/merge_requests", self.project_url(project));
+        let merge_requests: Vec<MergeRequestResponse> = self
+            .send(
+                self.http
+                    .get(url)
+                    .query(&[("state", "opened"), ("source_branch", branch)]),
+            )
+            .await?;
+        Ok(merge_requests.into_iter().map(pull_request).collect())
+    }
+
+    pub async fn create_merge_request(
+        &self,
+        project: &GitlabProject,
+        target_branch: &str,
+        pr: &NewPullRequest,
+    ) -> Result<PullRequest> {
+        let reviewer_ids = pr
+            .reviewers
+            .iter()
+            .map(|id| {
+                id.parse()
+                    .map_err(|_| anyhow::anyhow!("GitLab reviewers are user IDs, got '{}'", id))
+            })
+            .collect::<Result<Vec<u64>>>()?;
+        let description = pr.description_with_issues();
+        let request = CreateMergeRequest {
+            source_branch: &pr.source_branch,
+            target_branch,
+            title: &pr.title,
+            description: &description,
+            reviewer_ids,
+        };
+        let url = format!("{}/merge_requests", self.project_url(project));
+        let response: MergeRequestResponse = self.send(self.http.post(url).json(&request)).await?;
+        Ok(pull_request(response))
+    }
+
+    /// Opens a merge request against the default branch, unless one from a
+    /// branch starting with `gavin_prefix` is already open.
+    pub async fn open_fix_pull_request(
+        &self,
+        project: &GitlabProject,
+        pr: &NewPullRequest,
+        gavin_prefix: &str,
+    ) -> Result<PullRequestOutcome> {
+        let mut existing = self
+            .open_merge_requests_from(project, &pr.source_branch)
+            .await?
+            .into_iter()
+            .next();
+        if existing.is_none() && !gavin_prefix.is_empty() {
+            existing = self
+                .open_merge_requests(project)
+                .await?
+                .into_iter()
+                .find(|open| is_gavin_branch(&open.source_branch, &pr.source_branch, gavin_prefix));
+        }
+        if let Some(existing) = existing {
+            return Ok(PullRequestOutcome::Existing(existing));
+        }
+
+        let target = self.default_branch(project).await?;
+        let created = self.create_merge_request(project, &target, pr).await?;
+        Ok(PullRequestOutcome::Created(created))
+    }
+}
+
+fn pull_request(mr: MergeRequestResponse) -> PullRequest {
+    PullRequest {
+        id: mr.iid,
+        source_branch: mr.source_branch,
+        web_url: mr.web_url,
+    }
+}
@@ -1,5 +1,11 @@
+use crate::fixer::Fix;
+use crate::{
+    version_spec, Database, SupportedTask, TaskImplementation, TaskIssues, TaskValidState,
+    VersionCompare,
+};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -26,8 +32,21 @@ impl GitVersionState {
     }
 
     pub fn from_string(s: &str) -> Result<Self, String> {
-        // Expected format: "setup:VERSION,execute:VERSION,spec:VERSION"
-        let parts: Vec<&str> = s.split(',').collect();
+        // Expected format: "setup:VERSION,execute:VERSION,spec:VERSION", where a
+        // version may be a range with commas of its own, e.g. "spec:>=6.0, <7"
+        let mut parts: Vec<String> = Vec::new();
+        for part in s.split(',') {
+            let starts_pair = part
+                .split_once(':')
+                .is_some_and(|(key, _)| ["setup", "execute", "spec"].contains(&key.trim()));
+            match parts.last_mut() {
+                Some(last) if !starts_pair => {
+                    last.push(',');
+                    last.push_str(part);
+                }
+                _ => parts.push(part.to_string()),
+            }
+        }
         if parts.len() != 3 {
             return Err(
                 "Invalid format. Expected 'setup:VERSION,execute:VERSION,spec:VERSION'".to_string(),
@@ -38,16 +57,15 @@ impl GitVersionState {
         let mut execute = None;
         let mut spec = None;
 
-        for part in parts {
-            let kv: Vec<&str> = part.split(':').collect();
-            if kv.len() != 2 {
+        for part in &parts {
+            let Some((key, value)) = part.split_once(':') else {
                 return Err("Invalid key-value pair".to_string());
-            }
-            match kv[0].trim() {
-                "setup" => setup = Some(kv[1].trim()),
-                "execute" => execute = Some(kv[1].trim()),
-                "spec" => spec = Some(kv[1].trim()),
-                _ => return Err(format!("Unknown key: {}", kv[0])),
+            };
+            match key.trim() {
+                "setup" => setup = Some(value.trim()),
+                "execute" => execute = Some(value.trim()),
+                "spec" => spec = Some(value.trim()),
+                _ => return Err(format!("Unknown key: {}", key)),
             }
         }
 
@@ -57,4 +75,230 @@ impl GitVersionState {
             spec.ok_or("Missing spec version")?,
         ))
     }
+
+    /// Checks that each version is a version or a range.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        version_spec::validate(&self.setup_version)?;
+        version_spec::validate(&self.execute_version)?;
+        version_spec::validate(&self.spec_version)
+    }
+}
+
+/// Names of the two tasks a GitVersion state covers.
+pub const SETUP_TASK: &str = "gitversion/setup";
+pub const EXECUTE_TASK: &str = "gitversion/execute";
+
+/// The name implementations of `task_name` are grouped under. Task names are
+/// case-insensitive, so `GitVersion/setup` is [`SETUP_TASK`] too; other tasks
+/// keep the name the pipeline gives them.
+pub fn task_key(task_name: &str) -> String {
+    [SETUP_TASK, EXECUTE_TASK]
+        .into_iter()
+        .find(|task| task.eq_ignore_ascii_case(task_name))
+        .unwrap_or(task_name)
+        .to_string()
+}
+
+/// Checks the setup and execute tasks of each repository together against the
+/// GitVersion valid states.
+pub(crate) fn check_implementations(
+    task_name: &str,
+    task_implementations: &HashMap<String, Vec<TaskImplementation>>,
+    db: &Database,
+    issues: &mut TaskIssues,
+) -> Result<()> {
+    // Get valid states from database
+    let valid_states = db.list_valid_states(&SupportedTask::Gitversion)?;
+    let valid_states: Vec<GitVersionState> = valid_states
+        .into_iter()
+        .map(|state| match state {
+            TaskValidState::Gitversion(gv) => gv,
+            other => panic!("Expected GitVersion state, got {:?}", other),
+        })
+        .collect();
+    // Group implementations by repo URL to collect both setup and execute
+    let mut repo_implementations: HashMap<String, Vec<(&TaskImplementation, &str)>> =
+        HashMap::new();
+
+    // Get both setup and execute implementations
+    let empty_vec = Vec::new();
+    let setup_impls = task_implementations.get(SETUP_TASK).unwrap_or(&empty_vec);
+    let execute_impls = task_implementations.get(EXECUTE_TASK).unwrap_or(&empty_vec);
+
+    // Combine both sets of implementations
+    for impl_ in setup_impls {
+        repo_implementations
+            .entry(impl_.repo_url.clone())
+            .or_default()
+            .push((impl_, SETUP_TASK));
+    }
+
+    for impl_ in execute_impls {
+        repo_implementations
+            .entry(impl_.repo_url.clone())
+            .or_default()
+            .push((impl_, EXECUTE_TASK));
+    }
+    // Sort repos for consistent output
+    let mut repos: Vec<_> = repo_implementations
+        .iter()
+        .map(|(url, impls)| (&impls[0].0.repo_name, url, impls))
+        .collect();
+    repos.sort_by_key(|(repo_name, url, _)| (*repo_name, *url));
+    for (repo_name, _, impls) in repos {
+        // Find matching setup and execute implementations
+        let mut setup_version = None;
+        let mut execute_version = None;
+        let mut spec_version = None;
+        let mut file_path = None;
+        for (impl_, task_type) in impls {
+            match *task_type {
+                SETUP_TASK => {
+                    setup_version = Some(impl_.version.clone());
+                    file_path = Some(impl_.file_path.clone());
+                    spec_version = impl_.inputs.get("versionSpec").cloned();
+                }
+                EXECUTE_TASK => {
+                    execute_version = Some(impl_.version.clone());
+                }
+                _ => {}
+            }
+        }
+        // Validate against valid states
+        let mut is_valid = false;
+        for state in &valid_states {
+            if setup_version
+                .as_ref()
+                .is_some_and(|v| version_spec::matches(&state.setup_version, v))
+                && execute_version
+                    .as_ref()
+                    .is_some_and(|v| version_spec::matches(&state.execute_version, v))
+                && spec_version
+                    .as_ref()
+                    .is_some_and(|s| version_spec::matches(&state.spec_version, s))
+            {
+                is_valid = true;
+                break;
+            }
+        }
+        let status = if is_valid { "✓" } else { "✗" };
+        let path_info = if let Some(path) = &file_path {
+            format!(
+                " ({})",
+                path.strip_prefix(repo_name).unwrap_or(path).display()
+            )
+        } else {
+            String::new()
+        };
+        let default_str = "?".to_string();
+        println!(
+            "{} {:<25} {} | {} | {}{}",
+            status,
+            repo_name,
+            setup_version.as_ref().unwrap_or(&default_str),
+            execute_version.unwrap_or_else(|| "?".to_string()),
+            spec_version.unwrap_or_else(|| "?".to_string()),
+            path_info
+        );
+        // Track invalid states. The combination is invalid, so every
+        // implementation of the task being checked is, each under its own
+        // task; the other task's call records the rest.
+        if !is_valid {
+            let invalid: Vec<TaskImplementation> = impls
+                .iter()
+                .filter(|(_, task_type)| *task_type == task_name)
+                .map(|(impl_, _)| (*impl_).clone())
+                .collect();
+            if !invalid.is_empty() {
+                issues
+                    .invalid_states
+                    .entry(task_name.to_string())
+                    .or_default()
+                    .entry(repo_name.to_string())
+                    .or_default()
+                    .extend(invalid);
+            }
+        }
+    }
+    // Show valid states if we're processing setup tasks
+    if task_name == SETUP_TASK {
+        println!("\nValid states:");
+        for state in &valid_states {
+            println!(
+                "  - setup@{} | execute@{} | spec@{}",
+                state.setup_version, state.execute_version, state.spec_version
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Plans fixes moving the setup version, `versionSpec` and execute version of
+/// every repository with an invalid GitVersion setup to the newest valid state
+/// without ranges.
+pub(crate) fn plan_fixes(issues: &TaskIssues, db: &Database) -> Result<Vec<Fix>> {
+    let target = db
+        .list_valid_states(&SupportedTask::Gitversion)?
+        .into_iter()
+        .filter(|state| !state.has_range())
+        .filter_map(|state| match state {
+            TaskValidState::Gitversion(gv) => Some(gv),
+            _ => None,
+        })
+        .max_by(|a, b| {
+            a.setup_version
+                .version_cmp(&b.setup_version)
+                .then_with(|| a.execute_version.version_cmp(&b.execute_version))
+                .then_with(|| a.spec_version.version_cmp(&b.spec_version))
+        });
+    let Some(target) = target else {
+        return Ok(Vec::new());
+    };
+
+    let invalid_repos: Vec<&String> = [SETUP_TASK, EXECUTE_TASK]
+        .iter()
+        .filter_map(|task| issues.invalid_states.get(*task))
+        .flat_map(|repos| repos.keys())
+        .collect();
+    Ok(fixes_to(issues, &target, |repo| {
+        invalid_repos.iter().any(|invalid| *invalid == repo)
+    }))
+}
+
+/// Fixes moving the GitVersion tasks of the repositories `include` accepts to `target`.
+pub(crate) fn fixes_to(
+    issues: &TaskIssues,
+    target: &GitVersionState,
+    include: impl Fn(&str) -> bool,
+) -> Vec<Fix> {
+    let implementations = |task: &str| {
+        issues
+            .all_implementations
+            .get(task)
+            .into_iter()
+            .flatten()
+            .filter(|impl_| include(&impl_.repo_name))
+    };
+
+    let mut fixes = Vec::new();
+    for impl_ in implementations(SETUP_TASK) {
+        if impl_.version != target.setup_version {
+            fixes.push(Fix::version(SETUP_TASK, impl_, &target.setup_version));
+        }
+        if impl_.inputs.get("versionSpec") != Some(&target.spec_version) {
+            fixes.push(Fix::input(
+                SETUP_TASK,
+                impl_,
+                "versionSpec",
+                &target.spec_version,
+            ));
+        }
+    }
+    for impl_ in implementations(EXECUTE_TASK) {
+        if impl_.version != target.execute_version {
+            fixes.push(Fix::version(EXECUTE_TASK, impl_, &target.execute_version));
+        }
+    }
+    fixes
 }
@@ -0,0 +1,70 @@
+//! The kind of server a repository is hosted on, which decides how git
+//! authenticates to it.
+
+/// Where a repository lives. Stored with each repository, since self-hosted
+/// GitHub Enterprise and GitLab servers cannot be told apart by their URL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostType {
+    AzureDevOps,
+    GitHub,
+    GitLab,
+    /// Any other git server, given the username and token as they are.
+    Other,
+}
+
+impl HostType {
+    /// Guesses the host from the URL of a repository on a public service.
+    pub fn detect(url: &str) -> Self {
+        let host = crate::repo_id::RepoId::parse(url).host;
+        if host == "dev.azure.com" {
+            HostType::AzureDevOps
+        } else if host == "github.com" {
+            HostType::GitHub
+        } else if host == "gitlab.com" {
+            HostType::GitLab
+        } else {
+            HostType::Other
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HostType::AzureDevOps => "azure-devops",
+            HostType::GitHub => "github",
+            HostType::GitLab => "gitlab",
+            HostType::Other => "other",
+        }
+    }
+
+    /// The username git sends with the token. GitHub and GitLab expect a fixed
+    /// one for personal access tokens rather than the account name.
+    pub fn git_username(self, username: &str) -> &str {
+        match self {
+            HostType::GitHub => "x-access-token",
+            HostType::GitLab => "oauth2",
+            HostType::AzureDevOps | HostType::Other => username,
+        }
+    }
+}
+
+impl std::str::FromStr for HostType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            HostType::AzureDevOps,
+            HostType::GitHub,
+            HostType::GitLab,
+            HostType::Other,
+        ]
+        .into_iter()
+        .find(|host| host.as_str() == s)
+        .ok_or_else(|| anyhow::anyhow!("Unknown host type: {}", s))
+    }
+}
+
+impl std::fmt::Display for HostType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
@@ -1,32 +1,59 @@
-use anyhow::{Context, Result};
-use regex::Regex;
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::fs;
 use tokio::sync::Semaphore;
-use walkdir::WalkDir;
 // use itertools::Itertools;
+use parse_cache::ParseCache;
+use policy::{PipelineSummary, PolicyViolation};
 use semver::Version;
 
 // Re-export modules and types
+pub mod azure_devops;
+pub mod campaign;
 pub mod cli;
 pub mod cli_handler;
 pub mod config;
 pub mod database;
+pub mod discovery;
+pub mod fixer;
 pub mod git_manager;
+pub mod github_api;
+pub mod gitlab;
+pub mod gitlab_api;
 pub mod gitversion;
+pub mod host;
+pub mod metadata;
+pub mod migrations;
+pub mod parse_cache;
+pub mod pipeline;
+pub mod platform;
+pub mod policy;
+pub mod pull_request;
+pub mod repo_id;
 pub mod report;
+pub mod scan;
+pub mod secrets;
+pub mod template;
 pub mod utils;
+pub mod version_spec;
+pub mod workflow;
+pub mod workspace;
 
 // Re-export commonly used types
 pub use cli::Cli;
 pub use cli_handler::handle_cli_args;
 pub use config::Config;
 pub use database::Database;
-pub use git_manager::GitManager;
+pub use git_manager::{GitManager, SyncMode};
 pub use gitversion::{GitVersionImplementation, GitVersionState};
+pub use pipeline::{Location, TaskReference};
+pub use repo_id::RepoId;
+pub use template::{PipelineTasks, ResolvedTask, TemplateResolver};
+pub use workflow::GitRef;
+pub use workspace::Workspace;
 
 struct SearchResult {
     repo: String,
@@ -43,19 +70,69 @@ struct Finding {
 pub enum TaskValidState {
     Gitversion(GitVersionState),
     Default(String),
+    GitRef(GitRef),
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum SupportedTask {
     Gitversion,
     Default(String),
+    /// A GitHub action or reusable workflow, named `owner/repo[/path]`.
+    GithubAction(String),
+    /// A GitLab include or image, named `project:group/name`, `component:path`,
+    /// `remote` or `image:name`.
+    Gitlab(String),
 }
 
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub struct TaskImplementation {
+    repo_url: String,
     repo_name: String,
     version: String,
     file_path: PathBuf,
+    location: Location,
+    inputs: BTreeMap<String, String>,
+    input_locations: BTreeMap<String, Location>,
+    /// File the task is written in, which differs from `file_path` for template tasks
+    source_path: PathBuf,
+    /// Templates that were expanded to reach the task, outermost first
+    include_chain: Vec<PathBuf>,
+}
+
+impl TaskImplementation {
+    fn new(repo_url: &str, repo_name: &str, pipeline: &Path, resolved: ResolvedTask) -> Self {
+        TaskImplementation {
+            repo_url: repo_url.to_string(),
+            repo_name: repo_name.to_string(),
+            version: resolved.task.version,
+            file_path: pipeline.to_path_buf(),
+            location: resolved.task.location,
+            inputs: resolved.task.inputs,
+            input_locations: resolved.task.input_locations,
+            source_path: resolved.source,
+            include_chain: resolved.include_chain,
+        }
+    }
+}
+
+/// Formats an include chain as ` via a.yml → b.yml`, or an empty string when
+/// the task is written in the pipeline itself.
+pub fn format_include_chain(chain: &[PathBuf], repo_path: Option<&Path>) -> String {
+    if chain.is_empty() {
+        return String::new();
+    }
+    let chain = chain
+        .iter()
+        .map(|path| {
+            repo_path
+                .and_then(|root| path.strip_prefix(root).ok())
+                .unwrap_or(path)
+                .display()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join(" → ");
+    format!(" via {}", chain)
 }
 
 // Add a custom parser for clap
@@ -63,10 +140,7 @@ impl std::str::FromStr for SupportedTask {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "gitversion" => Ok(SupportedTask::Gitversion),
-            other => Ok(SupportedTask::Default(other.to_string())),
-        }
+        parse_task_name(&s.to_lowercase()).map_err(|e| e.to_string())
     }
 }
 
@@ -75,6 +149,8 @@ impl std::fmt::Display for SupportedTask {
         match self {
             SupportedTask::Gitversion => write!(f, "gitversion"),
             SupportedTask::Default(name) => write!(f, "{}", name),
+            SupportedTask::GithubAction(name) => write!(f, "{}{}", workflow::TASK_PREFIX, name),
+            SupportedTask::Gitlab(name) => write!(f, "{}{}", gitlab::TASK_PREFIX, name),
         }
     }
 }
@@ -88,6 +164,25 @@ impl std::fmt::Display for TaskValidState {
                 state.setup_version, state.execute_version, state.spec_version
             ),
             TaskValidState::Default(version) => write!(f, "@{}", version),
+            TaskValidState::GitRef(git_ref) => write!(f, "{}", git_ref),
+        }
+    }
+}
+
+impl TaskValidState {
+    /// Whether any version in the state is a range, which `--fix` and
+    /// campaigns cannot move a task to.
+    pub fn has_range(&self) -> bool {
+        match self {
+            TaskValidState::Gitversion(state) => [
+                &state.setup_version,
+                &state.execute_version,
+                &state.spec_version,
+            ]
+            .into_iter()
+            .any(|version| version_spec::is_range(version)),
+            TaskValidState::Default(version) => version_spec::is_range(version),
+            TaskValidState::GitRef(_) => false,
         }
     }
 }
@@ -113,52 +208,47 @@ pub fn format_task_states(_task: &SupportedTask, states: Vec<TaskValidState>) ->
         .join("\n")
 }
 
+/// Lists the pipeline files of every platform in a checkout.
 async fn find_pipeline_files(repo_path: &Path) -> Result<Vec<PathBuf>> {
-    // Create a bounded channel to prevent memory issues with large directories
-    let (tx, mut rx) = tokio::sync::mpsc::channel(1000);
-
-    // Spawn the blocking directory walk in a separate thread pool
-    tokio::task::spawn_blocking({
-        let tx = tx.clone();
-        let repo_path = repo_path.to_path_buf();
-        move || {
-            for entry in WalkDir::new(&repo_path)
-                .follow_links(true)
-                .into_iter()
-                .filter_map(Result::ok)
-            {
-                let path = entry.path().to_path_buf();
-                if path
-                    .extension()
-                    .map_or(false, |ext| ext == "yml" || ext == "yaml")
-                    && path.to_string_lossy().contains("pipeline")
-                    && tx.blocking_send(path).is_err()
-                {
-                    break; // Channel closed, receiver dropped
-                }
-            }
-        }
-    });
-
-    // Drop the sender to ensure the channel closes properly
-    drop(tx);
-
-    // Collect all paths
-    let mut pipeline_files = Vec::new();
-    while let Some(path) = rx.recv().await {
-        pipeline_files.push(path);
-    }
+    let repo_path = repo_path.to_path_buf();
+    // Walk the directory on the blocking thread pool
+    let files = tokio::task::spawn_blocking(move || {
+        platform::PLATFORMS
+            .iter()
+            .flat_map(|platform| platform.find_files(&repo_path))
+            .collect()
+    })
+    .await?;
+    Ok(files)
+}
 
-    Ok(pipeline_files)
+/// Reads every pipeline in a checkout on every platform, following templates and
+/// includes into other files and repositories. Files that cannot be parsed are
+/// reported and skipped. Checkouts where nothing changed since the last run are
+/// not parsed again.
+async fn collect_pipeline_tasks(
+    resolver: &TemplateResolver,
+    cache: &ParseCache,
+    repo_url: &str,
+    repo_path: &Path,
+) -> Result<Vec<PipelineTasks>> {
+    let pipelines = cache
+        .pipelines(repo_url, repo_path, platform::PLATFORMS, resolver)
+        .await;
+    Ok(pipelines)
 }
 
-async fn search_in_pipelines_concurrent(repos: &[String], query: &str) -> Result<()> {
+async fn search_in_pipelines_concurrent(
+    db: &Database,
+    repos: &[String],
+    query: &str,
+    sync_mode: SyncMode,
+) -> Result<()> {
     let max_concurrent = std::thread::available_parallelism()
         .map(|n| n.get())
         .unwrap_or(4);
     let semaphore = Arc::new(Semaphore::new(max_concurrent));
-    let db = Database::new()?;
-    ensure_all_repos_exist(&db, false).await?;
+    ensure_all_repos_exist(db, repos, sync_mode).await?;
 
     // Create a channel for results
     let (tx, mut rx) = tokio::sync::mpsc::channel(repos.len());
@@ -169,13 +259,11 @@ async fn search_in_pipelines_concurrent(repos: &[String], query: &str) -> Result
         let query = query.to_string();
         let permit = semaphore.clone().acquire_owned().await?;
         let tx = tx.clone();
-        let db = Database::new()?;
+        let repo_path = db.get_local_path(&repo);
 
         tokio::spawn(async move {
             let _permit = permit; // Hold the permit for the duration of this task
 
-            // Only change: Get local path from URL
-            let repo_path = db.get_local_path(&repo);
             let repo_name = repo.clone();
 
             match async {
@@ -261,103 +349,58 @@ async fn search_in_pipelines_concurrent(repos: &[String], query: &str) -> Result
 pub fn parse_task_name(name: &str) -> Result<SupportedTask> {
     if name.eq_ignore_ascii_case("gitversion") {
         Ok(SupportedTask::Gitversion)
+    } else if let Some(action) = name.strip_prefix(workflow::TASK_PREFIX) {
+        Ok(SupportedTask::GithubAction(action.to_string()))
+    } else if let Some(reference) = name.strip_prefix(gitlab::TASK_PREFIX) {
+        Ok(SupportedTask::Gitlab(reference.to_string()))
     } else {
         Ok(SupportedTask::Default(name.to_string()))
     }
 }
 
-async fn search_gitversion_tasks(repos: &[String], _verbose: bool) -> Result<()> {
-    let db = Database::new()?;
-    ensure_all_repos_exist(&db, false).await?;
+async fn search_gitversion_tasks(
+    db: &Database,
+    repos: &[String],
+    _verbose: bool,
+    sync_mode: SyncMode,
+) -> Result<()> {
+    ensure_all_repos_exist(db, repos, sync_mode).await?;
     let valid_states = db.list_valid_states(&SupportedTask::Gitversion)?;
     let valid_states: Vec<GitVersionState> = valid_states
         .into_iter()
         .map(|state| match state {
             TaskValidState::Gitversion(gv) => gv,
-            TaskValidState::Default(_) => panic!("Expected GitVersion state, got Default state"),
+            other => panic!("Expected GitVersion state, got {:?}", other),
         })
         .collect();
 
+    let resolver = TemplateResolver::from_database(db)?;
+    let cache = ParseCache::load(db)?;
     let mut any_invalid = false;
     println!("\nChecking GitVersion implementations:");
     println!("{}", "-".repeat(60));
 
     for repo_url in repos {
         let repo_path = db.get_local_path(repo_url);
-        let repo_name = repo_url
-            .split('/')
-            .last()
-            .unwrap_or(repo_url)
-            .trim_end_matches(".git");
+        let repo_name = RepoId::parse(repo_url).full_name();
 
-        let pipeline_files = find_pipeline_files(&repo_path).await?;
+        let pipelines = collect_pipeline_tasks(&resolver, &cache, repo_url, &repo_path).await?;
         let mut implementations = Vec::new();
 
-        // Group setup and execute tasks per file
-        for file in pipeline_files {
-            let content = std::fs::read_to_string(&file)
-                .with_context(|| format!("Failed to read {}", file.display()))?;
-
-            let lines: Vec<&str> = content.lines().collect();
+        // Group setup and execute tasks per pipeline
+        for pipeline in pipelines {
             let mut current_impl = GitVersionImplementation {
                 setup: None,
                 execute: None,
-                file_path: file.clone(), // Store the file path
+                file_path: pipeline.pipeline.clone(), // Store the file path
             };
 
-            for (i, line) in lines.iter().enumerate() {
-                let line_trimmed = line.trim();
-                if line_trimmed.starts_with('#') {
-                    continue;
-                }
-
-                if line_trimmed.contains("task: gitversion/") {
-                    let task_name = line_trimmed
-                        .split('@')
-                        .next()
-                        .unwrap_or("")
-                        .split(':')
-                        .nth(1)
-                        .unwrap_or("")
-                        .trim();
-                    let version = line_trimmed
-                        .split('@')
-                        .nth(1)
-                        .unwrap_or("")
-                        .trim()
-                        .to_string();
-
-                    // Look ahead for versionSpec
-                    let mut version_spec = None;
-                    for next_line in lines.iter().skip(i + 1).take(10) {
-                        let next_trimmed = next_line.trim();
-                        if next_trimmed.contains("versionSpec:") {
-                            version_spec = Some(
-                                next_trimmed
-                                    .split(':')
-                                    .nth(1)
-                                    .unwrap_or("")
-                                    .trim()
-                                    .trim_matches('\'')
-                                    .trim_matches('"')
-                                    .to_string(),
-                            );
-                            break;
-                        }
-                        if next_trimmed.contains("task:") {
-                            break;
-                        }
-                    }
-
-                    match task_name {
-                        "gitversion/setup" => {
-                            current_impl.setup = Some((version, version_spec));
-                        }
-                        "gitversion/execute" => {
-                            current_impl.execute = Some(version);
-                        }
-                        _ => {}
-                    }
+            for ResolvedTask { task, .. } in pipeline.tasks {
+                if task.name.eq_ignore_ascii_case("gitversion/setup") {
+                    let version_spec = task.inputs.get("versionSpec").cloned();
+                    current_impl.setup = Some((task.version, version_spec));
+                } else if task.name.eq_ignore_ascii_case("gitversion/execute") {
+                    current_impl.execute = Some(task.version);
                 }
             }
 
@@ -373,15 +416,16 @@ async fn search_gitversion_tasks(repos: &[String], _verbose: bool) -> Result<()>
 
                 // Validate against valid states
                 for state in &valid_states {
-                    let setup_matches = impl_.setup.as_ref().map_or(false, |(version, spec)| {
-                        version == &state.setup_version
-                            && spec.as_ref().map_or(false, |s| s == &state.spec_version)
+                    let setup_matches = impl_.setup.as_ref().is_some_and(|(version, spec)| {
+                        version_spec::matches(&state.setup_version, version)
+                            && spec
+                                .as_ref()
+                                .is_some_and(|s| version_spec::matches(&state.spec_version, s))
                     });
 
-                    let execute_matches = impl_
-                        .execute
-                        .as_ref()
-                        .map_or(false, |version| version == &state.execute_version);
+                    let execute_matches = impl_.execute.as_ref().is_some_and(|version| {
+                        version_spec::matches(&state.execute_version, version)
+                    });
 
                     if setup_matches && execute_matches {
                         is_valid = true;
@@ -445,6 +489,7 @@ async fn search_gitversion_tasks(repos: &[String], _verbose: bool) -> Result<()>
             }
         }
     }
+    cache.save(db)?;
 
     // If any implementations were invalid, show valid states at the bottom
     if any_invalid {
@@ -461,55 +506,48 @@ async fn search_gitversion_tasks(repos: &[String], _verbose: bool) -> Result<()>
 }
 
 pub(crate) async fn check_all_task_implementations(
+    db: &Database,
     repos: &[String],
     issues: Option<&mut TaskIssues>,
-    no_update: bool,
+    sync_mode: SyncMode,
 ) -> Result<TaskIssues> {
-    let db = Database::new()?;
     let mut local_issues = TaskIssues::default();
     let issues_ref = issues.unwrap_or(&mut local_issues);
 
     // First ensure all repos exist locally
-    ensure_all_repos_exist(&db, no_update).await?;
+    ensure_all_repos_exist(db, repos, sync_mode).await?;
 
     // First, collect all tasks from all repositories
+    let resolver = TemplateResolver::from_database(db)?;
+    let cache = ParseCache::load(db)?;
     let mut task_implementations: HashMap<String, Vec<TaskImplementation>> = HashMap::new();
+    let mut pipelines = Vec::new();
 
     for repo_url in repos {
         let repo_path = db.get_local_path(repo_url);
-        let repo_name = repo_url.split('/').last().unwrap_or(repo_url);
-
-        let pipeline_files = find_pipeline_files(&repo_path).await?;
-
-        for pipeline_file in pipeline_files {
-            let content = std::fs::read_to_string(&pipeline_file)?;
-
-            // Regular expression to match task definitions
-            let task_regex = Regex::new(r#"task:\s*([\w/]+)@(\d+)"#)?;
-
-            let lines: Vec<&str> = content
-                .lines()
-                .map(|line| line.trim())
-                .filter(|line| !line.starts_with('#') && !line.starts_with("//"))
-                .collect();
-
-            for line in lines {
-                if let Some(cap) = task_regex.captures(line) {
-                    let task_name = cap[1].to_string();
-                    let task_version = cap[2].to_string();
-
-                    task_implementations
-                        .entry(task_name)
-                        .or_default()
-                        .push(TaskImplementation {
-                            repo_name: repo_name.to_string(),
-                            version: task_version,
-                            file_path: pipeline_file.clone(),
-                        });
-                }
+        let repo_name = RepoId::parse(repo_url).full_name();
+
+        for pipeline in collect_pipeline_tasks(&resolver, &cache, repo_url, &repo_path).await? {
+            pipelines.push(PipelineSummary {
+                repo_url: repo_url.clone(),
+                repo_name: repo_name.clone(),
+                pipeline: pipeline.pipeline.clone(),
+                tasks: pipeline.tasks.iter().map(|r| r.task.name.clone()).collect(),
+            });
+            for resolved in pipeline.tasks {
+                task_implementations
+                    .entry(gitversion::task_key(&resolved.task.name))
+                    .or_default()
+                    .push(TaskImplementation::new(
+                        repo_url,
+                        &repo_name,
+                        &pipeline.pipeline,
+                        resolved,
+                    ));
             }
         }
     }
+    cache.save(db)?;
 
     // Sort task names for consistent output
     let mut task_names: Vec<_> = task_implementations.keys().collect();
@@ -525,229 +563,91 @@ pub(crate) async fn check_all_task_implementations(
             .all_implementations
             .insert(task_name.clone(), implementations.clone());
 
-        // Special handling for GitVersion tasks
-        if task_name == "gitversion/setup" || task_name == "gitversion/execute" {
-            // Get valid states from database
-            let valid_states = db.list_valid_states(&SupportedTask::Gitversion)?;
-            let valid_states: Vec<GitVersionState> = valid_states
-                .into_iter()
-                .map(|state| match state {
-                    TaskValidState::Gitversion(gv) => gv,
-                    TaskValidState::Default(_) => {
-                        panic!("Expected GitVersion state, got Default state")
-                    }
-                })
-                .collect();
-            // Group implementations by repo to collect both setup and execute
-            let mut repo_implementations: HashMap<String, Vec<(&TaskImplementation, &str)>> =
-                HashMap::new();
-
-            // Get both setup and execute implementations
-            let empty_vec = Vec::new();
-            let setup_impls = task_implementations
-                .get("gitversion/setup")
-                .unwrap_or(&empty_vec);
-            let execute_impls = task_implementations
-                .get("gitversion/execute")
-                .unwrap_or(&empty_vec);
-
-            // Combine both sets of implementations
-            for impl_ in setup_impls {
-                repo_implementations
-                    .entry(impl_.repo_name.clone())
-                    .or_default()
-                    .push((impl_, "gitversion/setup"));
-            }
+        // Tasks whose states span several tasks, such as GitVersion
+        let platform = platform::for_task(task_name);
+        if platform.check_grouped(task_name, &task_implementations, db, issues_ref)? {
+            continue;
+        }
 
-            for impl_ in execute_impls {
-                repo_implementations
-                    .entry(impl_.repo_name.clone())
-                    .or_default()
-                    .push((impl_, "gitversion/execute"));
-            }
-            // Sort repos for consistent output
-            let mut repo_names: Vec<_> = repo_implementations.keys().collect();
-            repo_names.sort();
-            for repo_name in &repo_names {
-                let impls = repo_implementations.get(*repo_name).unwrap();
-
-                // Find matching setup and execute implementations
-                let mut setup_version = None;
-                let mut execute_version = None;
-                let mut spec_version = None;
-                let mut file_path = None;
-                for (impl_, task_type) in impls {
-                    match *task_type {
-                        "gitversion/setup" => {
-                            setup_version = Some(impl_.version.clone());
-                            file_path = Some(impl_.file_path.clone());
-
-                            // Extract spec version from file content
-                            if let Ok(content) = std::fs::read_to_string(&impl_.file_path) {
-                                let lines: Vec<&str> = content.lines().collect();
-                                for (i, line) in lines.iter().enumerate() {
-                                    if line.contains("task: gitversion/setup") {
-                                        // Look ahead for versionSpec
-                                        for next_line in lines.iter().skip(i + 1).take(10) {
-                                            let next_trimmed = next_line.trim();
-                                            if next_trimmed.contains("versionSpec:") {
-                                                spec_version = Some(
-                                                    next_trimmed
-                                                        .split(':')
-                                                        .nth(1)
-                                                        .unwrap_or("")
-                                                        .trim()
-                                                        .trim_matches('\'')
-                                                        .trim_matches('"')
-                                                        .to_string(),
-                                                );
-                                                break;
-                                            }
-                                            if next_trimmed.contains("task:") {
-                                                break;
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        "gitversion/execute" => {
-                            execute_version = Some(impl_.version.clone());
-                        }
-                        _ => {}
-                    }
-                }
-                // Validate against valid states
-                let mut is_valid = false;
-                for state in &valid_states {
-                    if setup_version
-                        .as_ref()
-                        .map_or(false, |v| v == &state.setup_version)
-                        && execute_version
-                            .as_ref()
-                            .map_or(false, |v| v == &state.execute_version)
-                        && spec_version
-                            .as_ref()
-                            .map_or(false, |s| s == &state.spec_version)
-                    {
-                        is_valid = true;
-                        break;
-                    }
-                }
-                let status = if is_valid { "✓" } else { "✗" };
-                let path_info = if let Some(path) = &file_path {
-                    format!(
-                        " ({})",
-                        path.strip_prefix(repo_name).unwrap_or(path).display()
-                    )
-                } else {
-                    String::new()
-                };
-                let default_str = "?".to_string();
-                println!(
-                    "{} {:<25} {} | {} | {}{}",
-                    status,
-                    repo_name,
-                    setup_version.as_ref().unwrap_or(&default_str),
-                    execute_version.unwrap_or_else(|| "?".to_string()),
-                    spec_version.unwrap_or_else(|| "?".to_string()),
-                    path_info
-                );
-                // Track invalid states
-                if !is_valid {
-                    issues_ref
-                        .invalid_states
-                        .entry(task_name.to_string())
-                        .or_default()
-                        .entry(repo_name.to_string())
-                        .or_default()
-                        .push(TaskImplementation {
-                            repo_name: repo_name.to_string(),
-                            version: setup_version.unwrap_or_else(|| "?".to_string()),
-                            file_path: file_path.clone().unwrap_or_default(),
-                        });
-                }
-            }
-            // Show valid states if we're processing setup tasks
-            if task_name == "gitversion/setup" {
-                println!("\nValid states:");
-                for state in &valid_states {
-                    println!(
-                        "  - setup@{} | execute@{} | spec@{}",
-                        state.setup_version, state.execute_version, state.spec_version
-                    );
-                }
-            }
-        } else {
-            // Handle other tasks
-            let task = SupportedTask::Default(task_name.clone());
-            let valid_states = db.list_valid_states(&task)?;
+        // Handle other tasks
+        let task = parse_task_name(task_name)?;
+        let valid_states = db.list_valid_states(&task)?;
 
-            if valid_states.is_empty() {
-                issues_ref.missing_states.insert(task_name.clone());
-            }
+        if valid_states.is_empty() {
+            issues_ref.missing_states.insert(task_name.clone());
+        }
 
-            for implementation in implementations {
-                let is_valid = valid_states.iter().any(|state| {
-                    matches!(state, TaskValidState::Default(v) if v == &implementation.version)
-                });
+        for implementation in implementations {
+            let is_valid = valid_states
+                .iter()
+                .any(|state| platform.version_matches(state, &implementation.version));
 
-                if !is_valid {
-                    issues_ref
-                        .invalid_states
-                        .entry(task_name.to_string())
-                        .or_default()
-                        .entry(implementation.repo_name.clone())
-                        .or_default()
-                        .push(implementation.clone());
-                }
+            let status = if is_valid { "✓" } else { "✗" };
+            println!(
+                "{} {:<25} @{} ({}{})",
+                status,
+                implementation.repo_name,
+                implementation.version,
+                implementation.file_path.display(),
+                format_include_chain(&implementation.include_chain, None)
+            );
+
+            if !is_valid {
+                issues_ref
+                    .invalid_states
+                    .entry(task_name.to_string())
+                    .or_default()
+                    .entry(implementation.repo_name.clone())
+                    .or_default()
+                    .push(implementation.clone());
             }
         }
     }
 
+    let policies = db.task_policies()?;
+    if !policies.is_empty() {
+        println!("\nChecking task policies:");
+        println!("------------------------------------------------------------");
+        let today = chrono::Local::now().date_naive();
+        issues_ref.policy_violations = policies.check(&task_implementations, &pipelines, today);
+        if issues_ref.policy_violations.is_empty() {
+            println!("✓ Every pipeline follows the task policies");
+        }
+        for violation in &issues_ref.policy_violations {
+            println!(
+                "✗ {:<7} {:<25} {} ({})",
+                violation.severity,
+                violation.repo_name,
+                violation.message,
+                violation.location()
+            );
+        }
+    }
+
     Ok(local_issues)
 }
 
-async fn collect_task_usage(repos: &[String]) -> Result<()> {
+async fn collect_task_usage(db: &Database, repos: &[String]) -> Result<()> {
     let mut task_map: HashMap<String, HashMap<String, Vec<(String, PathBuf)>>> = HashMap::new();
+    let resolver = TemplateResolver::from_database(db)?;
+    let cache = ParseCache::load(db)?;
 
     for repo_url in repos {
-        let db = Database::new()?;
         let repo_path = db.get_local_path(repo_url);
-        // Extract just the repository name from the URL
-        let repo_name = repo_url
-            .split('/')
-            .last()
-            .unwrap_or(repo_url)
-            .trim_end_matches(".git");
-
-        let pipeline_files = find_pipeline_files(&repo_path).await?;
-
-        for file in pipeline_files {
-            let content = std::fs::read_to_string(&file)?;
-            let lines: Vec<&str> = content
-                .lines()
-                .map(|line| line.trim())
-                .filter(|line| !line.starts_with('#') && !line.starts_with("//"))
-                .collect();
-
-            let task_regex = Regex::new(r#"task:\s*([\w/]+)@(\d+)"#)?;
-
-            for line in lines {
-                if let Some(cap) = task_regex.captures(line) {
-                    let task_name = cap[1].to_string();
-                    let version = cap[2].to_string();
-
-                    task_map
-                        .entry(task_name)
-                        .or_default()
-                        .entry(version)
-                        .or_default()
-                        .push((repo_name.to_string(), file.clone()));
-                }
+        let repo_name = RepoId::parse(repo_url).full_name();
+
+        for pipeline in collect_pipeline_tasks(&resolver, &cache, repo_url, &repo_path).await? {
+            for ResolvedTask { task, .. } in pipeline.tasks {
+                task_map
+                    .entry(task.name)
+                    .or_default()
+                    .entry(task.version)
+                    .or_default()
+                    .push((repo_name.to_string(), pipeline.pipeline.clone()));
             }
         }
     }
+    cache.save(db)?;
 
     // Display results
     println!("Task Usage Analysis:");
@@ -802,27 +702,49 @@ async fn collect_task_usage(repos: &[String]) -> Result<()> {
     Ok(())
 }
 
-async fn ensure_all_repos_exist(db: &Database, skip_update: bool) -> Result<()> {
-    let credentials = db
-        .get_git_credentials()?
-        .ok_or_else(|| anyhow::anyhow!("Git credentials not found"))?;
-
-    let temp_dir = std::env::current_dir()?.join("temp_repos");
-    tokio::fs::create_dir_all(&temp_dir).await?;
+/// Clones or updates the checkouts of `repos`.
+async fn ensure_all_repos_exist(
+    db: &Database,
+    repos: &[String],
+    sync_mode: SyncMode,
+) -> Result<()> {
+    if sync_mode != SyncMode::DryRun {
+        tokio::fs::create_dir_all(db.workspace().checkouts_root()).await?;
+    }
 
     let semaphore = Arc::new(Semaphore::new(4));
     let mut handles = Vec::new();
 
-    for repo_url in db.list_repositories()? {
+    for repo_url in repos {
+        let repo_url = repo_url.clone();
+        let host = db.repository_host(&repo_url)?;
         let permit = semaphore.clone().acquire_owned().await?;
-        let creds = (credentials.0.clone(), credentials.1.clone());
+        let legacy_dir = db.workspace().legacy_checkout_dir(&repo_url);
+        let checked_out = db.workspace().checkout_dir(&repo_url).exists();
+        // Reading a checkout needs no credentials, only cloning and pulling do
+        let anonymous = GitManager::new(
+            db.workspace(),
+            String::new(),
+            String::new(),
+            &repo_url,
+            host,
+        );
+        let authenticated = db.git_credentials(&repo_url).map(|credentials| {
+            GitManager::with_credentials(db.workspace(), &credentials, &repo_url, host)
+        });
 
         handles.push(tokio::spawn(async move {
-            let git_manager = GitManager::new(creds.0, creds.1, &repo_url);
-            let result = if skip_update {
-                git_manager.ensure_repo_exists_no_update().await
+            let adopted = sync_mode != SyncMode::DryRun
+                && anonymous.adopt_legacy_checkout(&legacy_dir).await?;
+            let git_manager = if sync_mode != SyncMode::Update && (checked_out || adopted) {
+                anonymous
             } else {
-                git_manager.ensure_repo_exists().await
+                authenticated?
+            };
+            let result = match sync_mode {
+                SyncMode::Update => git_manager.ensure_repo_exists().await,
+                SyncMode::NoUpdate => git_manager.ensure_repo_exists_no_update().await,
+                SyncMode::DryRun => git_manager.ensure_repo_exists_dry_run(false).await,
             };
             drop(permit);
             result
@@ -836,39 +758,53 @@ async fn ensure_all_repos_exist(db: &Database, skip_update: bool) -> Result<()>
     Ok(())
 }
 
-pub async fn search_default_task(repos: &[String], task_name: &str, verbose: bool) -> Result<()> {
-    let db = Database::new()?;
-    ensure_all_repos_exist(&db, false).await?;
-    let valid_states = db.list_valid_states(&SupportedTask::Default(task_name.to_string()))?;
+pub async fn search_default_task(
+    db: &Database,
+    repos: &[String],
+    task_name: &str,
+    verbose: bool,
+    sync_mode: SyncMode,
+) -> Result<()> {
+    ensure_all_repos_exist(db, repos, sync_mode).await?;
+    let valid_states = db.list_valid_states(&parse_task_name(task_name)?)?;
+    let platform = platform::for_task(task_name);
+
+    let resolver = TemplateResolver::from_database(db)?;
+    let cache = ParseCache::load(db)?;
 
     println!("\nChecking {} implementations:", task_name);
     println!("{}", "-".repeat(60));
 
     for repo_url in repos {
         let repo_path = db.get_local_path(repo_url);
-        let pipeline_files = find_pipeline_files(&repo_path).await?;
 
-        for file in pipeline_files {
-            let content = std::fs::read_to_string(&file)?;
-            let task_regex = Regex::new(&format!(r#"task:\s*{}\s*@(\d+)"#, task_name))?;
+        for pipeline in collect_pipeline_tasks(&resolver, &cache, repo_url, &repo_path).await? {
+            let file = &pipeline.pipeline;
 
-            for cap in task_regex.captures_iter(&content) {
-                let version = cap[1].to_string();
+            for resolved in pipeline
+                .tasks
+                .iter()
+                .filter(|resolved| resolved.task.name.eq_ignore_ascii_case(task_name))
+            {
+                let version = &resolved.task.version;
                 let is_valid = valid_states
                     .iter()
-                    .any(|state| matches!(state, TaskValidState::Default(v) if v == &version));
+                    .any(|state| platform.version_matches(state, version));
 
                 let status = if is_valid { "✓" } else { "✗" };
                 let path_info = file
                     .strip_prefix(&repo_path)
                     .map_or_else(|_| file.display().to_string(), |p| p.display().to_string());
 
+                let via = format_include_chain(&resolved.include_chain, Some(&repo_path));
+
                 println!(
-                    "{} {:<25} @{} ({})",
+                    "{} {:<25} @{} ({}{})",
                     status,
-                    repo_url.split('/').last().unwrap_or(repo_url),
+                    RepoId::parse(repo_url).full_name(),
                     version,
-                    path_info
+                    path_info,
+                    via
                 );
 
                 if verbose {
@@ -878,6 +814,7 @@ pub async fn search_default_task(repos: &[String], task_name: &str, verbose: boo
                             .iter()
                             .filter_map(|s| match s {
                                 TaskValidState::Default(v) => Some(v),
+                                TaskValidState::GitRef(git_ref) => Some(git_ref.name()),
                                 _ => None,
                             })
                             .collect::<Vec<_>>()
@@ -886,6 +823,7 @@ pub async fn search_default_task(repos: &[String], task_name: &str, verbose: boo
             }
         }
     }
+    cache.save(db)?;
 
     Ok(())
 }
@@ -895,40 +833,40 @@ pub struct TaskIssues {
     pub missing_states: HashSet<String>,
     pub invalid_states: HashMap<String, HashMap<String, Vec<TaskImplementation>>>,
     pub all_implementations: HashMap<String, Vec<TaskImplementation>>,
+    /// Uses of forbidden or deprecated tasks and pipelines missing a required
+    /// one, errors first.
+    pub policy_violations: Vec<PolicyViolation>,
 }
 
 async fn collect_task_usage_data(
+    db: &Database,
     repos: &[String],
 ) -> Result<HashMap<String, HashMap<String, HashMap<String, Vec<PathBuf>>>>> {
     let mut handles = Vec::new();
+    let resolver = Arc::new(TemplateResolver::from_database(db)?);
+    let cache = Arc::new(ParseCache::load(db)?);
 
     for repo_url in repos {
         let repo_url = repo_url.clone();
-        let db = Database::new()?;
+        let repo_path = db.get_local_path(&repo_url);
+        let resolver = resolver.clone();
+        let cache = cache.clone();
 
         let handle = tokio::spawn(async move {
             let mut repo_task_map = HashMap::new();
-            let repo_path = db.get_local_path(&repo_url);
-            let repo_name = repo_url.split('/').last().unwrap_or(&repo_url);
-
-            let pipeline_files = find_pipeline_files(&repo_path).await?;
-
-            for file in pipeline_files {
-                let content = std::fs::read_to_string(&file)?;
-                let task_regex = Regex::new(r#"task:\s*([\w/]+)@(\d+)"#)?;
-
-                for cap in task_regex.captures_iter(&content) {
-                    let task_name = cap[1].to_string();
-                    let version = cap[2].to_string();
+            let repo_name = RepoId::parse(&repo_url).full_name();
 
+            for pipeline in collect_pipeline_tasks(&resolver, &cache, &repo_url, &repo_path).await?
+            {
+                for ResolvedTask { task, .. } in pipeline.tasks {
                     repo_task_map
-                        .entry(task_name)
+                        .entry(task.name)
                         .or_insert_with(HashMap::new)
-                        .entry(version)
+                        .entry(task.version)
                         .or_insert_with(HashMap::new)
                         .entry(repo_name.to_string())
                         .or_insert_with(Vec::new)
-                        .push(file.clone());
+                        .push(pipeline.pipeline.clone());
                 }
             }
 
@@ -953,34 +891,47 @@ async fn collect_task_usage_data(
             }
         }
     }
+    cache.save(db)?;
 
     Ok(final_map)
 }
 
 pub trait VersionCompare {
     fn version_eq(&self, other: &str) -> bool;
+    fn version_cmp(&self, other: &str) -> std::cmp::Ordering;
+}
+
+/// Parses a version, padding `2` and `2.1` out to full semver.
+fn normalize_version(v: &str) -> Result<Version> {
+    // Convert single number to proper semver
+    let v = if v.chars().all(|c| c.is_ascii_digit()) {
+        format!("{}.0.0", v)
+    } else if v.matches('.').count() == 1 {
+        format!("{}.0", v)
+    } else {
+        v.to_string()
+    };
+    Version::parse(&v).map_err(|e| anyhow::anyhow!("Invalid version: {}", e))
 }
 
 impl VersionCompare for String {
     fn version_eq(&self, other: &str) -> bool {
-        // Normalize version strings
-        let normalize = |v: &str| -> Result<Version> {
-            // Convert single number to proper semver
-            let v = if v.chars().all(|c| c.is_ascii_digit()) {
-                format!("{}.0.0", v)
-            } else if v.matches('.').count() == 1 {
-                format!("{}.0", v)
-            } else {
-                v.to_string()
-            };
-            Version::parse(&v).map_err(|e| anyhow::anyhow!("Invalid version: {}", e))
-        };
-
-        match (normalize(self), normalize(other)) {
+        match (normalize_version(self), normalize_version(other)) {
             (Ok(v1), Ok(v2)) => v1 == v2,
             _ => self == other, // Fallback to string comparison if parsing fails
         }
     }
+
+    /// Orders versions semantically. Versions that do not parse sort before
+    /// those that do, and by string among themselves.
+    fn version_cmp(&self, other: &str) -> std::cmp::Ordering {
+        match (normalize_version(self), normalize_version(other)) {
+            (Ok(v1), Ok(v2)) => v1.cmp(&v2),
+            (Ok(_), Err(_)) => std::cmp::Ordering::Greater,
+            (Err(_), Ok(_)) => std::cmp::Ordering::Less,
+            _ => self.as_str().cmp(other),
+        }
+    }
 }
 
 // Update TaskValidState equality comparison
@@ -993,6 +944,7 @@ impl PartialEq for TaskValidState {
                     && a.spec_version.version_eq(&b.spec_version)
             }
             (TaskValidState::Default(a), TaskValidState::Default(b)) => a.version_eq(b),
+            (TaskValidState::GitRef(a), TaskValidState::GitRef(b)) => a == b,
             _ => false,
         }
     }
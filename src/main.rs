@@ -1,11 +1,17 @@
 use anyhow::Result;
 use clap::Parser;
 use gavin::cli::Cli;
-use gavin::{handle_cli_args, Database};
+use gavin::workspace::Environment;
+use gavin::{handle_cli_args, Database, Workspace};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
-    let db = Database::new()?;
+    let workspace = Workspace::resolve(
+        cli.data_dir.as_deref(),
+        cli.config_path.as_deref(),
+        Environment::Process,
+    )?;
+    let db = Database::open(&workspace)?;
     handle_cli_args(&cli, &db).await
 }
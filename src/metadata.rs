@@ -0,0 +1,112 @@
+//! Owner, tags and groups kept per repository, and selecting repositories by them.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+
+/// What is known about a repository besides its URL.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct RepoMetadata {
+    pub url: String,
+    /// The team owning the repository.
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+/// Picks repositories by `--group` and `--tag`. A repository has to be in one
+/// of the groups and carry one of the tags; an empty list allows any.
+#[derive(Debug, Clone, Default)]
+pub struct RepoSelector {
+    pub groups: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+impl RepoSelector {
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty() && self.tags.is_empty()
+    }
+
+    pub fn matches(&self, repo: &RepoMetadata) -> bool {
+        let any_of = |wanted: &[String], values: &[String]| {
+            wanted.is_empty()
+                || wanted
+                    .iter()
+                    .any(|w| values.iter().any(|v| v.eq_ignore_ascii_case(w)))
+        };
+        any_of(&self.groups, &repo.groups) && any_of(&self.tags, &repo.tags)
+    }
+}
+
+/// Reads metadata from a YAML list of `url`/`owner`/`tags`/`groups` entries,
+/// or from a CSV file with those columns and `;` between tags and groups.
+pub fn load(path: &Path) -> Result<Vec<RepoMetadata>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("csv") => parse_csv(&content),
+        Some("yml" | "yaml") => parse_yaml(&content),
+        _ => Err(anyhow::anyhow!(
+            "Unsupported metadata file {}. Expected .csv, .yml or .yaml",
+            path.display()
+        )),
+    }
+}
+
+pub fn parse_yaml(content: &str) -> Result<Vec<RepoMetadata>> {
+    serde_yaml::from_str(content).context("Invalid metadata YAML")
+}
+
+/// Parses CSV with a header row naming the columns; only `url` is required.
+pub fn parse_csv(content: &str) -> Result<Vec<RepoMetadata>> {
+    let mut lines = content.lines().filter(|line| !line.trim().is_empty());
+    let header: Vec<String> = lines
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Metadata CSV is empty"))?
+        .split(',')
+        .map(|column| column.trim().to_lowercase())
+        .collect();
+    let column = |name: &str| header.iter().position(|column| column == name);
+    let url_column =
+        column("url").ok_or_else(|| anyhow::anyhow!("Metadata CSV has no url column"))?;
+    let (owner_column, tags_column, groups_column) =
+        (column("owner"), column("tags"), column("groups"));
+
+    lines
+        .enumerate()
+        .map(|(i, line)| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let field = |index: Option<usize>| {
+                index
+                    .and_then(|index| fields.get(index))
+                    .copied()
+                    .unwrap_or_default()
+            };
+            let url = field(Some(url_column));
+            if url.is_empty() {
+                return Err(anyhow::anyhow!("Metadata CSV row {} has no url", i + 2));
+            }
+            Ok(RepoMetadata {
+                url: url.to_string(),
+                owner: Some(field(owner_column))
+                    .filter(|owner| !owner.is_empty())
+                    .map(str::to_string),
+                tags: split_list(field(tags_column)),
+                groups: split_list(field(groups_column)),
+            })
+        })
+        .collect()
+}
+
+/// Splits a `;` or `,` separated list, dropping empty entries.
+pub fn split_list(value: &str) -> Vec<String> {
+    value
+        .split([';', ','])
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
@@ -0,0 +1,245 @@
+//! Versioned schema changes for `gavin.db`. The database's `user_version`
+//! counts the migrations applied to it; opening it applies the rest in order.
+
+use crate::repo_id::RepoId;
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+
+type Migration = fn(&Connection) -> Result<()>;
+
+/// Every migration, oldest first. Append new ones; never edit or reorder old
+/// ones. Databases from before versioning are at 0 with part of the schema
+/// already in place, so each step must tolerate what it adds already existing.
+const MIGRATIONS: &[(&str, Migration)] = &[
+    (
+        "create repositories, credentials and valid states",
+        initial_schema,
+    ),
+    ("add campaigns", add_campaigns),
+    ("store the host of each repository", add_repository_host),
+    (
+        "add repository owners, tags and groups",
+        add_repository_metadata,
+    ),
+    (
+        "store the identity of each repository",
+        add_repository_identity,
+    ),
+    (
+        "scope credentials by host and add SSH keys",
+        add_credential_scopes,
+    ),
+    ("record scans and their findings", add_scans),
+    ("cache parsed pipelines per file blob", add_parse_cache),
+    ("add task policies", add_task_policies),
+];
+
+/// The schema version this build creates and understands.
+pub const SCHEMA_VERSION: usize = MIGRATIONS.len();
+
+pub fn schema_version(conn: &Connection) -> Result<usize> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    Ok(version as usize)
+}
+
+/// Brings the schema up to [`SCHEMA_VERSION`], one transaction per migration.
+/// Refuses databases written by a newer gavin rather than misreading them.
+pub fn migrate(conn: &mut Connection) -> Result<()> {
+    let current = schema_version(conn)?;
+    if current > SCHEMA_VERSION {
+        return Err(anyhow::anyhow!(
+            "The database is at schema version {}, but this gavin only knows up to {}. \
+             Upgrade gavin to use it",
+            current,
+            SCHEMA_VERSION
+        ));
+    }
+
+    for (version, (description, migration)) in MIGRATIONS.iter().enumerate().skip(current) {
+        let tx = conn.transaction()?;
+        migration(&tx).with_context(|| {
+            format!(
+                "Database migration {} ({}) failed",
+                version + 1,
+                description
+            )
+        })?;
+        tx.pragma_update(None, "user_version", version + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str) -> Result<()> {
+    let exists: i64 = conn.query_row(
+        &format!(
+            "SELECT COUNT(*) FROM pragma_table_info('{}') WHERE name = ?1",
+            table
+        ),
+        params![column],
+        |row| row.get(0),
+    )?;
+    if exists == 0 {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} TEXT", table, column),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+fn initial_schema(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS repositories (
+            id INTEGER PRIMARY KEY,
+            url TEXT NOT NULL UNIQUE
+        );
+        CREATE TABLE IF NOT EXISTS git_credentials (
+            id INTEGER PRIMARY KEY,
+            username TEXT NOT NULL,
+            token BLOB NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS valid_states (
+            id INTEGER PRIMARY KEY,
+            task TEXT NOT NULL,
+            state_json TEXT NOT NULL
+        );",
+    )?;
+    Ok(())
+}
+
+fn add_campaigns(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS campaigns (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            task TEXT NOT NULL,
+            target_state_json TEXT NOT NULL,
+            status TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TABLE IF NOT EXISTS campaign_repos (
+            campaign_id INTEGER NOT NULL REFERENCES campaigns(id),
+            repo_url TEXT NOT NULL,
+            status TEXT NOT NULL,
+            detail TEXT NOT NULL DEFAULT '',
+            updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (campaign_id, repo_url)
+        );",
+    )?;
+    Ok(())
+}
+
+fn add_repository_host(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "repositories", "host")
+}
+
+fn add_repository_metadata(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "repositories", "owner")?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS repository_tags (
+            repo_url TEXT NOT NULL,
+            tag TEXT NOT NULL,
+            PRIMARY KEY (repo_url, tag)
+        );
+        CREATE TABLE IF NOT EXISTS repository_groups (
+            repo_url TEXT NOT NULL,
+            group_name TEXT NOT NULL,
+            PRIMARY KEY (repo_url, group_name)
+        );",
+    )?;
+    Ok(())
+}
+
+fn add_repository_identity(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "repositories", "canonical")?;
+
+    let unidentified = conn
+        .prepare("SELECT url FROM repositories WHERE canonical IS NULL")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+    for url in unidentified {
+        conn.execute(
+            "UPDATE repositories SET canonical = ?1 WHERE url = ?2",
+            params![RepoId::parse(&url).canonical(), url],
+        )?;
+    }
+    Ok(())
+}
+
+fn add_credential_scopes(conn: &Connection) -> Result<()> {
+    for column in ["host", "path_prefix", "ssh_key"] {
+        add_column_if_missing(conn, "git_credentials", column)?;
+    }
+    Ok(())
+}
+
+fn add_scans(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS scans (
+            id INTEGER PRIMARY KEY,
+            created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TABLE IF NOT EXISTS scan_repositories (
+            scan_id INTEGER NOT NULL REFERENCES scans(id),
+            repo_url TEXT NOT NULL,
+            commit_sha TEXT,
+            PRIMARY KEY (scan_id, repo_url)
+        );
+        CREATE TABLE IF NOT EXISTS task_occurrences (
+            id INTEGER PRIMARY KEY,
+            scan_id INTEGER NOT NULL REFERENCES scans(id),
+            repo_url TEXT NOT NULL,
+            task TEXT NOT NULL,
+            version TEXT NOT NULL,
+            pipeline TEXT NOT NULL,
+            file TEXT NOT NULL,
+            line INTEGER NOT NULL,
+            valid INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS task_occurrences_by_scan ON task_occurrences (scan_id, task);
+        CREATE TABLE IF NOT EXISTS findings (
+            id INTEGER PRIMARY KEY,
+            scan_id INTEGER NOT NULL REFERENCES scans(id),
+            occurrence_id INTEGER REFERENCES task_occurrences(id),
+            repo_url TEXT NOT NULL,
+            task TEXT NOT NULL,
+            severity TEXT NOT NULL,
+            message TEXT NOT NULL
+        );",
+    )?;
+    Ok(())
+}
+
+fn add_parse_cache(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS parsed_repositories (
+            repo_url TEXT PRIMARY KEY,
+            head_sha TEXT NOT NULL,
+            context TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS parsed_files (
+            id INTEGER PRIMARY KEY,
+            repo_url TEXT NOT NULL REFERENCES parsed_repositories(repo_url),
+            platform TEXT NOT NULL,
+            path TEXT NOT NULL,
+            blob_sha TEXT NOT NULL,
+            dependencies_json TEXT NOT NULL,
+            pipelines_json TEXT NOT NULL,
+            UNIQUE (repo_url, platform, path)
+        );",
+    )?;
+    Ok(())
+}
+
+fn add_task_policies(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS task_policies (
+            id INTEGER PRIMARY KEY,
+            kind TEXT NOT NULL,
+            task TEXT NOT NULL,
+            policy_json TEXT NOT NULL
+        );",
+    )?;
+    Ok(())
+}
@@ -0,0 +1,243 @@
+//! Pipelines parsed by earlier runs, kept per file and keyed by git blob ids.
+//! A checkout whose HEAD has not moved and whose working tree is clean reuses
+//! its last parse without touching its files. Otherwise only the files whose
+//! blob, or the blob of a file they depend on, changed are parsed again, so
+//! rescanning many repositories where little has moved takes seconds.
+
+use crate::git_manager::{changed_files, head_commit, index_blobs};
+use crate::platform::CiPlatform;
+use crate::template::{PipelineTasks, TemplateResolver};
+use crate::Database;
+use anyhow::Result;
+use ring::digest;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// A checkout as it was parsed.
+#[derive(Debug, Clone)]
+pub struct ParsedRepository {
+    pub head_sha: String,
+    /// Stands for the gavin version and the known repositories, which decide
+    /// how templates resolve.
+    pub context: String,
+    /// Every file a platform found, in the order their pipelines are listed.
+    pub files: Vec<ParsedFile>,
+}
+
+/// One file a platform found, as it was parsed.
+#[derive(Debug, Clone)]
+pub struct ParsedFile {
+    pub platform: String,
+    pub path: PathBuf,
+    pub blob_sha: String,
+    /// Blob ids of the templates the pipeline read or, for a template, of the
+    /// pipelines including it. The parse is reused only while all match.
+    pub dependencies: BTreeMap<PathBuf, String>,
+    /// The pipeline read from the file, none for a template.
+    pub pipelines: Vec<PipelineTasks>,
+}
+
+pub struct ParseCache {
+    context: String,
+    parsed: HashMap<String, ParsedRepository>,
+    updated: Mutex<Vec<(String, ParsedRepository)>>,
+}
+
+impl ParseCache {
+    pub fn load(db: &Database) -> Result<Self> {
+        let context = format!(
+            "{}\n{}",
+            env!("CARGO_PKG_VERSION"),
+            db.list_repositories()?.join("\n")
+        );
+        Ok(ParseCache {
+            context: blob_sha(context.as_bytes()),
+            parsed: db.parsed_repositories()?,
+            updated: Mutex::default(),
+        })
+    }
+
+    /// The pipelines `platforms` find in a checkout, reusing the last parse of
+    /// every file nothing changed for. Checkouts that are not git repositories
+    /// are always parsed.
+    pub async fn pipelines(
+        &self,
+        repo_url: &str,
+        repo_path: &Path,
+        platforms: &[&dyn CiPlatform],
+        resolver: &TemplateResolver,
+    ) -> Vec<PipelineTasks> {
+        let (Some(head_sha), Some(changed)) =
+            (head_commit(repo_path).await, changed_files(repo_path).await)
+        else {
+            return platforms
+                .iter()
+                .flat_map(|platform| {
+                    platform.pipeline_tasks(resolver, repo_path, &platform.find_files(repo_path))
+                })
+                .collect();
+        };
+        let previous = self
+            .parsed
+            .get(repo_url)
+            .filter(|parsed| parsed.context == self.context);
+
+        // Same commit and a clean tree: only templates from other checkouts can differ
+        if let Some(previous) = previous.filter(|parsed| parsed.head_sha == head_sha) {
+            let outside = Blobs::default();
+            let unchanged = changed.is_empty()
+                && previous.files.iter().all(|file| {
+                    file.dependencies
+                        .iter()
+                        .filter(|(path, _)| !path.starts_with(repo_path))
+                        .all(|(path, sha)| outside.get(path).as_ref() == Some(sha))
+                });
+            if unchanged {
+                return previous
+                    .files
+                    .iter()
+                    .flat_map(|file| file.pipelines.clone())
+                    .collect();
+            }
+        }
+
+        let mut blobs = Blobs {
+            index: index_blobs(repo_path).await.unwrap_or_default(),
+        };
+        // Changed files are hashed from the working tree instead
+        for path in &changed {
+            blobs.index.remove(path);
+        }
+
+        let mut pipelines = Vec::new();
+        let mut files = Vec::new();
+        for platform in platforms {
+            let mut found = platform.find_files(repo_path);
+            found.sort();
+
+            let mut reused = Vec::new();
+            let mut stale = Vec::new();
+            for path in &found {
+                let cached = previous
+                    .and_then(|parsed| {
+                        parsed
+                            .files
+                            .iter()
+                            .find(|file| file.platform == platform.name() && file.path == *path)
+                    })
+                    .filter(|file| blobs.unchanged(file));
+                match cached {
+                    Some(file) => reused.extend(file.pipelines.iter().cloned()),
+                    None => stale.push(path.clone()),
+                }
+            }
+
+            let mut parsed = platform.pipeline_tasks(resolver, repo_path, &stale);
+            // A pipeline that a new or changed file now includes is a template
+            reused.retain(|pipeline| {
+                !parsed
+                    .iter()
+                    .any(|other| other.files.contains(&pipeline.pipeline))
+            });
+            parsed.extend(reused);
+            parsed.sort_by(|a, b| a.pipeline.cmp(&b.pipeline));
+
+            for path in found {
+                let pipeline = parsed.iter().find(|pipeline| pipeline.pipeline == path);
+                let dependencies: Vec<&PathBuf> = match pipeline {
+                    Some(pipeline) => pipeline
+                        .files
+                        .iter()
+                        .filter(|file| **file != path)
+                        .collect(),
+                    None => parsed
+                        .iter()
+                        .filter(|other| other.files.contains(&path))
+                        .map(|other| &other.pipeline)
+                        .collect(),
+                };
+                // Files that failed to parse, and templates nothing includes,
+                // are parsed again next time
+                if pipeline.is_none() && dependencies.is_empty() {
+                    continue;
+                }
+                let Some(blob_sha) = blobs.get(&path) else {
+                    continue;
+                };
+                files.push(ParsedFile {
+                    platform: platform.name().to_string(),
+                    blob_sha,
+                    dependencies: dependencies
+                        .into_iter()
+                        .filter_map(|file| Some((file.clone(), blobs.get(file)?)))
+                        .collect(),
+                    pipelines: pipeline.into_iter().cloned().collect(),
+                    path,
+                });
+            }
+            pipelines.extend(parsed);
+        }
+
+        if let Ok(mut updated) = self.updated.lock() {
+            updated.push((
+                repo_url.to_string(),
+                ParsedRepository {
+                    head_sha,
+                    context: self.context.clone(),
+                    files,
+                },
+            ));
+        }
+        pipelines
+    }
+
+    /// Stores the parses made since [`ParseCache::load`] for the next run.
+    pub fn save(&self, db: &Database) -> Result<()> {
+        let updated = std::mem::take(
+            &mut *self
+                .updated
+                .lock()
+                .map_err(|_| anyhow::anyhow!("Parse cache lock poisoned"))?,
+        );
+        db.save_parsed_repositories(&updated)
+    }
+}
+
+/// Blob ids of the files in a checkout: git's own for files that match the
+/// index, and hashed from disk for the rest.
+#[derive(Default)]
+struct Blobs {
+    index: HashMap<PathBuf, String>,
+}
+
+impl Blobs {
+    /// `None` for files that cannot be read, so a deleted file never matches.
+    fn get(&self, path: &Path) -> Option<String> {
+        match self.index.get(path) {
+            Some(sha) => Some(sha.clone()),
+            None => std::fs::read(path).ok().map(|content| blob_sha(&content)),
+        }
+    }
+
+    fn unchanged(&self, file: &ParsedFile) -> bool {
+        self.get(&file.path).as_ref() == Some(&file.blob_sha)
+            && file
+                .dependencies
+                .iter()
+                .all(|(path, sha)| self.get(path).as_ref() == Some(sha))
+    }
+}
+
+/// The id git gives a blob with this content.
+pub fn blob_sha(content: &[u8]) -> String {
+    let mut context = digest::Context::new(&digest::SHA1_FOR_LEGACY_USE_ONLY);
+    context.update(format!("blob {}\0", content.len()).as_bytes());
+    context.update(content);
+    context
+        .finish()
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
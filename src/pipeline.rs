@@ -0,0 +1,659 @@
+//! Typed model of Azure Pipelines YAML and the shared step extraction API.
+//!
+//! The model is deserialized with `serde_yaml`, so quoting, flow mappings, block
+//! scalars and anchors are resolved by a real YAML parser. `serde_yaml` does not
+//! expose source positions, so those are recovered from a marked event stream and
+//! matched back to the model by node path.
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::Marker;
+
+/// Keys that mark a step as an inline script rather than a task.
+const SCRIPT_KEYS: [&str; 4] = ["script", "bash", "pwsh", "powershell"];
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Pipeline {
+    #[serde(default, deserialize_with = "lenient")]
+    pub resources: Option<Resources>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub extends: Option<TemplateReference>,
+    #[serde(default, deserialize_with = "entries")]
+    pub stages: Vec<Entry<Stage>>,
+    #[serde(default, deserialize_with = "entries")]
+    pub jobs: Vec<Entry<Job>>,
+    #[serde(default, deserialize_with = "entries")]
+    pub steps: Vec<Entry<Step>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Resources {
+    #[serde(default, deserialize_with = "lenient")]
+    pub repositories: Vec<RepositoryResource>,
+}
+
+/// A `resources.repositories` entry, which `template: path@alias` references resolve through.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RepositoryResource {
+    pub repository: String,
+    #[serde(default, rename = "type", deserialize_with = "lenient")]
+    pub kind: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub name: Option<String>,
+    #[serde(default, rename = "ref", deserialize_with = "lenient")]
+    pub git_ref: Option<String>,
+}
+
+/// An item in a `stages`, `jobs` or `steps` list.
+#[derive(Debug, Clone)]
+pub enum Entry<T> {
+    Item(T),
+    Template(TemplateReference),
+    /// A `${{ if }}` / `${{ each }}` insertion wrapping further entries.
+    Expression {
+        expression: String,
+        entries: Vec<Entry<T>>,
+    },
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateReference {
+    pub template: String,
+    #[serde(default, deserialize_with = "lenient")]
+    pub parameters: Mapping,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stage {
+    #[serde(default, deserialize_with = "lenient")]
+    pub stage: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub display_name: Option<String>,
+    #[serde(default, deserialize_with = "entries")]
+    pub jobs: Vec<Entry<Job>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Job {
+    #[serde(default, deserialize_with = "lenient")]
+    pub job: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub deployment: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub display_name: Option<String>,
+    #[serde(default, deserialize_with = "entries")]
+    pub steps: Vec<Entry<Step>>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub strategy: Option<DeploymentStrategy>,
+}
+
+/// Deployment job strategies. Each one holds its steps in lifecycle hooks.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeploymentStrategy {
+    #[serde(default, deserialize_with = "lenient")]
+    pub run_once: Option<LifecycleHooks>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub rolling: Option<LifecycleHooks>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub canary: Option<LifecycleHooks>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LifecycleHooks {
+    #[serde(default, deserialize_with = "lenient")]
+    pub pre_deploy: Option<Hook>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub deploy: Option<Hook>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub route_traffic: Option<Hook>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub post_route_traffic: Option<Hook>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub on: Option<OutcomeHooks>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OutcomeHooks {
+    #[serde(default, deserialize_with = "lenient")]
+    pub failure: Option<Hook>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub success: Option<Hook>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Hook {
+    #[serde(default, deserialize_with = "entries")]
+    pub steps: Vec<Entry<Step>>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Step {
+    Task(TaskStep),
+    Script(ScriptStep),
+    /// `checkout`, `download`, `publish` and any other step kind.
+    Other(Mapping),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskStep {
+    pub task: String,
+    #[serde(default, deserialize_with = "lenient")]
+    pub display_name: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub condition: Option<String>,
+    #[serde(default, deserialize_with = "inputs")]
+    pub inputs: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScriptStep {
+    /// Which script key was used (`script`, `bash`, `pwsh` or `powershell`).
+    pub kind: String,
+    pub script: String,
+}
+
+/// A position in the source file. `line` and `column` are 1-based, `offset` is in bytes.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+    pub offset: usize,
+}
+
+/// A node yielded by [`Pipeline::visit`].
+#[derive(Debug, Clone, Copy)]
+pub enum Node<'a> {
+    Step(&'a Step),
+    Template(&'a TemplateReference),
+}
+
+#[derive(Debug, Clone)]
+pub struct StepOccurrence {
+    pub step: Step,
+    pub location: Location,
+}
+
+/// A task step reduced to what the checkers need. The location points at the `task:` value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskReference {
+    pub name: String,
+    pub version: String,
+    pub inputs: BTreeMap<String, String>,
+    pub location: Location,
+    /// Where each input value is written, for inputs in the same file as the task.
+    pub input_locations: BTreeMap<String, Location>,
+}
+
+impl TaskStep {
+    /// Task name without the `@version` suffix.
+    pub fn name(&self) -> &str {
+        let task = self.task.trim();
+        task.rsplit_once('@').map_or(task, |(name, _)| name).trim()
+    }
+
+    /// Major version after the `@`, if one was given.
+    pub fn version(&self) -> Option<&str> {
+        self.task
+            .trim()
+            .rsplit_once('@')
+            .map(|(_, version)| version.trim())
+    }
+}
+
+impl TaskReference {
+    /// Builds a reference from a step. `locate` maps a node path relative to the
+    /// step, such as `task` or `inputs/versionSpec`, to its location.
+    pub fn from_step(step: &TaskStep, locate: impl Fn(&str) -> Option<Location>) -> Self {
+        TaskReference {
+            name: step.name().to_string(),
+            version: step.version().unwrap_or_default().to_string(),
+            inputs: step.inputs.clone(),
+            location: locate("task").unwrap_or_default(),
+            input_locations: step
+                .inputs
+                .keys()
+                .filter_map(|key| {
+                    locate(&format!("inputs/{}", escape_key(key))).map(|l| (key.clone(), l))
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Entry<T> {
+    fn from_value(value: Value) -> Result<Self, serde_yaml::Error> {
+        if let Value::Mapping(map) = &value {
+            if let Some((Value::String(key), Value::Sequence(items))) = map.iter().next() {
+                if map.len() == 1 && is_expression(key) {
+                    return Ok(Entry::Expression {
+                        expression: key.clone(),
+                        entries: items
+                            .iter()
+                            .cloned()
+                            .filter_map(|v| Self::from_value(v).ok())
+                            .collect(),
+                    });
+                }
+            }
+            if map.contains_key("template") && !map.contains_key("task") {
+                return TemplateReference::deserialize(value).map(Entry::Template);
+            }
+        }
+        T::deserialize(value).map(Entry::Item)
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Entry<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        Entry::from_value(value).map_err(serde::de::Error::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for Step {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let map = match Value::deserialize(deserializer)? {
+            Value::Mapping(map) => map,
+            _ => return Ok(Step::Other(Mapping::new())),
+        };
+
+        if map.contains_key("task") {
+            return TaskStep::deserialize(Value::Mapping(map))
+                .map(Step::Task)
+                .map_err(serde::de::Error::custom);
+        }
+
+        for kind in SCRIPT_KEYS {
+            if let Some(script) = map.get(kind) {
+                return Ok(Step::Script(ScriptStep {
+                    kind: kind.to_string(),
+                    script: scalar_to_string(script),
+                }));
+            }
+        }
+
+        Ok(Step::Other(map))
+    }
+}
+
+pub(crate) fn is_expression(key: &str) -> bool {
+    key.trim_start().starts_with("${{")
+}
+
+/// Deserializes a list of entries, treating anything that is not a sequence
+/// (for example a `${{ parameters.steps }}` expression) as empty.
+fn entries<'de, D, T>(deserializer: D) -> Result<Vec<Entry<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Sequence(items) => items
+            .into_iter()
+            .filter_map(|v| Entry::from_value(v).ok())
+            .collect(),
+        _ => Vec::new(),
+    })
+}
+
+/// Falls back to the default when a value has an unexpected shape, so one odd
+/// field does not hide every task in the file.
+fn lenient<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned + Default,
+{
+    let value = Value::deserialize(deserializer)?;
+    Ok(T::deserialize(value).unwrap_or_default())
+}
+
+fn inputs<'de, D>(deserializer: D) -> Result<BTreeMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Mapping(map) => map
+            .iter()
+            .filter_map(|(k, v)| k.as_str().map(|k| (k.to_string(), scalar_to_string(v))))
+            .collect(),
+        _ => BTreeMap::new(),
+    })
+}
+
+pub(crate) fn scalar_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::Null => String::new(),
+        other => serde_yaml::to_string(other)
+            .unwrap_or_default()
+            .trim_end()
+            .to_string(),
+    }
+}
+
+impl Pipeline {
+    pub fn parse(content: &str) -> Result<Self> {
+        let value: Value = serde_yaml::from_str(content).context("Invalid YAML")?;
+        Self::from_value(value)
+    }
+
+    pub fn from_value(value: Value) -> Result<Self> {
+        match value {
+            Value::Mapping(_) => Ok(Pipeline::deserialize(value)?),
+            _ => Ok(Pipeline::default()),
+        }
+    }
+
+    /// Visits every step and template reference in document order along with its
+    /// node path. `extends` is visited first, at path `/extends`.
+    pub fn visit<'a>(&'a self, visit: &mut dyn FnMut(&str, Node<'a>)) {
+        if let Some(extends) = &self.extends {
+            visit("/extends", Node::Template(extends));
+        }
+        visit_entries(
+            &self.stages,
+            "/stages",
+            visit,
+            &|path, stage: &'a Stage, visit| {
+                visit_jobs(&stage.jobs, &format!("{}/jobs", path), visit)
+            },
+        );
+        visit_jobs(&self.jobs, "/jobs", visit);
+        visit_step_entries(&self.steps, "/steps", visit);
+    }
+}
+
+/// Whether a parsed YAML document is an Azure Pipelines definition or template,
+/// as opposed to a GitHub workflow, GitLab file or unrelated YAML.
+pub fn is_pipeline_document(value: &Value) -> bool {
+    let Value::Mapping(map) = value else {
+        return false;
+    };
+
+    let extends_template = map
+        .get("extends")
+        .and_then(|extends| extends.get("template"))
+        .is_some_and(Value::is_string);
+
+    extends_template
+        || ["stages", "jobs", "steps"].iter().any(|key| {
+            matches!(map.get(*key), Some(Value::Sequence(items))
+                if !items.is_empty() && items.iter().all(Value::is_mapping))
+        })
+}
+
+/// Reads a YAML file and reports whether it is an Azure Pipelines definition or template.
+pub fn is_pipeline_file(path: &Path) -> bool {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_yaml::from_str::<Value>(&content).ok())
+        .is_some_and(|value| is_pipeline_document(&value))
+}
+
+type Visitor<'v, 'a> = &'v mut dyn FnMut(&str, Node<'a>);
+
+fn visit_entries<'a, T>(
+    entries: &'a [Entry<T>],
+    path: &str,
+    visit: Visitor<'_, 'a>,
+    item: &dyn Fn(&str, &'a T, Visitor<'_, 'a>),
+) {
+    for (i, entry) in entries.iter().enumerate() {
+        let entry_path = format!("{}/{}", path, i);
+        match entry {
+            Entry::Item(value) => item(&entry_path, value, visit),
+            Entry::Template(template) => visit(&entry_path, Node::Template(template)),
+            Entry::Expression {
+                expression,
+                entries,
+            } => visit_entries(
+                entries,
+                &format!("{}/{}", entry_path, escape_key(expression)),
+                visit,
+                item,
+            ),
+        }
+    }
+}
+
+fn visit_jobs<'a>(jobs: &'a [Entry<Job>], path: &str, visit: Visitor<'_, 'a>) {
+    visit_entries(jobs, path, visit, &|path, job: &'a Job, visit| {
+        visit_step_entries(&job.steps, &format!("{}/steps", path), visit);
+
+        let Some(strategy) = &job.strategy else {
+            return;
+        };
+        for (name, hooks) in [
+            ("runOnce", &strategy.run_once),
+            ("rolling", &strategy.rolling),
+            ("canary", &strategy.canary),
+        ] {
+            let Some(hooks) = hooks else { continue };
+            let hooks_path = format!("{}/strategy/{}", path, name);
+            for (name, hook) in [
+                ("preDeploy", &hooks.pre_deploy),
+                ("deploy", &hooks.deploy),
+                ("routeTraffic", &hooks.route_traffic),
+                ("postRouteTraffic", &hooks.post_route_traffic),
+            ] {
+                if let Some(hook) = hook {
+                    let steps_path = format!("{}/{}/steps", hooks_path, name);
+                    visit_step_entries(&hook.steps, &steps_path, visit);
+                }
+            }
+            if let Some(on) = &hooks.on {
+                for (name, hook) in [("failure", &on.failure), ("success", &on.success)] {
+                    if let Some(hook) = hook {
+                        let steps_path = format!("{}/on/{}/steps", hooks_path, name);
+                        visit_step_entries(&hook.steps, &steps_path, visit);
+                    }
+                }
+            }
+        }
+    });
+}
+
+fn visit_step_entries<'a>(steps: &'a [Entry<Step>], path: &str, visit: Visitor<'_, 'a>) {
+    visit_entries(steps, path, visit, &|path, step, visit| {
+        visit(path, Node::Step(step))
+    });
+}
+
+/// Escapes a mapping key for use as a node path segment.
+pub(crate) fn escape_key(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// Extracts every step in the file with the position where it is written.
+pub fn extract_steps(content: &str) -> Result<Vec<StepOccurrence>> {
+    let pipeline = Pipeline::parse(content)?;
+    let spans = SpanIndex::build(content)?;
+
+    let mut occurrences = Vec::new();
+    pipeline.visit(&mut |path, node| {
+        let Node::Step(step) = node else {
+            return;
+        };
+        let node = match step {
+            Step::Task(_) => format!("{}/task", path),
+            _ => path.to_string(),
+        };
+        occurrences.push(StepOccurrence {
+            step: step.clone(),
+            location: spans.get(&node).unwrap_or_default(),
+        });
+    });
+
+    Ok(occurrences)
+}
+
+/// Extracts every `task:` step in the file. This is the single definition of a
+/// "task" shared by all commands.
+pub fn extract_tasks(content: &str) -> Result<Vec<TaskReference>> {
+    let pipeline = Pipeline::parse(content)?;
+    let spans = SpanIndex::build(content)?;
+
+    let mut tasks = Vec::new();
+    pipeline.visit(&mut |path, node| {
+        if let Node::Step(Step::Task(task)) = node {
+            tasks.push(TaskReference::from_step(task, |node| {
+                spans.get(&format!("{}/{}", path, node))
+            }));
+        }
+    });
+
+    Ok(tasks)
+}
+
+/// Source locations of every node in a document, keyed by node path.
+pub(crate) struct SpanIndex {
+    spans: HashMap<String, Location>,
+}
+
+impl SpanIndex {
+    pub(crate) fn build(content: &str) -> Result<Self> {
+        let mut recorder = SpanRecorder {
+            line_starts: std::iter::once(0)
+                .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+                .collect(),
+            content,
+            ..Default::default()
+        };
+        Parser::new_from_str(content)
+            .load(&mut recorder, false)
+            .map_err(|e| anyhow::anyhow!("Invalid YAML: {}", e))?;
+        Ok(SpanIndex {
+            spans: recorder.spans,
+        })
+    }
+
+    pub(crate) fn get(&self, path: &str) -> Option<Location> {
+        self.spans.get(path).copied()
+    }
+}
+
+enum Frame {
+    Sequence { path: String, next: usize },
+    Mapping { path: String, key: Option<String> },
+}
+
+#[derive(Default)]
+struct SpanRecorder<'a> {
+    content: &'a str,
+    line_starts: Vec<usize>,
+    stack: Vec<Frame>,
+    anchors: HashMap<usize, String>,
+    spans: HashMap<String, Location>,
+}
+
+impl SpanRecorder<'_> {
+    fn location(&self, mark: Marker) -> Location {
+        let line_start = self
+            .line_starts
+            .get(mark.line().saturating_sub(1))
+            .copied()
+            .unwrap_or(self.content.len());
+        let offset = self.content[line_start..]
+            .char_indices()
+            .nth(mark.col())
+            .map_or(self.content.len(), |(i, _)| line_start + i);
+        Location {
+            line: mark.line(),
+            column: mark.col() + 1,
+            offset,
+        }
+    }
+
+    /// Works out the path of the next node. Returns `None` when the node is a mapping key.
+    fn next_path(&mut self, key: Option<&str>) -> Option<String> {
+        match self.stack.last_mut() {
+            None => Some(String::new()),
+            Some(Frame::Sequence { path, next }) => {
+                *next += 1;
+                Some(format!("{}/{}", path, *next - 1))
+            }
+            Some(Frame::Mapping { path, key: pending }) => match pending.take() {
+                Some(pending) => Some(format!("{}/{}", path, escape_key(&pending))),
+                None => {
+                    // Complex keys are not addressable; give them a placeholder name.
+                    *pending = Some(key.unwrap_or("?").to_string());
+                    None
+                }
+            },
+        }
+    }
+
+    fn record(&mut self, path: &str, anchor: usize, mark: Marker) {
+        let location = self.location(mark);
+        self.spans.insert(path.to_string(), location);
+        if anchor > 0 {
+            self.anchors.insert(anchor, path.to_string());
+        }
+    }
+}
+
+impl MarkedEventReceiver for SpanRecorder<'_> {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        match event {
+            Event::Scalar(value, _, anchor, _) => {
+                if let Some(path) = self.next_path(Some(&value)) {
+                    self.record(&path, anchor, mark);
+                }
+            }
+            Event::SequenceStart(anchor, _) | Event::MappingStart(anchor, _) => {
+                let path = self.next_path(None).unwrap_or_else(|| {
+                    let parent = match self.stack.last() {
+                        Some(Frame::Sequence { path, .. } | Frame::Mapping { path, .. }) => path,
+                        None => "",
+                    };
+                    format!("{}/?", parent)
+                });
+                self.record(&path, anchor, mark);
+                self.stack
+                    .push(if matches!(event, Event::SequenceStart(..)) {
+                        Frame::Sequence { path, next: 0 }
+                    } else {
+                        Frame::Mapping { path, key: None }
+                    });
+            }
+            Event::SequenceEnd | Event::MappingEnd => {
+                self.stack.pop();
+            }
+            Event::Alias(anchor) => {
+                let Some(path) = self.next_path(None) else {
+                    return;
+                };
+                // An alias reuses the anchored node, so it shares its source locations.
+                if let Some(source) = self.anchors.get(&anchor).cloned() {
+                    let copied: Vec<_> = self
+                        .spans
+                        .iter()
+                        .filter_map(|(p, loc)| {
+                            p.strip_prefix(&source)
+                                .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+                                .map(|rest| (format!("{}{}", path, rest), *loc))
+                        })
+                        .collect();
+                    self.spans.extend(copied);
+                }
+            }
+            _ => {}
+        }
+    }
+}
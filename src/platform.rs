@@ -0,0 +1,363 @@
+//! CI platforms gavin understands. Each one knows which files it reads, how to
+//! turn them into task references, and how versions are compared against valid
+//! states, so the scanners in `lib.rs` work the same way for every platform.
+
+use crate::fixer::Fix;
+use crate::template::{PipelineTasks, ResolvedTask, TemplateResolver};
+use crate::{
+    gitlab, gitversion, pipeline, version_spec, workflow, Database, GitRef, GitVersionState,
+    SupportedTask, TaskImplementation, TaskIssues, TaskValidState, VersionCompare,
+};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+pub trait CiPlatform: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Prefix of the task names this platform produces. Empty for Azure DevOps.
+    fn task_prefix(&self) -> &'static str;
+
+    /// Sparse-checkout patterns covering every file [`CiPlatform::find_files`] can return.
+    fn sparse_patterns(&self) -> &'static [&'static str];
+
+    /// Lists the pipeline files of a checkout.
+    fn find_files(&self, repo_path: &Path) -> Vec<PathBuf>;
+
+    /// Reads `files` into pipelines and the task references each one reaches.
+    /// Files that cannot be read are reported and skipped.
+    fn pipeline_tasks(
+        &self,
+        resolver: &TemplateResolver,
+        repo_path: &Path,
+        files: &[PathBuf],
+    ) -> Vec<PipelineTasks>;
+
+    /// Parses a `--state-value` for one of this platform's tasks.
+    fn parse_state(&self, task: &SupportedTask, value: &str) -> Result<TaskValidState>;
+
+    /// Whether a version found in a pipeline satisfies a valid state.
+    fn version_matches(&self, state: &TaskValidState, version: &str) -> bool;
+
+    /// The version `--fix` moves invalid implementations to.
+    fn target_version(&self, states: &[TaskValidState]) -> Option<String>;
+
+    /// Checks tasks whose valid states span several tasks. Returns `false` for
+    /// tasks that are checked one version at a time.
+    fn check_grouped(
+        &self,
+        _task_name: &str,
+        _task_implementations: &HashMap<String, Vec<TaskImplementation>>,
+        _db: &Database,
+        _issues: &mut TaskIssues,
+    ) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Plans fixes for the tasks handled by [`CiPlatform::check_grouped`].
+    /// Returns `None` for tasks fixed one version at a time.
+    fn grouped_fixes(
+        &self,
+        _task_name: &str,
+        _issues: &TaskIssues,
+        _db: &Database,
+    ) -> Result<Option<Vec<Fix>>> {
+        Ok(None)
+    }
+}
+
+/// Every supported platform. Azure DevOps comes last as it owns unprefixed names.
+pub static PLATFORMS: &[&dyn CiPlatform] = &[&GithubActions, &GitlabCi, &AzureDevOps];
+
+/// The platform a task name belongs to.
+pub fn for_task(task_name: &str) -> &'static dyn CiPlatform {
+    PLATFORMS
+        .iter()
+        .copied()
+        .find(|platform| task_name.starts_with(platform.task_prefix()))
+        .unwrap_or(&AzureDevOps)
+}
+
+/// The platform a pipeline file belongs to, judged by where it lives.
+pub fn for_pipeline(pipeline: &Path) -> &'static dyn CiPlatform {
+    let in_workflows = pipeline
+        .parent()
+        .is_some_and(|dir| dir.ends_with(workflow::WORKFLOWS_DIR));
+    if in_workflows {
+        &GithubActions
+    } else if pipeline.file_name() == Some(gitlab::CI_FILE.as_ref()) {
+        &GitlabCi
+    } else {
+        &AzureDevOps
+    }
+}
+
+/// Sparse-checkout patterns for all platforms.
+pub fn sparse_patterns() -> Vec<&'static str> {
+    PLATFORMS
+        .iter()
+        .flat_map(|platform| platform.sparse_patterns())
+        .copied()
+        .collect()
+}
+
+/// Reads each file with `parse`, treating it as a pipeline of its own.
+fn standalone_pipelines(
+    files: &[PathBuf],
+    parse: impl Fn(&Path) -> Result<Vec<ResolvedTask>>,
+) -> Vec<PipelineTasks> {
+    files
+        .iter()
+        .filter_map(|file| match parse(file) {
+            Ok(tasks) => {
+                let mut files = vec![file.clone()];
+                for task in &tasks {
+                    if !files.contains(&task.source) {
+                        files.push(task.source.clone());
+                    }
+                }
+                Some(PipelineTasks {
+                    pipeline: file.clone(),
+                    tasks,
+                    files,
+                })
+            }
+            Err(e) => {
+                eprintln!("Skipping {}: {}", file.display(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// The newest tag among the states, otherwise the first pinned commit or branch.
+fn target_git_ref(states: &[TaskValidState]) -> Option<String> {
+    let refs: Vec<&GitRef> = states
+        .iter()
+        .filter_map(|state| match state {
+            TaskValidState::GitRef(git_ref) => Some(git_ref),
+            _ => None,
+        })
+        .collect();
+    let strip = |tag: &String| tag.trim_start_matches(['v', 'V']).to_string();
+
+    refs.iter()
+        .filter_map(|git_ref| match git_ref {
+            GitRef::Tag(tag) => Some(tag),
+            _ => None,
+        })
+        .max_by(|a, b| strip(a).version_cmp(&strip(b)))
+        .or_else(|| {
+            refs.iter()
+                .find(|r| matches!(r, GitRef::Sha(_)))
+                .map(|r| r.name())
+        })
+        .or_else(|| refs.first().map(|r| r.name()))
+        .cloned()
+}
+
+fn parse_git_ref(value: &str) -> Result<TaskValidState> {
+    GitRef::from_string(value)
+        .map(TaskValidState::GitRef)
+        .map_err(|e| anyhow::anyhow!("Invalid ref format: {}", e))
+}
+
+pub struct AzureDevOps;
+
+impl CiPlatform for AzureDevOps {
+    fn name(&self) -> &'static str {
+        "Azure DevOps"
+    }
+
+    fn task_prefix(&self) -> &'static str {
+        ""
+    }
+
+    fn sparse_patterns(&self) -> &'static [&'static str] {
+        &[
+            "*.yml",
+            "*.yaml",
+            "**/azure-pipelines.yml",
+            "**/azure-pipelines.yaml",
+            "**/*.pipeline.yml",
+            "**/*.pipeline.yaml",
+        ]
+    }
+
+    fn find_files(&self, repo_path: &Path) -> Vec<PathBuf> {
+        WalkDir::new(repo_path)
+            .follow_links(true)
+            .into_iter()
+            .filter_entry(|entry| entry.file_name() != ".git")
+            .filter_map(Result::ok)
+            .map(|entry| entry.into_path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|ext| ext == "yml" || ext == "yaml")
+                    && pipeline::is_pipeline_file(path)
+            })
+            .collect()
+    }
+
+    fn pipeline_tasks(
+        &self,
+        resolver: &TemplateResolver,
+        repo_path: &Path,
+        files: &[PathBuf],
+    ) -> Vec<PipelineTasks> {
+        resolver.pipeline_tasks(repo_path, files)
+    }
+
+    fn parse_state(&self, task: &SupportedTask, value: &str) -> Result<TaskValidState> {
+        match task {
+            SupportedTask::Gitversion => {
+                let state = GitVersionState::from_string(value)
+                    .map_err(|e| anyhow::anyhow!("Invalid GitVersion state format: {}", e))?;
+                state.validate()?;
+                Ok(TaskValidState::Gitversion(state))
+            }
+            _ => {
+                version_spec::validate(value)?;
+                Ok(TaskValidState::Default(value.to_string()))
+            }
+        }
+    }
+
+    fn version_matches(&self, state: &TaskValidState, version: &str) -> bool {
+        matches!(state, TaskValidState::Default(v) if version_spec::matches(v, version))
+    }
+
+    fn target_version(&self, states: &[TaskValidState]) -> Option<String> {
+        states
+            .iter()
+            .filter_map(|state| match state {
+                TaskValidState::Default(version) if !version_spec::is_range(version) => {
+                    Some(version)
+                }
+                _ => None,
+            })
+            .max_by(|a, b| a.version_cmp(b))
+            .cloned()
+    }
+
+    fn check_grouped(
+        &self,
+        task_name: &str,
+        task_implementations: &HashMap<String, Vec<TaskImplementation>>,
+        db: &Database,
+        issues: &mut TaskIssues,
+    ) -> Result<bool> {
+        if task_name != gitversion::SETUP_TASK && task_name != gitversion::EXECUTE_TASK {
+            return Ok(false);
+        }
+        gitversion::check_implementations(task_name, task_implementations, db, issues)?;
+        Ok(true)
+    }
+
+    fn grouped_fixes(
+        &self,
+        task_name: &str,
+        issues: &TaskIssues,
+        db: &Database,
+    ) -> Result<Option<Vec<Fix>>> {
+        match task_name {
+            gitversion::SETUP_TASK => Ok(Some(gitversion::plan_fixes(issues, db)?)),
+            // Planned together with the setup task
+            gitversion::EXECUTE_TASK => Ok(Some(Vec::new())),
+            _ => Ok(None),
+        }
+    }
+}
+
+pub struct GithubActions;
+
+impl CiPlatform for GithubActions {
+    fn name(&self) -> &'static str {
+        "GitHub Actions"
+    }
+
+    fn task_prefix(&self) -> &'static str {
+        workflow::TASK_PREFIX
+    }
+
+    fn sparse_patterns(&self) -> &'static [&'static str] {
+        &[".github/workflows/*.yml", ".github/workflows/*.yaml"]
+    }
+
+    fn find_files(&self, repo_path: &Path) -> Vec<PathBuf> {
+        workflow::find_workflow_files(repo_path)
+    }
+
+    fn pipeline_tasks(
+        &self,
+        _resolver: &TemplateResolver,
+        _repo_path: &Path,
+        files: &[PathBuf],
+    ) -> Vec<PipelineTasks> {
+        standalone_pipelines(files, |file| {
+            let content = std::fs::read_to_string(file)?;
+            Ok(workflow::extract_actions(&content)?
+                .into_iter()
+                .map(|action| ResolvedTask {
+                    task: action.into_task_reference(),
+                    source: file.to_path_buf(),
+                    include_chain: Vec::new(),
+                })
+                .collect())
+        })
+    }
+
+    fn parse_state(&self, _task: &SupportedTask, value: &str) -> Result<TaskValidState> {
+        parse_git_ref(value)
+    }
+
+    fn version_matches(&self, state: &TaskValidState, version: &str) -> bool {
+        matches!(state, TaskValidState::GitRef(git_ref) if git_ref.matches(version))
+    }
+
+    fn target_version(&self, states: &[TaskValidState]) -> Option<String> {
+        target_git_ref(states)
+    }
+}
+
+pub struct GitlabCi;
+
+impl CiPlatform for GitlabCi {
+    fn name(&self) -> &'static str {
+        "GitLab CI"
+    }
+
+    fn task_prefix(&self) -> &'static str {
+        gitlab::TASK_PREFIX
+    }
+
+    fn sparse_patterns(&self) -> &'static [&'static str] {
+        &[gitlab::CI_FILE]
+    }
+
+    fn find_files(&self, repo_path: &Path) -> Vec<PathBuf> {
+        gitlab::find_ci_file(repo_path).into_iter().collect()
+    }
+
+    fn pipeline_tasks(
+        &self,
+        _resolver: &TemplateResolver,
+        repo_path: &Path,
+        files: &[PathBuf],
+    ) -> Vec<PipelineTasks> {
+        standalone_pipelines(files, |file| gitlab::expand(repo_path, file))
+    }
+
+    fn parse_state(&self, _task: &SupportedTask, value: &str) -> Result<TaskValidState> {
+        parse_git_ref(value)
+    }
+
+    fn version_matches(&self, state: &TaskValidState, version: &str) -> bool {
+        matches!(state, TaskValidState::GitRef(git_ref) if git_ref.matches(version))
+    }
+
+    fn target_version(&self, states: &[TaskValidState]) -> Option<String> {
+        target_git_ref(states)
+    }
+}
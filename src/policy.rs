@@ -0,0 +1,236 @@
+//! Task policies from `gavinconfig.yml`: tasks that may not be used at all,
+//! versions that are deprecated until a sunset date, and tasks every pipeline
+//! has to run.
+
+use crate::{platform, version_spec, TaskImplementation};
+use anyhow::Result;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Policies {
+    #[serde(default)]
+    pub forbidden: Vec<ForbiddenTask>,
+    #[serde(default)]
+    pub deprecated: Vec<DeprecatedTask>,
+    #[serde(default)]
+    pub required: Vec<RequiredTask>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForbiddenTask {
+    pub task: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub use_instead: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeprecatedTask {
+    pub task: String,
+    /// Versions or ranges that are deprecated; every version when empty.
+    #[serde(default)]
+    pub versions: Vec<String>,
+    /// `YYYY-MM-DD`. Uses are warnings before this day and errors from it on.
+    pub sunset: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub use_instead: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequiredTask {
+    pub task: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl std::str::FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warning" => Ok(Severity::Warning),
+            "error" => Ok(Severity::Error),
+            _ => Err(format!("Unknown severity '{}'", s)),
+        }
+    }
+}
+
+/// A pipeline breaking a policy.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PolicyViolation {
+    pub severity: Severity,
+    pub repo_url: String,
+    pub repo_name: String,
+    pub task: String,
+    /// Where the task is written, or the pipeline missing a required task.
+    pub file: PathBuf,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl PolicyViolation {
+    /// `file:line`, or just the file for a missing task.
+    pub fn location(&self) -> String {
+        match self.line {
+            Some(line) => format!("{}:{}", self.file.display(), line),
+            None => self.file.display().to_string(),
+        }
+    }
+}
+
+/// A pipeline and the names of the tasks it runs, for required tasks.
+pub struct PipelineSummary {
+    pub repo_url: String,
+    pub repo_name: String,
+    pub pipeline: PathBuf,
+    pub tasks: Vec<String>,
+}
+
+impl Policies {
+    pub fn is_empty(&self) -> bool {
+        self.forbidden.is_empty() && self.deprecated.is_empty() && self.required.is_empty()
+    }
+
+    /// Checks the sunset dates and deprecated versions.
+    pub fn validate(&self) -> Result<()> {
+        for deprecated in &self.deprecated {
+            sunset_date(deprecated)?;
+            for version in &deprecated.versions {
+                version_spec::validate(version)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Every violation among `implementations`, keyed by task name, and
+    /// `pipelines`, as of `today`.
+    pub fn check(
+        &self,
+        implementations: &HashMap<String, Vec<TaskImplementation>>,
+        pipelines: &[PipelineSummary],
+        today: NaiveDate,
+    ) -> Vec<PolicyViolation> {
+        let mut violations = Vec::new();
+        let violation =
+            |severity, name: &str, implementation: &TaskImplementation, message| PolicyViolation {
+                severity,
+                repo_url: implementation.repo_url.clone(),
+                repo_name: implementation.repo_name.clone(),
+                task: name.to_string(),
+                file: implementation.source_path.clone(),
+                line: Some(implementation.location.line),
+                message,
+            };
+
+        for forbidden in &self.forbidden {
+            for (name, implementation) in uses(implementations, &forbidden.task) {
+                let message = match &forbidden.use_instead {
+                    Some(instead) => format!("{} is forbidden, use {} instead", name, instead),
+                    None => format!("{} is forbidden", name),
+                };
+                violations.push(violation(Severity::Error, name, implementation, message));
+            }
+        }
+
+        for deprecated in &self.deprecated {
+            let Ok(sunset) = sunset_date(deprecated) else {
+                continue;
+            };
+            let deprecated_uses =
+                uses(implementations, &deprecated.task).filter(|(_, implementation)| {
+                    deprecated.versions.is_empty()
+                        || deprecated
+                            .versions
+                            .iter()
+                            .any(|spec| version_spec::matches(spec, &implementation.version))
+                });
+            for (name, implementation) in deprecated_uses {
+                let (severity, mut message) = if today < sunset {
+                    (
+                        Severity::Warning,
+                        format!(
+                            "{}@{} is deprecated and sunset on {}",
+                            name, implementation.version, sunset
+                        ),
+                    )
+                } else {
+                    (
+                        Severity::Error,
+                        format!(
+                            "{}@{} was sunset on {}",
+                            name, implementation.version, sunset
+                        ),
+                    )
+                };
+                if let Some(instead) = &deprecated.use_instead {
+                    message.push_str(&format!(", use {} instead", instead));
+                }
+                violations.push(violation(severity, name, implementation, message));
+            }
+        }
+
+        for required in &self.required {
+            // A GitHub action can only be required of workflows, and so on
+            let platform = platform::for_task(&required.task).name();
+            for pipeline in pipelines {
+                if platform::for_pipeline(&pipeline.pipeline).name() != platform
+                    || pipeline
+                        .tasks
+                        .iter()
+                        .any(|task| task.eq_ignore_ascii_case(&required.task))
+                {
+                    continue;
+                }
+                violations.push(PolicyViolation {
+                    severity: Severity::Error,
+                    repo_url: pipeline.repo_url.clone(),
+                    repo_name: pipeline.repo_name.clone(),
+                    task: required.task.clone(),
+                    file: pipeline.pipeline.clone(),
+                    line: None,
+                    message: format!("Pipeline does not run the required task {}", required.task),
+                });
+            }
+        }
+
+        // Errors first
+        violations.sort_by(|a, b| b.severity.cmp(&a.severity).then_with(|| a.cmp(b)));
+        violations
+    }
+}
+
+/// Every implementation of `task`, with the name it is written as.
+fn uses<'a>(
+    implementations: &'a HashMap<String, Vec<TaskImplementation>>,
+    task: &'a str,
+) -> impl Iterator<Item = (&'a String, &'a TaskImplementation)> + 'a {
+    implementations
+        .iter()
+        .filter(move |(name, _)| name.eq_ignore_ascii_case(task))
+        .flat_map(|(name, implementations)| implementations.iter().map(move |i| (name, i)))
+}
+
+fn sunset_date(deprecated: &DeprecatedTask) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(&deprecated.sunset, "%Y-%m-%d").map_err(|_| {
+        anyhow::anyhow!(
+            "Sunset date '{}' of {} is not a YYYY-MM-DD date",
+            deprecated.sunset,
+            deprecated.task
+        )
+    })
+}
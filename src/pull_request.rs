@@ -0,0 +1,102 @@
+//! Opening pull requests for pushed fix branches, on whichever host a
+//! repository lives on.
+
+use crate::azure_devops::{AzureDevOpsClient, AzureRepo};
+use crate::github_api::{GithubClient, GithubRepo};
+use crate::gitlab_api::{GitlabClient, GitlabProject};
+use anyhow::Result;
+
+/// What to open a pull request with.
+#[derive(Debug, Clone, Default)]
+pub struct NewPullRequest {
+    pub source_branch: String,
+    pub title: String,
+    pub description: String,
+    /// Reviewer IDs as the host expects them: identity IDs on Azure DevOps,
+    /// logins on GitHub and numeric user IDs on GitLab.
+    pub reviewers: Vec<String>,
+    pub work_items: Vec<String>,
+}
+
+impl NewPullRequest {
+    /// The description with work items linked as issue references, for hosts
+    /// without work item links of their own.
+    pub(crate) fn description_with_issues(&self) -> String {
+        if self.work_items.is_empty() {
+            return self.description.clone();
+        }
+        let issues: Vec<String> = self
+            .work_items
+            .iter()
+            .map(|id| format!("#{}", id.trim_start_matches('#')))
+            .collect();
+        format!("{}\nRelated to {}\n", self.description, issues.join(", "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PullRequest {
+    pub id: u64,
+    pub source_branch: String,
+    pub web_url: String,
+}
+
+/// Whether a pull request was opened, or an open gavin one was found instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PullRequestOutcome {
+    Created(PullRequest),
+    Existing(PullRequest),
+}
+
+/// Whether an open pull request's branch is the fix branch, or another branch
+/// gavin pushed, which all start with `gavin_prefix`.
+pub(crate) fn is_gavin_branch(branch: &str, fix_branch: &str, gavin_prefix: &str) -> bool {
+    branch == fix_branch || (!gavin_prefix.is_empty() && branch.starts_with(gavin_prefix))
+}
+
+/// The host of a URL, without credentials or port.
+pub(crate) fn url_host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split('/').next().unwrap_or_default();
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    host.split(':').next().unwrap_or_default()
+}
+
+/// One client per supported host, picking the right one for each repository.
+pub struct PullRequestClients {
+    pub azure: AzureDevOpsClient,
+    pub github: GithubClient,
+    pub gitlab: GitlabClient,
+}
+
+impl PullRequestClients {
+    /// Opens a pull request for the repository at `repo_url`, unless an open one
+    /// comes from a gavin branch already.
+    pub async fn open_fix_pull_request(
+        &self,
+        repo_url: &str,
+        pr: &NewPullRequest,
+        gavin_prefix: &str,
+    ) -> Result<PullRequestOutcome> {
+        if let Some(repo) = AzureRepo::parse(repo_url) {
+            self.azure
+                .open_fix_pull_request(&repo, pr, gavin_prefix)
+                .await
+        } else if let Some(repo) = GithubRepo::parse(repo_url, self.github.clone_host()) {
+            self.github
+                .open_fix_pull_request(&repo, pr, gavin_prefix)
+                .await
+        } else if let Some(project) = GitlabProject::parse(repo_url, self.gitlab.clone_host()) {
+            self.gitlab
+                .open_fix_pull_request(&project, pr, gavin_prefix)
+                .await
+        } else {
+            Err(anyhow::anyhow!(
+                "{} is not on Azure DevOps or a configured GitHub or GitLab host",
+                url_host(repo_url)
+            ))
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Stable identity for repositories: the host, namespace and name a clone URL
+//! points at, and where its checkout lives on disk.
+
+use crate::utils::percent_decode;
+use std::path::{Path, PathBuf};
+
+/// A repository as `host/namespace.../name`, however its URL was written.
+/// `https://user@dev.azure.com/org/My%20Project/_git/api` and
+/// `https://org.visualstudio.com/My Project/_git/api` are both
+/// `dev.azure.com/org/My Project/api`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoId {
+    pub host: String,
+    /// Organization, project, owner or groups, decoded.
+    pub namespace: Vec<String>,
+    pub name: String,
+}
+
+impl RepoId {
+    pub fn parse(url: &str) -> Self {
+        let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+        let (authority, path) = if scheme == "file" {
+            ("local", rest)
+        } else if let Some(scp) = scp_like(url) {
+            scp
+        } else {
+            rest.split_once('/').unwrap_or((rest, ""))
+        };
+        let host = authority
+            .rsplit_once('@')
+            .map_or(authority, |(_, host)| host)
+            .to_lowercase();
+        // SSH servers often listen on another port than HTTPS
+        let host = match (scheme, host.split_once(':')) {
+            ("ssh", Some((host, _port))) => host.to_string(),
+            _ => host,
+        };
+
+        let mut segments: Vec<String> = path
+            .trim_end_matches('/')
+            .trim_end_matches(".git")
+            .split('/')
+            .filter(|segment| !segment.is_empty() && *segment != "_git")
+            .map(percent_decode)
+            .collect();
+
+        // Azure DevOps SSH URLs point at their own host, with a `v3` first segment
+        let host = if host == "ssh.dev.azure.com" || host == "vs-ssh.visualstudio.com" {
+            if segments.first().is_some_and(|segment| segment == "v3") {
+                segments.remove(0);
+            }
+            "dev.azure.com".to_string()
+        } else {
+            host
+        };
+
+        // Old-style Azure DevOps URLs carry the organization in the host
+        let host = match host.strip_suffix(".visualstudio.com") {
+            Some(organization) => {
+                segments.insert(0, organization.to_string());
+                "dev.azure.com".to_string()
+            }
+            None => host,
+        };
+
+        let name = segments.pop().unwrap_or_else(|| "repo".to_string());
+        RepoId {
+            host,
+            namespace: segments,
+            name,
+        }
+    }
+
+    /// Namespace and name, e.g. `org/My Project/api`. Unique per host, and used
+    /// to tell repositories apart in output.
+    pub fn full_name(&self) -> String {
+        self.namespace
+            .iter()
+            .chain(std::iter::once(&self.name))
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// Host, namespace and name, e.g. `dev.azure.com/org/My Project/api`.
+    pub fn canonical(&self) -> String {
+        format!("{}/{}", self.host, self.full_name())
+    }
+
+    /// Whether both name the same repository; hosts compare names case-insensitively.
+    pub fn same_as(&self, other: &RepoId) -> bool {
+        self.canonical().eq_ignore_ascii_case(&other.canonical())
+    }
+
+    /// The checkout directory under `root`: one directory per host and
+    /// namespace segment, so repositories with the same name do not collide.
+    pub fn local_dir(&self, root: &Path) -> PathBuf {
+        let mut dir = root.join(path_component(&self.host));
+        for segment in &self.namespace {
+            dir.push(path_component(segment));
+        }
+        dir.join(path_component(&self.name))
+    }
+}
+
+/// Whether git reaches `url` over SSH, as `ssh://...` or `user@host:path`.
+pub fn is_ssh_url(url: &str) -> bool {
+    url.starts_with("ssh://") || scp_like(url).is_some()
+}
+
+/// The host and path of scp-like SSH URLs such as `git@github.com:org/repo.git`.
+fn scp_like(url: &str) -> Option<(&str, &str)> {
+    if url.contains("://") {
+        return None;
+    }
+    let (authority, path) = url.split_once(':')?;
+    (authority.contains('@') && !authority.contains('/')).then_some((authority, path))
+}
+
+/// A path segment that is safe as a single directory name.
+fn path_component(segment: &str) -> String {
+    match segment.replace(['/', '\\', ':'], "_") {
+        dots if dots.chars().all(|c| c == '.') => dots.replace('.', "_"),
+        component => component,
+    }
+}
@@ -1,8 +1,11 @@
 use crate::database::Database;
-use crate::{collect_task_usage_data, format_task_states, TaskIssues};
+use crate::policy::Severity;
+use crate::{
+    collect_task_usage_data, format_include_chain, format_task_states, RepoId, TaskIssues,
+};
 use anyhow::Result;
 use itertools::Itertools;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 
 pub async fn generate_markdown_report(
@@ -14,10 +17,12 @@ pub async fn generate_markdown_report(
 
     generate_header(&mut md);
     generate_summary_section(&mut md, issues)?;
+    generate_team_section(&mut md, repos, db, issues)?;
     generate_valid_states_section(&mut md, db).await?;
     generate_issues_section(&mut md, issues)?;
+    generate_policy_section(&mut md, issues);
     generate_implementation_details(&mut md, issues)?;
-    generate_task_usage_section(&mut md, repos).await?;
+    generate_task_usage_section(&mut md, db, repos).await?;
 
     Ok(md)
 }
@@ -56,10 +61,64 @@ fn generate_summary_section(md: &mut String, issues: &TaskIssues) -> Result<()>
         .map(|repos| repos.len())
         .sum::<usize>();
     md.push_str(&format!(
-        "- Invalid state implementations: **{}**\n\n",
+        "- Invalid state implementations: **{}**\n",
         invalid_count
     ));
 
+    let errors = issues
+        .policy_violations
+        .iter()
+        .filter(|violation| violation.severity == Severity::Error)
+        .count();
+    md.push_str(&format!(
+        "- Policy violations: **{}** errors, **{}** warnings\n\n",
+        errors,
+        issues.policy_violations.len() - errors
+    ));
+
+    Ok(())
+}
+
+/// Rolls invalid implementations up to the team owning each repository.
+fn generate_team_section(
+    md: &mut String,
+    repos: &[String],
+    db: &Database,
+    issues: &TaskIssues,
+) -> Result<()> {
+    // team -> (repositories, repositories with invalid states, invalid implementations)
+    let mut teams: BTreeMap<String, (usize, usize, usize)> = BTreeMap::new();
+    for repo_url in repos {
+        let owner = db
+            .repository_metadata(repo_url)?
+            .owner
+            .unwrap_or_else(|| "Unowned".to_string());
+        let repo_name = RepoId::parse(repo_url).full_name();
+        let invalid = issues
+            .invalid_states
+            .values()
+            .filter_map(|repos| repos.get(&repo_name))
+            .map(|impls| impls.len())
+            .sum::<usize>();
+
+        let team = teams.entry(owner).or_default();
+        team.0 += 1;
+        if invalid > 0 {
+            team.1 += 1;
+        }
+        team.2 += invalid;
+    }
+
+    md.push_str("## By Team\n\n");
+    md.push_str("| Team | Repositories | With invalid states | Invalid implementations |\n");
+    md.push_str("|------|--------------|---------------------|-------------------------|\n");
+    for (team, (total, invalid_repos, invalid)) in &teams {
+        md.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            team, total, invalid_repos, invalid
+        ));
+    }
+    md.push('\n');
     Ok(())
 }
 
@@ -83,9 +142,10 @@ fn generate_issues_section(md: &mut String, issues: &TaskIssues) -> Result<()> {
                     md.push_str(&format!("- {}\n", repo));
                     for impl_ in impls {
                         md.push_str(&format!(
-                            "  - {}: {}\n",
+                            "  - {}: {}{}\n",
                             impl_.version,
-                            impl_.file_path.display()
+                            impl_.file_path.display(),
+                            format_include_chain(&impl_.include_chain, None)
                         ));
                     }
                 }
@@ -96,6 +156,26 @@ fn generate_issues_section(md: &mut String, issues: &TaskIssues) -> Result<()> {
     Ok(())
 }
 
+fn generate_policy_section(md: &mut String, issues: &TaskIssues) {
+    if issues.policy_violations.is_empty() {
+        return;
+    }
+    md.push_str("## Policy Violations\n\n");
+    md.push_str("| Severity | Repository | Task | Location | Problem |\n");
+    md.push_str("|----------|------------|------|----------|---------|\n");
+    for violation in &issues.policy_violations {
+        md.push_str(&format!(
+            "| {} | {} | {} | {} | {} |\n",
+            violation.severity,
+            violation.repo_name,
+            violation.task,
+            violation.location(),
+            violation.message
+        ));
+    }
+    md.push('\n');
+}
+
 fn generate_implementation_details(md: &mut String, issues: &TaskIssues) -> Result<()> {
     md.push_str("## Implementation Details\n\n");
 
@@ -154,9 +234,13 @@ async fn generate_valid_states_section(md: &mut String, db: &Database) -> Result
     Ok(())
 }
 
-async fn generate_task_usage_section(md: &mut String, repos: &[String]) -> Result<()> {
+async fn generate_task_usage_section(
+    md: &mut String,
+    db: &Database,
+    repos: &[String],
+) -> Result<()> {
     md.push_str("## Task Usage Analysis\n\n");
-    let task_usage = collect_task_usage_data(repos).await?;
+    let task_usage = collect_task_usage_data(db, repos).await?;
 
     for task_name in task_usage.keys().sorted() {
         md.push_str(&format!("### {}\n\n", task_name));
@@ -169,7 +253,7 @@ async fn generate_task_usage_section(md: &mut String, repos: &[String]) -> Resul
             for repo_url in repos.keys().sorted() {
                 let repo_name = repo_url
                     .split('/')
-                    .last()
+                    .next_back()
                     .unwrap_or(repo_url)
                     .trim_end_matches(".git");
 
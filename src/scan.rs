@@ -0,0 +1,352 @@
+//! Scans kept in the database: every task occurrence a `--check-tasks` run
+//! found, whether it was valid, and the commit each repository was at.
+
+use crate::git_manager::head_commit;
+use crate::gitversion;
+use crate::policy::Severity;
+use crate::{Database, TaskIssues};
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// One run of `--check-tasks`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scan {
+    pub id: i64,
+    /// UTC, as `YYYY-MM-DD HH:MM:SS`.
+    pub created_at: String,
+    pub repositories: usize,
+    pub occurrences: usize,
+    pub invalid: usize,
+}
+
+impl Scan {
+    /// The share of task occurrences that were valid, in percent.
+    pub fn compliance(&self) -> Option<f64> {
+        (self.occurrences > 0)
+            .then(|| (self.occurrences - self.invalid) as f64 * 100.0 / self.occurrences as f64)
+    }
+}
+
+/// A task used in a pipeline, as found by one scan.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TaskOccurrence {
+    pub repo_url: String,
+    pub task: String,
+    pub version: String,
+    /// The pipeline running the task, relative to the checkout.
+    pub pipeline: String,
+    /// The file the task is written in, which differs for template tasks.
+    pub file: String,
+    pub line: usize,
+    /// Why the occurrence is not valid, if it is not.
+    pub issue: Option<String>,
+}
+
+impl TaskOccurrence {
+    pub fn is_valid(&self) -> bool {
+        self.issue.is_none()
+    }
+}
+
+/// A repository as a scan found it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScannedRepository {
+    pub url: String,
+    /// `None` when there was no checkout to read it from, e.g. in a dry run.
+    pub commit_sha: Option<String>,
+}
+
+/// A policy violation, as found by one scan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyFinding {
+    pub repo_url: String,
+    pub task: String,
+    pub severity: Severity,
+    /// What is wrong and where, e.g. `CmdLine is forbidden (azure-pipelines.yml:3)`.
+    pub message: String,
+}
+
+/// Every occurrence in `issues`, each marked with why it is invalid if it is.
+pub fn occurrences(db: &Database, issues: &TaskIssues) -> Vec<TaskOccurrence> {
+    let mut occurrences = Vec::new();
+    for (task, implementations) in &issues.all_implementations {
+        for implementation in implementations {
+            let url = &implementation.repo_url;
+            let is_invalid = issues
+                .invalid_states
+                .get(task)
+                .and_then(|repos| repos.get(&implementation.repo_name))
+                .is_some_and(|invalid| invalid.contains(implementation));
+            let issue = is_invalid.then(|| {
+                if issues.missing_states.contains(task) {
+                    format!("No valid states are set for {}", task)
+                } else if task == gitversion::SETUP_TASK || task == gitversion::EXECUTE_TASK {
+                    "The GitVersion setup, execute and versionSpec combination is not a valid state"
+                        .to_string()
+                } else {
+                    format!(
+                        "{} is not a valid version of {}",
+                        implementation.version, task
+                    )
+                }
+            });
+
+            let checkout = db.get_local_path(url);
+            occurrences.push(TaskOccurrence {
+                repo_url: url.to_string(),
+                task: task.clone(),
+                version: implementation.version.clone(),
+                pipeline: relative_path(&implementation.file_path, &checkout, db),
+                file: relative_path(&implementation.source_path, &checkout, db),
+                line: implementation.location.line,
+                issue,
+            });
+        }
+    }
+    occurrences.sort();
+    occurrences
+}
+
+/// The policy violations in `issues`.
+pub fn policy_findings(db: &Database, issues: &TaskIssues) -> Vec<PolicyFinding> {
+    issues
+        .policy_violations
+        .iter()
+        .map(|violation| {
+            let checkout = db.get_local_path(&violation.repo_url);
+            let file = relative_path(&violation.file, &checkout, db);
+            let location = match violation.line {
+                Some(line) => format!("{}:{}", file, line),
+                None => file,
+            };
+            PolicyFinding {
+                repo_url: violation.repo_url.clone(),
+                task: violation.task.clone(),
+                severity: violation.severity,
+                message: format!("{} ({})", violation.message, location),
+            }
+        })
+        .collect()
+}
+
+/// Stores a scan of `repos` with what `issues` found in them.
+pub async fn record(db: &Database, repos: &[String], issues: &TaskIssues) -> Result<Scan> {
+    let mut repositories = Vec::new();
+    for url in repos {
+        repositories.push(ScannedRepository {
+            url: url.clone(),
+            commit_sha: head_commit(&db.get_local_path(url)).await,
+        });
+    }
+    db.record_scan(
+        &repositories,
+        &occurrences(db, issues),
+        &policy_findings(db, issues),
+    )
+}
+
+/// `path` relative to the checkout it is in, or to the checkouts root for
+/// templates from other repositories.
+fn relative_path(path: &Path, checkout: &Path, db: &Database) -> String {
+    path.strip_prefix(checkout)
+        .or_else(|_| path.strip_prefix(db.workspace().checkouts_root()))
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+/// Everything a scan recorded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanResults {
+    pub repositories: Vec<ScannedRepository>,
+    pub occurrences: Vec<TaskOccurrence>,
+}
+
+/// A scan named by its id, or by a date for the last scan on or before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanRef {
+    Id(i64),
+    Date(chrono::NaiveDate),
+}
+
+impl std::str::FromStr for ScanRef {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(id) = s.parse() {
+            return Ok(ScanRef::Id(id));
+        }
+        chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .map(ScanRef::Date)
+            .map_err(|_| format!("Expected a scan id or a YYYY-MM-DD date, got '{}'", s))
+    }
+}
+
+/// A task whose versions in one file changed between two scans.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionChange {
+    pub repo_url: String,
+    pub task: String,
+    pub file: String,
+    pub from: Vec<String>,
+    pub to: Vec<String>,
+}
+
+/// What changed from one scan to a later one. Occurrences are compared only
+/// in repositories both scans covered.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanDiff {
+    pub newly_invalid: Vec<TaskOccurrence>,
+    pub fixed: Vec<TaskOccurrence>,
+    pub added_repositories: Vec<String>,
+    pub removed_repositories: Vec<String>,
+    pub version_changes: Vec<VersionChange>,
+}
+
+/// Compares two scans. An occurrence is matched by repository, task, pipeline,
+/// file and version, ignoring its line, which moves with unrelated edits.
+pub fn diff(old: &ScanResults, new: &ScanResults) -> ScanDiff {
+    let urls = |results: &ScanResults| -> BTreeSet<String> {
+        results.repositories.iter().map(|r| r.url.clone()).collect()
+    };
+    let (old_urls, new_urls) = (urls(old), urls(new));
+    let in_both = |occurrence: &&TaskOccurrence| {
+        old_urls.contains(&occurrence.repo_url) && new_urls.contains(&occurrence.repo_url)
+    };
+
+    let invalid = |results: &ScanResults| -> BTreeMap<OccurrenceKey, TaskOccurrence> {
+        results
+            .occurrences
+            .iter()
+            .filter(in_both)
+            .filter(|o| !o.is_valid())
+            .map(|o| (OccurrenceKey::of(o), o.clone()))
+            .collect()
+    };
+    let (old_invalid, new_invalid) = (invalid(old), invalid(new));
+
+    let versions = |results: &ScanResults| {
+        let mut versions: BTreeMap<(String, String, String), BTreeSet<String>> = BTreeMap::new();
+        for o in results.occurrences.iter().filter(in_both) {
+            versions
+                .entry((o.repo_url.clone(), o.task.clone(), o.file.clone()))
+                .or_default()
+                .insert(o.version.clone());
+        }
+        versions
+    };
+    let (old_versions, new_versions) = (versions(old), versions(new));
+    let version_changes = old_versions
+        .iter()
+        .filter_map(|(key, from)| {
+            let to = new_versions.get(key)?;
+            (from != to).then(|| VersionChange {
+                repo_url: key.0.clone(),
+                task: key.1.clone(),
+                file: key.2.clone(),
+                from: from.iter().cloned().collect(),
+                to: to.iter().cloned().collect(),
+            })
+        })
+        .collect();
+
+    ScanDiff {
+        newly_invalid: new_invalid
+            .iter()
+            .filter(|(key, _)| !old_invalid.contains_key(key))
+            .map(|(_, o)| o.clone())
+            .collect(),
+        fixed: old_invalid
+            .iter()
+            .filter(|(key, _)| !new_invalid.contains_key(key))
+            .map(|(_, o)| o.clone())
+            .collect(),
+        added_repositories: new_urls.difference(&old_urls).cloned().collect(),
+        removed_repositories: old_urls.difference(&new_urls).cloned().collect(),
+        version_changes,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct OccurrenceKey {
+    repo_url: String,
+    task: String,
+    pipeline: String,
+    file: String,
+    version: String,
+}
+
+impl OccurrenceKey {
+    fn of(occurrence: &TaskOccurrence) -> Self {
+        OccurrenceKey {
+            repo_url: occurrence.repo_url.clone(),
+            task: occurrence.task.clone(),
+            pipeline: occurrence.pipeline.clone(),
+            file: occurrence.file.clone(),
+            version: occurrence.version.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrendFormat {
+    #[default]
+    Markdown,
+    Csv,
+}
+
+impl std::str::FromStr for TrendFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "markdown" | "md" => Ok(TrendFormat::Markdown),
+            "csv" => Ok(TrendFormat::Csv),
+            _ => Err(format!("Unknown format '{}'. Expected markdown or csv", s)),
+        }
+    }
+}
+
+/// Compliance per scan, oldest first, as a table.
+pub fn trend_table(scans: &[Scan], format: TrendFormat) -> String {
+    let rows = scans.iter().map(|scan| {
+        [
+            scan.id.to_string(),
+            scan.created_at.clone(),
+            scan.repositories.to_string(),
+            scan.occurrences.to_string(),
+            (scan.occurrences - scan.invalid).to_string(),
+            scan.compliance()
+                .map_or_else(String::new, |percent| format!("{:.1}", percent)),
+        ]
+    });
+    let header = [
+        "Scan",
+        "Date",
+        "Repositories",
+        "Occurrences",
+        "Valid",
+        "Compliance (%)",
+    ];
+
+    let mut table = String::new();
+    match format {
+        TrendFormat::Csv => {
+            table.push_str(&header.join(","));
+            table.push('\n');
+            for row in rows {
+                table.push_str(&row.join(","));
+                table.push('\n');
+            }
+        }
+        TrendFormat::Markdown => {
+            table.push_str(&format!("| {} |\n", header.join(" | ")));
+            table.push_str(&format!("|{}\n", "---|".repeat(header.len())));
+            for row in rows {
+                table.push_str(&format!("| {} |\n", row.join(" | ")));
+            }
+        }
+    }
+    table
+}
@@ -0,0 +1,288 @@
+//! Where git credentials come from: the environment, or the database with
+//! tokens encrypted with AES-256-GCM under a key kept outside it.
+
+use crate::repo_id::RepoId;
+use crate::workspace::Environment;
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::path::{Path, PathBuf};
+
+/// Token and username variables, checked in order before the database.
+pub const TOKEN_VARS: [(&str, &str); 2] = [
+    ("GAVIN_TOKEN", "GAVIN_USERNAME"),
+    ("AZURE_TOKEN", "AZURE_USERNAME"),
+];
+
+/// A base64 encoded 32 byte key to encrypt stored tokens with.
+pub const KEY_VAR: &str = "GAVIN_SECRET_KEY";
+/// A file holding the key, used when [`KEY_VAR`] is not set.
+pub const KEY_FILE_VAR: &str = "GAVIN_KEY_FILE";
+pub const DEFAULT_KEY_FILE: &str = "gavin.key";
+
+/// Marks tokens encrypted by [`SecretKey::encrypt`]. Tokens stored by older
+/// versions were XORed with 0xFF, so their first byte is never ASCII.
+const ENCRYPTED_PREFIX: &[u8] = b"GV1";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeySource {
+    Environment,
+    File(PathBuf),
+}
+
+impl std::fmt::Display for KeySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeySource::Environment => write!(f, "{}", KEY_VAR),
+            KeySource::File(path) => write!(f, "key file {}", path.display()),
+        }
+    }
+}
+
+/// Where the active credentials came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CredentialSource {
+    Environment(&'static str),
+    /// Stored, with the token encrypted under a key from here if there is one.
+    Database(Option<KeySource>),
+    /// Nothing configured; SSH falls back to the user's agent and config.
+    SshDefaults,
+}
+
+impl std::fmt::Display for CredentialSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CredentialSource::Environment(var) => write!(f, "environment variable {}", var),
+            CredentialSource::Database(Some(key)) => {
+                write!(f, "database, encrypted with the key from {}", key)
+            }
+            CredentialSource::Database(None) => write!(f, "database"),
+            CredentialSource::SshDefaults => write!(f, "SSH agent and config"),
+        }
+    }
+}
+
+/// Which repositories a set of credentials is for: those on `host`, and
+/// under `path` within it if given. Without a host they are the default.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CredentialScope {
+    pub host: Option<String>,
+    pub path: Option<String>,
+}
+
+impl CredentialScope {
+    /// Parses `host` or `host/org/...`, with or without a scheme.
+    pub fn parse(scope: &str) -> Self {
+        let scope = scope.split_once("://").map_or(scope, |(_, rest)| rest);
+        let (host, path) = scope.split_once('/').unwrap_or((scope, ""));
+        let path = path.trim_matches('/');
+        CredentialScope {
+            host: Some(host.to_lowercase()).filter(|host| !host.is_empty()),
+            path: Some(path.to_string()).filter(|path| !path.is_empty()),
+        }
+    }
+
+    /// How well the scope covers `repo`: `None` if it does not, and higher
+    /// the more specific it is.
+    pub fn specificity(&self, repo: &RepoId) -> Option<usize> {
+        let Some(host) = &self.host else {
+            return Some(0);
+        };
+        if !host.eq_ignore_ascii_case(&repo.host) {
+            return None;
+        }
+        let Some(path) = &self.path else {
+            return Some(1);
+        };
+
+        let repo_segments: Vec<&str> = repo
+            .namespace
+            .iter()
+            .map(String::as_str)
+            .chain(std::iter::once(repo.name.as_str()))
+            .collect();
+        let scope_segments: Vec<&str> = path.split('/').collect();
+        let covers = scope_segments.len() <= repo_segments.len()
+            && scope_segments
+                .iter()
+                .zip(&repo_segments)
+                .all(|(scope, repo)| scope.eq_ignore_ascii_case(repo));
+        covers.then_some(1 + scope_segments.len())
+    }
+}
+
+impl std::fmt::Display for CredentialScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.host, &self.path) {
+            (None, _) => write!(f, "default"),
+            (Some(host), None) => write!(f, "{}", host),
+            (Some(host), Some(path)) => write!(f, "{}/{}", host, path),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    /// Empty when only an SSH key is set.
+    pub token: String,
+    /// The private key git uses for SSH URLs.
+    pub ssh_key: Option<PathBuf>,
+    pub scope: CredentialScope,
+    pub source: CredentialSource,
+}
+
+impl Credentials {
+    /// For SSH URLs no credentials were configured for.
+    pub fn ssh_defaults() -> Self {
+        Credentials {
+            username: String::new(),
+            token: String::new(),
+            ssh_key: None,
+            scope: CredentialScope::default(),
+            source: CredentialSource::SshDefaults,
+        }
+    }
+}
+
+/// Credentials from the first token variable that is set.
+pub fn from_environment(environment: &Environment) -> Option<Credentials> {
+    TOKEN_VARS.iter().find_map(|(token_var, username_var)| {
+        let token = environment.var(token_var).filter(|t| !t.is_empty())?;
+        Some(Credentials {
+            // Personal access tokens work with any username
+            username: environment
+                .var(username_var)
+                .unwrap_or_else(|| "pat".to_string()),
+            token,
+            ssh_key: None,
+            scope: CredentialScope::default(),
+            source: CredentialSource::Environment(token_var),
+        })
+    })
+}
+
+pub struct SecretKey {
+    key: LessSafeKey,
+    pub source: KeySource,
+}
+
+impl SecretKey {
+    /// The key from [`KEY_VAR`], or else from the file named by [`KEY_FILE_VAR`]
+    /// or `default_file`. That is created with a random key when `create` is
+    /// set and it does not exist yet.
+    pub fn load(
+        default_file: &Path,
+        create: bool,
+        environment: &Environment,
+    ) -> Result<Option<SecretKey>> {
+        if let Some(encoded) = environment.var(KEY_VAR) {
+            return Self::from_base64(&encoded, KeySource::Environment).map(Some);
+        }
+
+        let path = environment
+            .var(KEY_FILE_VAR)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| default_file.to_path_buf());
+        if path.exists() {
+            let encoded = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read key file {}", path.display()))?;
+            return Self::from_base64(&encoded, KeySource::File(path)).map(Some);
+        }
+        if !create {
+            return Ok(None);
+        }
+
+        let mut bytes = [0u8; 32];
+        SystemRandom::new()
+            .fill(&mut bytes)
+            .map_err(|_| anyhow::anyhow!("Failed to generate an encryption key"))?;
+        write_private(&path, &BASE64.encode(bytes))?;
+        println!("Created encryption key file {}", path.display());
+        Self::from_bytes(&bytes, KeySource::File(path)).map(Some)
+    }
+
+    fn from_base64(encoded: &str, source: KeySource) -> Result<Self> {
+        let bytes = BASE64
+            .decode(encoded.trim())
+            .with_context(|| format!("The key from {} is not valid base64", source))?;
+        Self::from_bytes(&bytes, source)
+    }
+
+    fn from_bytes(bytes: &[u8], source: KeySource) -> Result<Self> {
+        let key = UnboundKey::new(&AES_256_GCM, bytes)
+            .map_err(|_| anyhow::anyhow!("The key from {} must be 32 bytes", source))?;
+        Ok(SecretKey {
+            key: LessSafeKey::new(key),
+            source,
+        })
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| anyhow::anyhow!("Failed to generate a nonce"))?;
+
+        let mut sealed = plaintext.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut sealed,
+            )
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt the token"))?;
+
+        Ok([ENCRYPTED_PREFIX, &nonce, &sealed].concat())
+    }
+
+    pub fn decrypt(&self, stored: &[u8]) -> Result<String> {
+        let sealed = stored
+            .strip_prefix(ENCRYPTED_PREFIX)
+            .filter(|rest| rest.len() > NONCE_LEN)
+            .ok_or_else(|| anyhow::anyhow!("The stored token is not encrypted"))?;
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+
+        let mut buffer = ciphertext.to_vec();
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| anyhow::anyhow!("The stored token is corrupt"))?;
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::empty(), &mut buffer)
+            .map_err(|_| {
+                anyhow::anyhow!(
+                    "Failed to decrypt the stored token with the key from {}",
+                    self.source
+                )
+            })?;
+        Ok(String::from_utf8(plaintext.to_vec())?)
+    }
+}
+
+pub fn is_encrypted(stored: &[u8]) -> bool {
+    stored.starts_with(ENCRYPTED_PREFIX)
+}
+
+/// Reads a token stored by versions that XORed it with 0xFF.
+pub fn decode_legacy(stored: &[u8]) -> Result<String> {
+    let token = stored.iter().map(|b| b ^ 0xFF).collect::<Vec<u8>>();
+    Ok(String::from_utf8(token)?)
+}
+
+/// Writes a file only the current user can read.
+fn write_private(path: &Path, content: &str) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to create key file {}", path.display()))?;
+    std::io::Write::write_all(&mut file, content.as_bytes())?;
+    Ok(())
+}
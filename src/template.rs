@@ -0,0 +1,908 @@
+//! Expansion of `template:` references and `extends:` so that tasks are reported
+//! against the pipelines that run them rather than the template files they live in.
+//!
+//! Template parameters are substituted where their values are known statically:
+//! defaults, literal values passed by the caller, and `${{ if }}` conditions over
+//! those. Anything that depends on runtime state is left as written.
+
+use crate::pipeline::{
+    escape_key, is_expression, scalar_to_string, Location, Node, Pipeline, RepositoryResource,
+    SpanIndex, Step, TaskReference,
+};
+use crate::utils::percent_decode;
+use crate::Database;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// Azure DevOps stops expanding templates nested deeper than this.
+const MAX_TEMPLATE_DEPTH: usize = 20;
+
+/// A task reached from a pipeline, possibly through templates.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResolvedTask {
+    pub task: TaskReference,
+    /// The file the task is written in. `task.location` points into this file.
+    pub source: PathBuf,
+    /// Templates expanded on the way from the pipeline to the task, outermost first.
+    pub include_chain: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineTasks {
+    pub pipeline: PathBuf,
+    pub tasks: Vec<ResolvedTask>,
+    /// Every file read to find the tasks, the pipeline included.
+    pub files: Vec<PathBuf>,
+}
+
+/// Resolves template references against local checkouts of known repositories.
+pub struct TemplateResolver {
+    checkouts: Vec<(String, PathBuf)>,
+}
+
+impl TemplateResolver {
+    /// Creates a resolver from `(repository url, local checkout)` pairs.
+    pub fn new(checkouts: Vec<(String, PathBuf)>) -> Self {
+        TemplateResolver { checkouts }
+    }
+
+    pub fn from_database(db: &Database) -> Result<Self> {
+        let checkouts = db
+            .list_repositories()?
+            .into_iter()
+            .map(|url| {
+                let path = db.get_local_path(&url);
+                (url, path)
+            })
+            .collect();
+        Ok(Self::new(checkouts))
+    }
+
+    /// Expands every pipeline among `files`. A pipeline is a file that no other
+    /// file in the same checkout includes as a template.
+    pub fn pipeline_tasks(&self, repo_root: &Path, files: &[PathBuf]) -> Vec<PipelineTasks> {
+        let included: HashSet<PathBuf> = files
+            .iter()
+            .flat_map(|file| local_template_references(repo_root, file))
+            .collect();
+
+        files
+            .iter()
+            .filter(|file| !included.contains(&normalize(file)))
+            .filter_map(|pipeline| match self.expand_pipeline(repo_root, pipeline) {
+                Ok(tasks) => Some(tasks),
+                Err(e) => {
+                    eprintln!("Skipping {}: {}", pipeline.display(), e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Expands a single pipeline and returns every task it runs.
+    pub fn expand(&self, repo_root: &Path, pipeline: &Path) -> Result<Vec<ResolvedTask>> {
+        Ok(self.expand_pipeline(repo_root, pipeline)?.tasks)
+    }
+
+    fn expand_pipeline(&self, repo_root: &Path, pipeline: &Path) -> Result<PipelineTasks> {
+        let mut expander = Expander {
+            resolver: self,
+            self_root: repo_root.to_path_buf(),
+            pipeline: normalize(pipeline),
+            aliases: HashMap::new(),
+            tasks: Vec::new(),
+            files: Vec::new(),
+        };
+        expander.expand_file(pipeline, repo_root, HashMap::new(), &[])?;
+        Ok(PipelineTasks {
+            pipeline: pipeline.to_path_buf(),
+            tasks: expander.tasks,
+            files: expander.files,
+        })
+    }
+
+    /// Finds the local checkout of a `resources.repositories` entry among the known repositories.
+    fn repository_checkout(&self, resource: &RepositoryResource) -> Option<PathBuf> {
+        let name = resource.name.as_deref()?.trim();
+        let wanted: Vec<String> = name.split('/').map(url_segment).collect();
+        let azure_repos = resource
+            .kind
+            .as_deref()
+            .is_none_or(|kind| kind.eq_ignore_ascii_case("git"));
+
+        self.checkouts
+            .iter()
+            .find(|(url, _)| {
+                let segments: Vec<String> = url
+                    .trim_end_matches('/')
+                    .trim_end_matches(".git")
+                    .split('/')
+                    .map(url_segment)
+                    .collect();
+
+                if !azure_repos {
+                    return segments.ends_with(&wanted);
+                }
+
+                // Azure Repos URLs look like .../{project}/_git/{repo}
+                let Some(git) = segments.iter().position(|s| s == "_git") else {
+                    return false;
+                };
+                let repo = segments.get(git + 1);
+                let project = git.checked_sub(1).and_then(|i| segments.get(i));
+                match wanted.as_slice() {
+                    [p, r] => project == Some(p) && repo == Some(r),
+                    [r] => repo == Some(r),
+                    _ => false,
+                }
+            })
+            .map(|(_, path)| path.clone())
+    }
+}
+
+fn url_segment(segment: &str) -> String {
+    percent_decode(segment).to_lowercase()
+}
+
+/// Template files a pipeline file includes from its own repository.
+fn local_template_references(repo_root: &Path, file: &Path) -> Vec<PathBuf> {
+    let Ok(pipeline) = std::fs::read_to_string(file)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| Pipeline::parse(&content))
+    else {
+        return Vec::new();
+    };
+
+    let mut references = Vec::new();
+    pipeline.visit(&mut |_, node| {
+        if let Node::Template(template) = node {
+            let (path, alias) = split_reference(&template.template);
+            if matches!(alias, None | Some("self")) && !path.contains("${{") {
+                references.push(template_path(path, alias.is_some(), file, repo_root));
+            }
+        }
+    });
+    references
+}
+
+/// Splits `path@alias` into its parts.
+fn split_reference(reference: &str) -> (&str, Option<&str>) {
+    let reference = reference.trim();
+    match reference.rsplit_once('@') {
+        Some((path, alias)) => (path, Some(alias)),
+        None => (reference, None),
+    }
+}
+
+/// Template paths are relative to the including file, or to the repository root
+/// when they start with `/` or name a repository.
+fn template_path(path: &str, has_alias: bool, current_file: &Path, root: &Path) -> PathBuf {
+    let path = if let Some(rest) = path.strip_prefix('/') {
+        root.join(rest)
+    } else if has_alias {
+        root.join(path)
+    } else {
+        current_file.parent().unwrap_or(root).join(path)
+    };
+    normalize(&path)
+}
+
+/// Resolves `.` and `..` without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// A source file with the locations of its nodes.
+struct Source {
+    path: PathBuf,
+    spans: SpanIndex,
+}
+
+/// Where a node of an expanded document was originally written.
+#[derive(Clone)]
+enum Origin {
+    Source(Arc<Source>, String),
+    Expanded(Arc<Expanded>, String),
+}
+
+impl Origin {
+    fn child(&self, segment: &str) -> Origin {
+        match self {
+            Origin::Source(source, path) => {
+                Origin::Source(source.clone(), format!("{}/{}", path, segment))
+            }
+            Origin::Expanded(doc, path) => {
+                Origin::Expanded(doc.clone(), format!("{}/{}", path, segment))
+            }
+        }
+    }
+}
+
+/// A document after parameter substitution. Nodes that moved or were inserted
+/// from parameters are remapped to where they were written.
+struct Expanded {
+    source: Arc<Source>,
+    remaps: HashMap<String, Origin>,
+}
+
+impl Expanded {
+    fn locate(&self, path: &str) -> Option<(PathBuf, Location)> {
+        let mut prefix = path;
+        loop {
+            if let Some(origin) = self.remaps.get(prefix) {
+                let rest = &path[prefix.len()..];
+                return match origin {
+                    Origin::Source(source, p) => source
+                        .spans
+                        .get(&format!("{}{}", p, rest))
+                        .map(|location| (source.path.clone(), location)),
+                    Origin::Expanded(doc, p) => doc.locate(&format!("{}{}", p, rest)),
+                };
+            }
+            match prefix.rfind('/') {
+                Some(i) => prefix = &prefix[..i],
+                None => break,
+            }
+        }
+        self.source
+            .spans
+            .get(path)
+            .map(|location| (self.source.path.clone(), location))
+    }
+}
+
+#[derive(Clone)]
+struct Parameter {
+    value: Value,
+    origin: Option<Origin>,
+}
+
+type Parameters = HashMap<String, Parameter>;
+
+/// Parameters declared by a template, with their defaults.
+fn declared_parameters(document: &Value, source: &Arc<Source>) -> Parameters {
+    let origin = |path: String| Some(Origin::Source(source.clone(), path));
+    match document.get("parameters") {
+        Some(Value::Sequence(items)) => items
+            .iter()
+            .enumerate()
+            .filter_map(|(i, item)| {
+                let name = item.get("name")?.as_str()?;
+                let parameter = Parameter {
+                    value: item.get("default")?.clone(),
+                    origin: origin(format!("/parameters/{}/default", i)),
+                };
+                Some((name.to_string(), parameter))
+            })
+            .collect(),
+        Some(Value::Mapping(map)) => map
+            .iter()
+            .filter_map(|(name, value)| {
+                let name = name.as_str()?;
+                let parameter = Parameter {
+                    value: value.clone(),
+                    origin: origin(format!("/parameters/{}", escape_key(name))),
+                };
+                Some((name.to_string(), parameter))
+            })
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+struct Expander<'a> {
+    resolver: &'a TemplateResolver,
+    self_root: PathBuf,
+    pipeline: PathBuf,
+    aliases: HashMap<String, PathBuf>,
+    tasks: Vec<ResolvedTask>,
+    files: Vec<PathBuf>,
+}
+
+impl Expander<'_> {
+    fn expand_file(
+        &mut self,
+        file: &Path,
+        repo_root: &Path,
+        passed: Parameters,
+        chain: &[PathBuf],
+    ) -> Result<()> {
+        self.files.push(file.to_path_buf());
+        let content = std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        let raw: Value = serde_yaml::from_str(&content).context("Invalid YAML")?;
+        let source = Arc::new(Source {
+            path: file.to_path_buf(),
+            spans: SpanIndex::build(&content)?,
+        });
+
+        let mut parameters = declared_parameters(&raw, &source);
+        parameters.extend(passed);
+
+        let mut substitution = Substitution {
+            parameters: &parameters,
+            source: &source,
+            remaps: HashMap::new(),
+        };
+        let value = substitution.value(raw, "", "");
+        let document = Arc::new(Expanded {
+            source: source.clone(),
+            remaps: substitution.remaps,
+        });
+        let pipeline = Pipeline::from_value(value)?;
+
+        // Repository resources can only be declared by the pipeline itself.
+        if chain.is_empty() {
+            let repositories = pipeline.resources.iter().flat_map(|r| &r.repositories);
+            for repository in repositories {
+                match self.resolver.repository_checkout(repository) {
+                    Some(path) => {
+                        self.aliases.insert(repository.repository.clone(), path);
+                    }
+                    None => eprintln!(
+                        "Repository resource '{}' in {} is not a known repository",
+                        repository.repository,
+                        file.display()
+                    ),
+                }
+            }
+        }
+
+        let mut templates = Vec::new();
+        pipeline.visit(&mut |path, node| match node {
+            Node::Step(Step::Task(task)) => {
+                let (source, _) = document
+                    .locate(&format!("{}/task", path))
+                    .unwrap_or_else(|| (file.to_path_buf(), Location::default()));
+                let task = TaskReference::from_step(task, |node| {
+                    document
+                        .locate(&format!("{}/{}", path, node))
+                        .filter(|(file, _)| *file == source)
+                        .map(|(_, location)| location)
+                });
+                self.tasks.push(ResolvedTask {
+                    task,
+                    source,
+                    include_chain: chain.to_vec(),
+                });
+            }
+            Node::Template(template) => templates.push((path.to_string(), template.clone())),
+            Node::Step(_) => {}
+        });
+
+        for (path, template) in templates {
+            let Some((template_file, template_root)) =
+                self.resolve(&template.template, file, repo_root)
+            else {
+                continue;
+            };
+
+            if template_file == self.pipeline || chain.contains(&template_file) {
+                eprintln!(
+                    "Skipping recursive template {} in {}",
+                    template_file.display(),
+                    file.display()
+                );
+                continue;
+            }
+            if chain.len() >= MAX_TEMPLATE_DEPTH {
+                eprintln!(
+                    "Skipping {}: templates nested more than {} levels deep",
+                    template_file.display(),
+                    MAX_TEMPLATE_DEPTH
+                );
+                continue;
+            }
+
+            let passed = template
+                .parameters
+                .iter()
+                .filter_map(|(name, value)| {
+                    let name = name.as_str()?;
+                    let parameter = Parameter {
+                        value: value.clone(),
+                        origin: Some(Origin::Expanded(
+                            document.clone(),
+                            format!("{}/parameters/{}", path, escape_key(name)),
+                        )),
+                    };
+                    Some((name.to_string(), parameter))
+                })
+                .collect();
+
+            let mut next_chain = chain.to_vec();
+            next_chain.push(template_file.clone());
+            if let Err(e) = self.expand_file(&template_file, &template_root, passed, &next_chain) {
+                eprintln!("Skipping template {}: {}", template_file.display(), e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Resolves a template reference to a file and the root of the repository it lives in.
+    fn resolve(
+        &self,
+        reference: &str,
+        current_file: &Path,
+        current_root: &Path,
+    ) -> Option<(PathBuf, PathBuf)> {
+        let (path, alias) = split_reference(reference);
+        if path.contains("${{") {
+            // Depends on a parameter we could not work out statically
+            return None;
+        }
+
+        let root = match alias {
+            None => current_root.to_path_buf(),
+            Some("self") => self.self_root.clone(),
+            Some(alias) => match self.aliases.get(alias) {
+                Some(root) => root.clone(),
+                None => {
+                    eprintln!(
+                        "Template {} in {} uses unknown repository alias '{}'",
+                        reference.trim(),
+                        current_file.display(),
+                        alias
+                    );
+                    return None;
+                }
+            },
+        };
+
+        let file = template_path(path, alias.is_some(), current_file, &root);
+        if !file.starts_with(&root) || !file.is_file() {
+            eprintln!(
+                "Template {} referenced from {} was not found",
+                reference.trim(),
+                current_file.display()
+            );
+            return None;
+        }
+        Some((file, root))
+    }
+}
+
+/// Tracks the state of an `${{ if }}` / `${{ elseif }}` / `${{ else }}` chain.
+#[derive(Clone, Copy)]
+enum Branch {
+    None,
+    Taken,
+    NotTaken,
+    Unknown,
+}
+
+struct Substitution<'a> {
+    parameters: &'a Parameters,
+    source: &'a Arc<Source>,
+    remaps: HashMap<String, Origin>,
+}
+
+impl<'a> Substitution<'a> {
+    fn value(&mut self, value: Value, old: &str, new: &str) -> Value {
+        if old != new {
+            self.remaps.insert(
+                new.to_string(),
+                Origin::Source(self.source.clone(), old.to_string()),
+            );
+        }
+
+        match value {
+            Value::String(s) => {
+                if let Some(parameter) = self.whole_parameter(&s) {
+                    if let Some(origin) = &parameter.origin {
+                        self.remaps.insert(new.to_string(), origin.clone());
+                    }
+                    return parameter.value.clone();
+                }
+                Value::String(self.interpolate(&s))
+            }
+            Value::Sequence(items) => Value::Sequence(self.sequence(items, old, new)),
+            Value::Mapping(map) => Value::Mapping(self.mapping(map, old, new)),
+            other => other,
+        }
+    }
+
+    fn sequence(&mut self, items: Vec<Value>, old: &str, new: &str) -> Vec<Value> {
+        let mut result = Vec::new();
+        let mut branch = Branch::None;
+
+        for (i, item) in items.into_iter().enumerate() {
+            let item_old = format!("{}/{}", old, i);
+
+            if let Some((key, children)) = single_expression(&item) {
+                match self.condition(&key, &mut branch) {
+                    Some(true) => {
+                        for (j, child) in children.into_iter().enumerate() {
+                            let child_old = format!("{}/{}/{}", item_old, escape_key(&key), j);
+                            self.push_item(&mut result, child, &child_old, new);
+                        }
+                        continue;
+                    }
+                    Some(false) => continue,
+                    None => {}
+                }
+            } else {
+                branch = Branch::None;
+            }
+
+            self.push_item(&mut result, item, &item_old, new);
+        }
+
+        result
+    }
+
+    /// Appends an item, splicing in list parameters such as `- ${{ parameters.steps }}`.
+    fn push_item(&mut self, result: &mut Vec<Value>, item: Value, old: &str, parent: &str) {
+        if let Value::String(s) = &item {
+            if let Some(parameter) = self.whole_parameter(s) {
+                if let Value::Sequence(values) = &parameter.value {
+                    for (j, value) in values.iter().enumerate() {
+                        let new = format!("{}/{}", parent, result.len());
+                        if let Some(origin) = &parameter.origin {
+                            self.remaps.insert(new, origin.child(&j.to_string()));
+                        }
+                        result.push(value.clone());
+                    }
+                    return;
+                }
+            }
+        }
+
+        let new = format!("{}/{}", parent, result.len());
+        let value = self.value(item, old, &new);
+        result.push(value);
+    }
+
+    fn mapping(&mut self, map: Mapping, old: &str, new: &str) -> Mapping {
+        let mut result = Mapping::new();
+        let mut branch = Branch::None;
+
+        for (key, value) in map {
+            let Value::String(key) = key else {
+                result.insert(key, value);
+                continue;
+            };
+            let key_old = format!("{}/{}", old, escape_key(&key));
+
+            if is_expression(&key) {
+                match self.condition(&key, &mut branch) {
+                    Some(true) => {
+                        if let Value::Mapping(inner) = value {
+                            for (k, v) in inner {
+                                let Some(k) = k.as_str().map(str::to_string) else {
+                                    continue;
+                                };
+                                let child_old = format!("{}/{}", key_old, escape_key(&k));
+                                let child_new = format!("{}/{}", new, escape_key(&k));
+                                let v = self.value(v, &child_old, &child_new);
+                                result.insert(Value::String(k), v);
+                            }
+                        }
+                        continue;
+                    }
+                    Some(false) => continue,
+                    None => {}
+                }
+            } else {
+                branch = Branch::None;
+            }
+
+            let new_key = self.interpolate(&key);
+            let key_new = format!("{}/{}", new, escape_key(&new_key));
+            let value = self.value(value, &key_old, &key_new);
+            result.insert(Value::String(new_key), value);
+        }
+
+        result
+    }
+
+    /// Evaluates a conditional insertion key. `None` means it cannot be decided statically.
+    fn condition(&self, key: &str, branch: &mut Branch) -> Option<bool> {
+        let inner = expression_body(key)?;
+        let (keyword, rest) = inner
+            .split_once(|c: char| c.is_whitespace() || c == '(')
+            .map(|(k, _)| (k, inner[k.len()..].trim()))
+            .unwrap_or((inner, ""));
+
+        let evaluate = |branch: &mut Branch| {
+            let result = Evaluator::new(rest, self.parameters)
+                .parse()
+                .map(|value| truthy(&value));
+            *branch = match result {
+                Some(true) => Branch::Taken,
+                Some(false) => Branch::NotTaken,
+                None => Branch::Unknown,
+            };
+            result
+        };
+
+        match keyword {
+            "if" => evaluate(branch),
+            "elseif" => match *branch {
+                Branch::Taken => Some(false),
+                Branch::NotTaken => evaluate(branch),
+                Branch::None | Branch::Unknown => None,
+            },
+            "else" => {
+                let result = match *branch {
+                    Branch::Taken => Some(false),
+                    Branch::NotTaken => Some(true),
+                    Branch::None | Branch::Unknown => None,
+                };
+                *branch = Branch::None;
+                result
+            }
+            _ => {
+                *branch = Branch::None;
+                None
+            }
+        }
+    }
+
+    /// Looks up a value that is exactly `${{ parameters.name }}`.
+    fn whole_parameter(&self, s: &str) -> Option<&'a Parameter> {
+        parameter_name(expression_body(s)?).and_then(|name| self.parameters.get(name))
+    }
+
+    /// Replaces `${{ parameters.name }}` inside a string when the value is a scalar.
+    fn interpolate(&self, s: &str) -> String {
+        let mut result = String::new();
+        let mut rest = s;
+
+        while let Some(start) = rest.find("${{") {
+            let Some(len) = rest[start..].find("}}").map(|end| end + 2) else {
+                break;
+            };
+            let expression = &rest[start..start + len];
+            result.push_str(&rest[..start]);
+
+            let scalar = self
+                .whole_parameter(expression)
+                .map(|parameter| &parameter.value)
+                .filter(|value| !value.is_sequence() && !value.is_mapping());
+            match scalar {
+                Some(value) => result.push_str(&scalar_to_string(value)),
+                None => result.push_str(expression),
+            }
+            rest = &rest[start + len..];
+        }
+
+        result.push_str(rest);
+        result
+    }
+}
+
+/// Returns the key and items of a `- ${{ ... }}:` list entry.
+fn single_expression(item: &Value) -> Option<(String, Vec<Value>)> {
+    let Value::Mapping(map) = item else {
+        return None;
+    };
+    match map.iter().next() {
+        Some((Value::String(key), Value::Sequence(items))) if map.len() == 1 => {
+            is_expression(key).then(|| (key.clone(), items.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// The text inside `${{ ... }}`, if the whole string is one expression.
+fn expression_body(s: &str) -> Option<&str> {
+    let body = s.trim().strip_prefix("${{")?.strip_suffix("}}")?;
+    (!body.contains("}}")).then(|| body.trim())
+}
+
+/// The parameter name in `parameters.name` or `parameters['name']`.
+fn parameter_name(expression: &str) -> Option<&str> {
+    let name = expression.strip_prefix("parameters")?;
+    let name = match name.strip_prefix('.') {
+        Some(name) => name,
+        None => name
+            .strip_prefix("['")
+            .or_else(|| name.strip_prefix("[\""))?
+            .strip_suffix("']")
+            .or_else(|| name.strip_suffix("\"]"))?,
+    };
+    name.chars()
+        .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        .then_some(name)
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Bool(b) => *b,
+        Value::Null => false,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty() && !s.eq_ignore_ascii_case("false"),
+        _ => true,
+    }
+}
+
+/// A small evaluator for the compile-time expression functions that show up in
+/// template conditions. Unknown functions or references evaluate to `None`.
+struct Evaluator<'a> {
+    input: &'a str,
+    position: usize,
+    parameters: &'a Parameters,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(input: &'a str, parameters: &'a Parameters) -> Self {
+        Evaluator {
+            input,
+            position: 0,
+            parameters,
+        }
+    }
+
+    fn parse(mut self) -> Option<Value> {
+        let value = self.expression()?;
+        self.skip_whitespace();
+        if self.position == self.input.len() {
+            value
+        } else {
+            None
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(c) {
+            self.position += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Parses one expression. The outer `Option` is a syntax error, the inner one an unknown value.
+    fn expression(&mut self) -> Option<Option<Value>> {
+        self.skip_whitespace();
+        let rest = self.rest();
+
+        if let Some(quoted) = rest.strip_prefix('\'') {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            while let Some((i, c)) = chars.next() {
+                if c == '\'' {
+                    if quoted[i + 1..].starts_with('\'') {
+                        value.push('\'');
+                        chars.next();
+                        continue;
+                    }
+                    self.position += i + 2;
+                    return Some(Some(Value::String(value)));
+                }
+                value.push(c);
+            }
+            return None;
+        }
+
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || "._-".contains(c)))
+            .unwrap_or(rest.len());
+        let word = &rest[..len];
+        if word.is_empty() {
+            return None;
+        }
+        self.position += len;
+
+        if self.eat('(') {
+            let mut args = Vec::new();
+            if !self.eat(')') {
+                loop {
+                    args.push(self.expression()?);
+                    if self.eat(')') {
+                        break;
+                    }
+                    if !self.eat(',') {
+                        return None;
+                    }
+                }
+            }
+            return Some(call(word, &args));
+        }
+
+        Some(match word.to_lowercase().as_str() {
+            "true" => Some(Value::Bool(true)),
+            "false" => Some(Value::Bool(false)),
+            "null" => Some(Value::Null),
+            _ if word.parse::<f64>().is_ok() => serde_yaml::from_str(word).ok(),
+            _ => self.reference(word),
+        })
+    }
+
+    /// Resolves `parameters.name` and `parameters.name.property`.
+    fn reference(&self, word: &str) -> Option<Value> {
+        let mut parts = word.split('.');
+        if parts.next()? != "parameters" {
+            return None;
+        }
+        let mut value = &self.parameters.get(parts.next()?)?.value;
+        for part in parts {
+            value = value.get(part)?;
+        }
+        Some(value.clone())
+    }
+}
+
+fn call(function: &str, args: &[Option<Value>]) -> Option<Value> {
+    let text = |i: usize| -> Option<String> {
+        args.get(i)?
+            .as_ref()
+            .map(|v| scalar_to_string(v).to_lowercase())
+    };
+    let bools = || args.iter().map(|a| a.as_ref().map(truthy));
+
+    let result = match function.to_lowercase().as_str() {
+        "eq" => text(0)? == text(1)?,
+        "ne" => text(0)? != text(1)?,
+        "not" => !truthy(args.first()?.as_ref()?),
+        "and" => {
+            if bools().any(|b| b == Some(false)) {
+                false
+            } else if bools().all(|b| b == Some(true)) {
+                true
+            } else {
+                return None;
+            }
+        }
+        "or" => {
+            if bools().any(|b| b == Some(true)) {
+                true
+            } else if bools().all(|b| b == Some(false)) {
+                false
+            } else {
+                return None;
+            }
+        }
+        "in" | "notin" => {
+            let needle = text(0)?;
+            let mut found = false;
+            for i in 1..args.len() {
+                found |= text(i)? == needle;
+            }
+            found == (function.eq_ignore_ascii_case("in"))
+        }
+        "startswith" => text(0)?.starts_with(&text(1)?),
+        "endswith" => text(0)?.ends_with(&text(1)?),
+        "contains" => text(0)?.contains(&text(1)?),
+        "containsvalue" => {
+            let needle = text(1)?;
+            match args.first()?.as_ref()? {
+                Value::Sequence(items) => items
+                    .iter()
+                    .any(|item| scalar_to_string(item).to_lowercase() == needle),
+                Value::Mapping(map) => map
+                    .values()
+                    .any(|item| scalar_to_string(item).to_lowercase() == needle),
+                _ => false,
+            }
+        }
+        _ => return None,
+    };
+    Some(Value::Bool(result))
+}
@@ -4,3 +4,59 @@ use std::path::PathBuf;
 pub fn sanitize_file_path(path: &str) -> PathBuf {
     PathBuf::from(path.replace(['/', '\\'], "_"))
 }
+
+/// Decodes `%XX` escapes in a URL component, e.g. `My%20Repo` becomes `My Repo`
+pub fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Quotes `s` as one word for `sh`, which is how git runs `GIT_SSH_COMMAND`.
+pub fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Masks each of `secrets` and any `user:password@` in URLs, for git output
+/// shown to the user.
+pub fn scrub_secrets(text: &str, secrets: &[String]) -> String {
+    let mut scrubbed = secrets
+        .iter()
+        .filter(|secret| !secret.is_empty())
+        .fold(text.to_string(), |text, secret| {
+            text.replace(secret.as_str(), "***")
+        });
+
+    let mut from = 0;
+    while let Some(scheme) = scrubbed[from..].find("://") {
+        let start = from + scheme + 3;
+        let authority_end = scrubbed[start..]
+            .find(|c: char| c == '/' || c.is_whitespace())
+            .map_or(scrubbed.len(), |end| start + end);
+        match scrubbed[start..authority_end].rfind('@') {
+            Some(at) if scrubbed[start..start + at].contains(':') => {
+                scrubbed.replace_range(start..start + at, "***");
+                from = start + 4;
+            }
+            _ => from = authority_end,
+        }
+    }
+    scrubbed
+}
@@ -0,0 +1,39 @@
+//! Versions in valid states. A state names either one version, such as `2` or
+//! `6.0.3`, or a semver range, such as `>=2, <4`, `^6.0` or `6.x`.
+
+use crate::{normalize_version, VersionCompare};
+use anyhow::Result;
+use semver::VersionReq;
+
+/// Whether `spec` is a range rather than a single version.
+pub fn is_range(spec: &str) -> bool {
+    normalize_version(spec).is_err() && VersionReq::parse(spec).is_ok()
+}
+
+/// Checks that `spec` is a version or a range.
+pub fn validate(spec: &str) -> Result<()> {
+    if normalize_version(spec).is_ok() || VersionReq::parse(spec).is_ok() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "'{}' is neither a version nor a range such as '>=2, <4', '^6.0' or '6.x'",
+            spec
+        ))
+    }
+}
+
+/// Whether a version found in a pipeline satisfies `spec`. Versions are padded,
+/// so `2` is the same as `2.0.0` and lies in `>=2, <4`. A version that does not
+/// parse, such as a `versionSpec` of `6.x`, only matches the same text.
+pub fn matches(spec: &str, version: &str) -> bool {
+    if spec == version {
+        return true;
+    }
+    if !is_range(spec) {
+        return spec.to_string().version_eq(version);
+    }
+    match (VersionReq::parse(spec), normalize_version(version)) {
+        (Ok(range), Ok(version)) => range.matches(&version),
+        _ => false,
+    }
+}
@@ -0,0 +1,186 @@
+//! GitHub Actions workflows and the `uses:` references they make to actions and
+//! reusable workflows.
+
+use crate::pipeline::{escape_key, scalar_to_string, Location, SpanIndex, TaskReference};
+use crate::VersionCompare;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Directory GitHub loads workflows from. Subdirectories are not searched.
+pub const WORKFLOWS_DIR: &str = ".github/workflows";
+
+/// Prefix that keeps action names apart from Azure task names, e.g. `github:actions/checkout`.
+pub const TASK_PREFIX: &str = "github:";
+
+/// A `uses:` value, either on a step (an action) or on a job (a reusable workflow).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionReference {
+    /// `owner/repo`, `owner/repo/path` or `owner/repo/.github/workflows/file.yml`.
+    pub name: String,
+    /// Tag, branch or commit SHA after the `@`.
+    pub git_ref: String,
+    pub with: BTreeMap<String, String>,
+    /// Points at the `uses:` value.
+    pub location: Location,
+}
+
+impl ActionReference {
+    /// Splits `owner/repo@ref`. Local (`./path`) and `docker://` references are
+    /// not versioned through a ref, so they return `None`.
+    pub fn parse(uses: &str, with: BTreeMap<String, String>, location: Location) -> Option<Self> {
+        let uses = uses.trim();
+        if uses.starts_with("./") || uses.starts_with("docker://") || uses.is_empty() {
+            return None;
+        }
+        let (name, git_ref) = uses.rsplit_once('@').unwrap_or((uses, ""));
+        Some(ActionReference {
+            name: name.trim().to_string(),
+            git_ref: git_ref.trim().to_string(),
+            with,
+            location,
+        })
+    }
+
+    /// The reference in the shape the task checkers work with, named with [`TASK_PREFIX`].
+    pub fn into_task_reference(self) -> TaskReference {
+        TaskReference {
+            name: format!("{}{}", TASK_PREFIX, self.name),
+            version: self.git_ref,
+            inputs: self.with,
+            location: self.location,
+            input_locations: Default::default(),
+        }
+    }
+}
+
+/// A valid state for an action: the ref a workflow is expected to pin it to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "name", rename_all = "lowercase")]
+pub enum GitRef {
+    /// Compared as a version, so `v4` also accepts `4` and `v4.0.0`.
+    Tag(String),
+    Branch(String),
+    /// A full commit SHA, compared case-insensitively.
+    Sha(String),
+}
+
+impl GitRef {
+    /// Parses `tag:v4`, `branch:main` or `sha:<commit>`. Without a prefix, a full
+    /// commit SHA is taken as a SHA and anything else as a tag.
+    pub fn from_string(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let git_ref = match s.split_once(':') {
+            Some(("tag", name)) => GitRef::Tag(name.trim().to_string()),
+            Some(("branch", name)) => GitRef::Branch(name.trim().to_string()),
+            Some(("sha", sha)) if is_full_sha(sha.trim()) => GitRef::Sha(sha.trim().to_string()),
+            Some(("sha", _)) => return Err("Expected a full 40 character commit SHA".to_string()),
+            _ if is_full_sha(s) => GitRef::Sha(s.to_string()),
+            _ => GitRef::Tag(s.to_string()),
+        };
+        if git_ref.name().is_empty() {
+            return Err("Missing ref name".to_string());
+        }
+        Ok(git_ref)
+    }
+
+    pub fn name(&self) -> &String {
+        match self {
+            GitRef::Tag(name) | GitRef::Branch(name) | GitRef::Sha(name) => name,
+        }
+    }
+
+    /// Whether a `uses: owner/repo@ref` ref satisfies this state.
+    pub fn matches(&self, git_ref: &str) -> bool {
+        match self {
+            GitRef::Tag(tag) => {
+                let strip = |v: &str| v.strip_prefix(['v', 'V']).unwrap_or(v).to_string();
+                strip(tag).version_eq(&strip(git_ref))
+            }
+            GitRef::Branch(branch) => branch == git_ref,
+            GitRef::Sha(sha) => sha.eq_ignore_ascii_case(git_ref),
+        }
+    }
+}
+
+impl std::fmt::Display for GitRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GitRef::Tag(tag) => write!(f, "@{}", tag),
+            GitRef::Branch(branch) => write!(f, "@{} (branch)", branch),
+            GitRef::Sha(sha) => write!(f, "@{} (commit)", sha),
+        }
+    }
+}
+
+fn is_full_sha(s: &str) -> bool {
+    (s.len() == 40 || s.len() == 64) && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Whether a parsed YAML document is a GitHub workflow.
+pub fn is_workflow_document(value: &Value) -> bool {
+    value.get("jobs").is_some_and(Value::is_mapping)
+}
+
+/// Lists the workflow files of a checkout, sorted by path.
+pub fn find_workflow_files(repo_path: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(repo_path.join(WORKFLOWS_DIR)) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|ext| ext == "yml" || ext == "yaml")
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Extracts every job-level and step-level `uses:` in document order.
+pub fn extract_actions(content: &str) -> Result<Vec<ActionReference>> {
+    let value: Value = serde_yaml::from_str(content).context("Invalid YAML")?;
+    let Some(Value::Mapping(jobs)) = value.get("jobs") else {
+        return Ok(Vec::new());
+    };
+    let spans = SpanIndex::build(content)?;
+
+    let mut actions = Vec::new();
+    let mut push = |node: &Value, path: &str| {
+        let Some(uses) = node.get("uses").and_then(Value::as_str) else {
+            return;
+        };
+        let location = spans.get(&format!("{}/uses", path)).unwrap_or_default();
+        actions.extend(ActionReference::parse(uses, with_inputs(node), location));
+    };
+
+    for (id, job) in jobs {
+        let Some(id) = id.as_str() else { continue };
+        let job_path = format!("/jobs/{}", escape_key(id));
+        push(job, &job_path);
+
+        if let Some(Value::Sequence(steps)) = job.get("steps") {
+            for (i, step) in steps.iter().enumerate() {
+                push(step, &format!("{}/steps/{}", job_path, i));
+            }
+        }
+    }
+
+    Ok(actions)
+}
+
+fn with_inputs(node: &Value) -> BTreeMap<String, String> {
+    match node.get("with") {
+        Some(Value::Mapping(map)) => map
+            .iter()
+            .filter_map(|(k, v)| k.as_str().map(|k| (k.to_string(), scalar_to_string(v))))
+            .collect(),
+        _ => BTreeMap::new(),
+    }
+}
@@ -0,0 +1,143 @@
+//! Where gavin keeps its database, encryption key and checkouts.
+
+use crate::config::Config;
+use crate::repo_id::RepoId;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Overrides the data directory when `--data-dir` is not given.
+pub const HOME_VAR: &str = "GAVIN_HOME";
+
+/// Where the variables gavin reads, such as [`HOME_VAR`] and the token and
+/// key variables, come from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Environment {
+    /// The process environment.
+    #[default]
+    Process,
+    /// Only these variables, so tests need not change the process's.
+    Fixed(BTreeMap<String, String>),
+}
+
+impl Environment {
+    pub fn fixed(vars: &[(&str, &str)]) -> Self {
+        Environment::Fixed(
+            vars.iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    pub fn var(&self, name: &str) -> Option<String> {
+        match self {
+            Environment::Process => std::env::var(name).ok(),
+            Environment::Fixed(vars) => vars.get(name).cloned(),
+        }
+    }
+}
+
+/// A data directory holding `gavin.db`, `gavin.key` and `temp_repos`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Workspace {
+    root: PathBuf,
+    /// Name of a shared in-memory database used instead of `gavin.db`.
+    memory_database: Option<String>,
+    environment: Environment,
+}
+
+impl Workspace {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Workspace {
+            root: root.into(),
+            memory_database: None,
+            environment: Environment::Process,
+        }
+    }
+
+    /// The same workspace, reading its variables from `environment`.
+    pub fn with_environment(self, environment: Environment) -> Self {
+        Workspace {
+            environment,
+            ..self
+        }
+    }
+
+    /// A workspace whose database lives in memory, shared by every
+    /// connection opened in this process while one of them stays open.
+    /// Checkouts and the key file still go under `root`.
+    pub fn in_memory(root: impl Into<PathBuf>) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        Workspace {
+            root: root.into(),
+            memory_database: Some(format!(
+                "gavin-{}-{}",
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            )),
+            environment: Environment::Process,
+        }
+    }
+
+    /// The data directory from `--data-dir`, then `GAVIN_HOME`, then
+    /// `data_dir` in the config file, and otherwise the current directory.
+    /// It is created if missing.
+    pub fn resolve(
+        data_dir: Option<&str>,
+        config_path: Option<&str>,
+        environment: Environment,
+    ) -> Result<Self> {
+        let root = match data_dir
+            .map(PathBuf::from)
+            .or_else(|| environment.var(HOME_VAR).map(PathBuf::from))
+        {
+            Some(root) => root,
+            None => match Config::load(config_path)?.data_dir {
+                Some(root) => root,
+                None => std::env::current_dir()?,
+            },
+        };
+
+        std::fs::create_dir_all(&root)
+            .with_context(|| format!("Failed to create data directory {}", root.display()))?;
+        Ok(Workspace::new(root).with_environment(environment))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    pub fn memory_database(&self) -> Option<&str> {
+        self.memory_database.as_deref()
+    }
+
+    pub fn database_path(&self) -> PathBuf {
+        self.root.join("gavin.db")
+    }
+
+    pub fn key_file(&self) -> PathBuf {
+        self.root.join(crate::secrets::DEFAULT_KEY_FILE)
+    }
+
+    /// Where checkouts live.
+    pub fn checkouts_root(&self) -> PathBuf {
+        self.root.join("temp_repos")
+    }
+
+    /// The checkout directory of the repository at `url`.
+    pub fn checkout_dir(&self, url: &str) -> PathBuf {
+        RepoId::parse(url).local_dir(&self.checkouts_root())
+    }
+
+    /// Where checkouts lived before they were laid out by [`RepoId`]: straight
+    /// under the root, named after the last URL segment.
+    pub fn legacy_checkout_dir(&self, url: &str) -> PathBuf {
+        self.checkouts_root()
+            .join(url.split('/').next_back().unwrap_or("repo"))
+    }
+}
@@ -0,0 +1,215 @@
+use anyhow::Result;
+use gavin::azure_devops::{AzureDevOpsClient, AzureRepo, AzureScope};
+use gavin::discovery::{plan_sync, RepoFilter};
+use gavin::pull_request::{NewPullRequest, PullRequestOutcome};
+use serde_json::json;
+use wiremock::matchers::{body_partial_json, header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const PULL_REQUESTS: &str = "/contoso/Platform/_apis/git/repositories/api/pullrequests";
+
+fn repo() -> AzureRepo {
+    AzureRepo::parse("https://contoso@dev.azure.com/contoso/Platform/_git/api").unwrap()
+}
+
+fn fix_pull_request() -> NewPullRequest {
+    NewPullRequest {
+        source_branch: "gavin/update-task-versions-20240131".to_string(),
+        title: "Update PowerShell to the standard version".to_string(),
+        description: "- `PowerShell @1 → @2`".to_string(),
+        reviewers: vec!["3f2a6c1e-0000-4000-8000-000000000001".to_string()],
+        work_items: vec!["4242".to_string()],
+    }
+}
+
+#[test]
+fn test_parses_clone_urls() {
+    assert_eq!(
+        repo(),
+        AzureRepo {
+            organization: "contoso".to_string(),
+            project: "Platform".to_string(),
+            repository: "api".to_string(),
+        }
+    );
+    assert_eq!(
+        AzureRepo::parse("https://contoso.visualstudio.com/Platform/_git/api"),
+        Some(repo())
+    );
+    assert_eq!(AzureRepo::parse("https://github.com/contoso/api"), None);
+}
+
+#[tokio::test]
+async fn test_opens_pull_request_against_default_branch() -> Result<()> {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(PULL_REQUESTS))
+        .and(query_param(
+            "searchCriteria.sourceRefName",
+            "refs/heads/gavin/update-task-versions-20240131",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "value": [], "count": 0 })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(PULL_REQUESTS))
+        .and(query_param("searchCriteria.status", "active"))
+        .and(query_param("$skip", "0"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "value": [{ "pullRequestId": 3, "sourceRefName": "refs/heads/feature/login" }],
+            "count": 1
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/contoso/Platform/_apis/git/repositories/api"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "defaultBranch": "refs/heads/develop" })),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(PULL_REQUESTS))
+        .and(query_param("api-version", "7.1"))
+        // user:token
+        .and(header("authorization", "Basic dXNlcjp0b2tlbg=="))
+        .and(body_partial_json(json!({
+            "sourceRefName": "refs/heads/gavin/update-task-versions-20240131",
+            "targetRefName": "refs/heads/develop",
+            "title": "Update PowerShell to the standard version",
+            "reviewers": [{ "id": "3f2a6c1e-0000-4000-8000-000000000001" }],
+            "workItemRefs": [{ "id": "4242" }]
+        })))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+            "pullRequestId": 17,
+            "sourceRefName": "refs/heads/gavin/update-task-versions-20240131"
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = AzureDevOpsClient::new(&server.uri(), "user", "token");
+    let outcome = client
+        .open_fix_pull_request(&repo(), &fix_pull_request(), "gavin/")
+        .await?;
+
+    match outcome {
+        PullRequestOutcome::Created(pr) => {
+            assert_eq!(pr.id, 17);
+            assert_eq!(
+                pr.web_url,
+                format!("{}/contoso/Platform/_git/api/pullrequest/17", server.uri())
+            );
+        }
+        other => panic!("Expected a new pull request, got {:?}", other),
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_skips_repos_with_open_gavin_pull_request() -> Result<()> {
+    let server = MockServer::start().await;
+    let pull = |id: u64, branch: &str| json!({ "pullRequestId": id, "sourceRefName": format!("refs/heads/{}", branch) });
+    Mock::given(method("GET"))
+        .and(path(PULL_REQUESTS))
+        .and(query_param(
+            "searchCriteria.sourceRefName",
+            "refs/heads/gavin/update-task-versions-20240131",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "value": [] })))
+        .mount(&server)
+        .await;
+    let first_page: Vec<_> = (1..=100)
+        .map(|id| pull(id, &format!("feature/{}", id)))
+        .collect();
+    Mock::given(method("GET"))
+        .and(path(PULL_REQUESTS))
+        .and(query_param("$top", "100"))
+        .and(query_param("$skip", "0"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "value": first_page })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(PULL_REQUESTS))
+        .and(query_param("$skip", "100"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "value": [pull(109, "gavin/update-task-versions-20231201")]
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let client = AzureDevOpsClient::new(&server.uri(), "user", "token");
+    let outcome = client
+        .open_fix_pull_request(&repo(), &fix_pull_request(), "gavin/")
+        .await?;
+
+    assert!(matches!(outcome, PullRequestOutcome::Existing(pr) if pr.id == 109));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_discovers_organization_repositories() -> Result<()> {
+    let server = MockServer::start().await;
+    let listing = |project: &str, name: &str, extra: serde_json::Value| {
+        let mut repo = json!({
+            "name": name,
+            "remoteUrl": format!("https://contoso@dev.azure.com/contoso/{}/_git/{}", project, name),
+            "project": { "name": project },
+            "defaultBranch": "refs/heads/main",
+            "size": 2048
+        });
+        repo.as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        repo
+    };
+    Mock::given(method("GET"))
+        .and(path("/contoso/_apis/git/repositories"))
+        .and(query_param("api-version", "7.1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "value": [
+                listing("Platform", "api", json!({})),
+                listing("Platform", "web", json!({})),
+                listing("Platform", "legacy", json!({ "isDisabled": true })),
+                listing("Platform", "scratch", json!({ "defaultBranch": null, "size": 0 })),
+                listing("Sandbox", "demo", json!({})),
+            ]
+        })))
+        .mount(&server)
+        .await;
+
+    let client = AzureDevOpsClient::new(&server.uri(), "user", "token");
+    let scope = AzureScope::parse("contoso").unwrap();
+    let discovered = client.list_repositories(&scope).await?;
+    assert_eq!(discovered.len(), 5);
+
+    let existing = vec![
+        "https://dev.azure.com/contoso/Platform/_git/api".to_string(),
+        "https://dev.azure.com/contoso/Platform/_git/retired".to_string(),
+        "https://github.com/contoso/tools".to_string(),
+        // Added by hand and filtered out, but still there
+        "https://dev.azure.com/contoso/Sandbox/_git/demo".to_string(),
+    ];
+    let filter = RepoFilter::new(None, Some("^Sandbox/"))?;
+    let sync = plan_sync(&discovered, &filter, &existing, |url| scope.contains(url));
+
+    assert_eq!(
+        sync.added,
+        vec!["https://dev.azure.com/contoso/Platform/_git/web".to_string()]
+    );
+    assert_eq!(sync.removed, vec![existing[1].clone()]);
+    assert_eq!(
+        sync.skipped,
+        vec![
+            ("Platform/legacy".to_string(), "disabled".to_string()),
+            ("Platform/scratch".to_string(), "empty".to_string()),
+        ]
+    );
+    Ok(())
+}
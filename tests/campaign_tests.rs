@@ -0,0 +1,90 @@
+use anyhow::Result;
+use gavin::campaign::{CampaignStatus, RepoStatus};
+use gavin::parse_cache::ParsedRepository;
+use gavin::{Database, SupportedTask, TaskValidState};
+use tempfile::tempdir;
+
+#[tokio::test]
+async fn test_campaign_tracks_repository_status() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let db = Database::in_memory(temp_dir.path())?;
+    let task = SupportedTask::Default("PowerShell".to_string());
+    let target = TaskValidState::Default("2".to_string());
+    let repos = vec![
+        "https://dev.azure.com/org/project/_git/api".to_string(),
+        "https://dev.azure.com/org/project/_git/web".to_string(),
+    ];
+
+    let campaign = db.create_campaign("powershell-2", &task, &target, &repos)?;
+    assert_eq!(campaign.status, CampaignStatus::Active);
+    assert_eq!(campaign.target, target);
+    assert!(db
+        .create_campaign("powershell-2", &task, &target, &repos)
+        .is_err());
+
+    let statuses: Vec<RepoStatus> = db
+        .campaign_repos(&campaign)?
+        .iter()
+        .map(|repo| repo.status)
+        .collect();
+    assert_eq!(statuses, vec![RepoStatus::Pending, RepoStatus::Pending]);
+
+    db.set_campaign_repo_status(&campaign, &repos[0], RepoStatus::PrOpen, "https://pr/1")?;
+    db.set_campaign_repo_status(&campaign, &repos[1], RepoStatus::Failed, "push rejected")?;
+    let stored = db.campaign_repos(&campaign)?;
+    assert_eq!(stored[0].status, RepoStatus::PrOpen);
+    assert_eq!(stored[0].detail, "https://pr/1");
+    assert!(stored[1].status.needs_push());
+    assert!(db
+        .set_campaign_repo_status(
+            &campaign,
+            "https://example.com/other",
+            RepoStatus::Merged,
+            ""
+        )
+        .is_err());
+
+    db.set_campaign_status(&campaign, CampaignStatus::Cancelled)?;
+    let reloaded = db.get_campaign("powershell-2")?.unwrap();
+    assert_eq!(reloaded.status, CampaignStatus::Cancelled);
+    assert_eq!(db.list_campaigns()?.len(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_deleting_a_repository_skips_it_in_campaigns() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let db = Database::in_memory(temp_dir.path())?;
+    let task = SupportedTask::Default("PowerShell".to_string());
+    let target = TaskValidState::Default("2".to_string());
+    let repos = vec![
+        "https://dev.azure.com/org/project/_git/api".to_string(),
+        "https://dev.azure.com/org/project/_git/web".to_string(),
+    ];
+    for repo in &repos {
+        db.add_repository_sync(repo)?;
+    }
+    let campaign = db.create_campaign("powershell-2", &task, &target, &repos)?;
+    db.set_campaign_repo_status(&campaign, &repos[1], RepoStatus::Merged, "")?;
+    db.save_parsed_repositories(&[(
+        repos[0].clone(),
+        ParsedRepository {
+            head_sha: "abc".to_string(),
+            context: "context".to_string(),
+            files: Vec::new(),
+        },
+    )])?;
+
+    db.delete_repository(&repos[0])?;
+    db.delete_repository(&repos[1])?;
+
+    let statuses: Vec<RepoStatus> = db
+        .campaign_repos(&campaign)?
+        .iter()
+        .map(|repo| repo.status)
+        .collect();
+    assert_eq!(statuses, vec![RepoStatus::Skipped, RepoStatus::Merged]);
+    assert!(db.parsed_repositories()?.is_empty());
+    Ok(())
+}
@@ -1,14 +1,12 @@
 use anyhow::Result;
 use gavin::{Database, GitVersionState, SupportedTask, TaskValidState};
-use std::env;
 use tempfile::tempdir;
 
 #[tokio::test]
 async fn test_task_state_deletion() -> Result<()> {
     // Setup
     let temp_dir = tempdir()?;
-    env::set_current_dir(temp_dir.path())?;
-    let db = Database::new()?;
+    let db = Database::in_memory(temp_dir.path())?;
 
     // Add a test state
     let task = SupportedTask::Default("copyfiles".to_string());
@@ -33,8 +31,7 @@ async fn test_task_state_deletion() -> Result<()> {
 #[tokio::test]
 async fn test_task_state_formatting() -> Result<()> {
     let temp_dir = tempdir()?;
-    env::set_current_dir(temp_dir.path())?;
-    let db = Database::new()?;
+    let db = Database::in_memory(temp_dir.path())?;
 
     // Test GitVersion state formatting
     let gv_task = SupportedTask::Gitversion;
@@ -74,8 +71,7 @@ async fn test_task_state_formatting() -> Result<()> {
 #[tokio::test]
 async fn test_cli_task_state_operations() -> Result<()> {
     let temp_dir = tempdir()?;
-    env::set_current_dir(temp_dir.path())?;
-    let db = Database::new()?;
+    let db = Database::in_memory(temp_dir.path())?;
 
     // Test adding a task state
     let task = "copyfiles";
@@ -0,0 +1,54 @@
+//! Fixtures shared by the integration tests.
+#![allow(dead_code)]
+
+use anyhow::Result;
+use clap::Parser;
+use gavin::{handle_cli_args, Cli, Database};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use tempfile::{tempdir, TempDir};
+
+/// Runs git in `dir` as a test user and returns its trimmed output.
+pub fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .output()
+        .expect("git runs");
+    assert!(output.status.success(), "git {:?}: {:?}", args, output);
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// Adds `url` and commits `files`, as paths relative to the checkout and their
+/// content, to a checkout where gavin looks for it. Returns the HEAD commit.
+pub fn committed_checkout(db: &Database, url: &str, files: &[(&str, &str)]) -> Result<String> {
+    db.add_repository_sync(url)?;
+    let checkout = db.workspace().checkout_dir(url);
+    for (path, content) in files {
+        let path = checkout.join(path);
+        fs::create_dir_all(path.parent().unwrap_or(&checkout))?;
+        fs::write(path, content)?;
+    }
+    git(&checkout, &["init", "-q"]);
+    git(&checkout, &["add", "."]);
+    git(&checkout, &["commit", "-q", "-m", "pipelines"]);
+    Ok(git(&checkout, &["rev-parse", "HEAD"]))
+}
+
+/// Runs `--check-tasks --no-update` with the extra `args`.
+pub async fn check_tasks(db: &Database, args: &[&str]) -> Result<()> {
+    let args = ["gavin"]
+        .iter()
+        .chain(args)
+        .chain(&["--check-tasks", "--no-update"]);
+    handle_cli_args(&Cli::parse_from(args), db).await
+}
+
+/// An in-memory database whose workspace lives in the returned directory.
+pub fn test_database() -> Result<(TempDir, Database)> {
+    let temp_dir = tempdir()?;
+    let db = Database::in_memory(temp_dir.path())?;
+    Ok((temp_dir, db))
+}
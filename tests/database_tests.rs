@@ -1,16 +1,13 @@
 use anyhow::Result;
 use gavin::config::Config;
 use gavin::{Database, GitVersionState, SupportedTask, TaskValidState};
-use std::env;
 use tempfile::tempdir;
 
 #[tokio::test]
 async fn test_task_state_operations() -> Result<()> {
     // Create temporary directory for test database
     let temp_dir = tempdir()?;
-    env::set_current_dir(temp_dir.path())?;
-
-    let db = Database::new()?;
+    let db = Database::in_memory(temp_dir.path())?;
 
     // Test PowerShell state
     let powershell_task = SupportedTask::Default("powershell".to_string());
@@ -43,13 +40,13 @@ async fn test_task_state_operations() -> Result<()> {
 async fn test_config_merge() -> Result<()> {
     // Create temporary directory for test database
     let temp_dir = tempdir()?;
-    env::set_current_dir(temp_dir.path())?;
-
-    let db = Database::new()?;
+    let db = Database::in_memory(temp_dir.path())?;
 
     // Create a test config
     let mut config = Config {
         task_states: Default::default(),
+        data_dir: None,
+        policies: None,
     };
 
     // Add GitVersion state
@@ -64,8 +61,16 @@ async fn test_config_merge() -> Result<()> {
         vec!["2.0".to_string(), "3.0".to_string()],
     );
 
+    // Preview the merge
+    let changes = db.config_state_changes(&config)?;
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[0].task, SupportedTask::Gitversion);
+    assert_eq!(changes[1].added.len(), 2);
+    assert!(changes[1].removed.is_empty());
+
     // Merge config into database
     db.merge_config_states(&config)?;
+    assert!(db.config_state_changes(&config)?.is_empty());
 
     // Verify GitVersion states
     let gitversion_states = db.list_valid_states(&SupportedTask::Gitversion)?;
@@ -96,8 +101,7 @@ async fn test_config_merge() -> Result<()> {
 #[tokio::test]
 async fn test_case_insensitive_task_names() -> Result<()> {
     let temp_dir = tempdir()?;
-    env::set_current_dir(temp_dir.path())?;
-    let db = Database::new()?;
+    let db = Database::in_memory(temp_dir.path())?;
 
     // Add test states with different cases
     let task_lower = SupportedTask::Default("powershell".to_string());
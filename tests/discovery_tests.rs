@@ -0,0 +1,118 @@
+use anyhow::Result;
+use gavin::discovery::{plan_sync, RepoFilter};
+use gavin::github_api::GithubClient;
+use gavin::gitlab_api::GitlabClient;
+use gavin::host::HostType;
+use serde_json::json;
+use wiremock::matchers::{header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn github_repo(name: &str, extra: serde_json::Value) -> serde_json::Value {
+    let mut repo = json!({
+        "full_name": format!("contoso/{}", name),
+        "html_url": format!("https://github.com/contoso/{}", name),
+        "archived": false,
+        "fork": false,
+        "size": 120,
+        "visibility": "private",
+        "topics": ["pipelines"]
+    });
+    repo.as_object_mut()
+        .unwrap()
+        .extend(extra.as_object().unwrap().clone());
+    repo
+}
+
+#[tokio::test]
+async fn test_discovers_github_organization_across_pages() -> Result<()> {
+    let server = MockServer::start().await;
+    let first_page: Vec<_> = (0..100)
+        .map(|i| github_repo(&format!("service-{:03}", i), json!({})))
+        .collect();
+    Mock::given(method("GET"))
+        .and(path("/orgs/contoso/repos"))
+        .and(query_param("page", "1"))
+        .and(header("authorization", "Bearer token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(first_page))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/orgs/contoso/repos"))
+        .and(query_param("page", "2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+            github_repo("old", json!({ "archived": true })),
+            github_repo("upstream-fork", json!({ "fork": true })),
+            github_repo("docs", json!({ "visibility": "public" })),
+            github_repo("infra", json!({ "topics": ["terraform"] })),
+        ])))
+        .mount(&server)
+        .await;
+
+    let client = GithubClient::new(&server.uri(), "token");
+    let discovered = client.list_org_repositories("contoso").await?;
+    assert_eq!(discovered.len(), 104);
+
+    let filter = RepoFilter {
+        visibility: vec!["private".to_string()],
+        topics: vec!["pipelines".to_string()],
+        ..RepoFilter::default()
+    };
+    let sync = plan_sync(&discovered, &filter, &[], |_| false);
+    assert_eq!(sync.added.len(), 100);
+    assert!(sync
+        .added
+        .iter()
+        .all(|url| url.starts_with("https://github.com/contoso/service-")));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_discovers_gitlab_group_projects() -> Result<()> {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/groups/platform%2Fci/projects"))
+        .and(query_param("include_subgroups", "true"))
+        .and(header("PRIVATE-TOKEN", "token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+            {
+                "path_with_namespace": "platform/ci/templates",
+                "web_url": "https://gitlab.example.com/platform/ci/templates",
+                "visibility": "internal",
+                "topics": []
+            },
+            {
+                "path_with_namespace": "platform/ci/runner-fork",
+                "web_url": "https://gitlab.example.com/platform/ci/runner-fork",
+                "forked_from_project": { "id": 7 }
+            },
+            {
+                "path_with_namespace": "platform/ci/new",
+                "web_url": "https://gitlab.example.com/platform/ci/new",
+                "empty_repo": true
+            }
+        ])))
+        .mount(&server)
+        .await;
+
+    let client = GitlabClient::new(&server.uri(), "token");
+    let discovered = client.list_group_projects("platform/ci").await?;
+    let existing = vec!["https://gitlab.example.com/platform/ci/removed".to_string()];
+    let sync = plan_sync(&discovered, &RepoFilter::default(), &existing, |url| {
+        url.contains("/platform/ci/")
+    });
+
+    assert_eq!(
+        sync.added,
+        vec!["https://gitlab.example.com/platform/ci/templates".to_string()]
+    );
+    assert_eq!(sync.removed, existing);
+    assert_eq!(
+        sync.skipped,
+        vec![("platform/ci/new".to_string(), "empty".to_string())]
+    );
+
+    // A self-hosted server cannot be recognised from its URL, hence the stored host
+    assert_eq!(HostType::detect(&sync.added[0]), HostType::Other);
+    assert_eq!(HostType::GitLab.git_username("alice"), "oauth2");
+    Ok(())
+}
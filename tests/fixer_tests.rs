@@ -0,0 +1,106 @@
+use anyhow::Result;
+use gavin::fixer::{apply_edits, patches, plan_edits, value_edit, version_edit, Fix};
+use gavin::pipeline::extract_tasks;
+use std::collections::BTreeMap;
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn test_rewrites_only_the_version() {
+    let content = "\
+steps:
+  # pinned until the next release
+  - task: PowerShell@2 # keep inline
+    inputs:
+      targetType: inline
+  - { task: \"CopyFiles@1\", inputs: { Contents: '**' } }
+  - task: gitversion/setup@0
+    inputs:
+      versionSpec: '5.x'   # trailing comment
+";
+    let tasks = extract_tasks(content).unwrap();
+    let edits = vec![
+        version_edit(content, tasks[0].location, "2", "3").unwrap(),
+        version_edit(content, tasks[1].location, "1", "2").unwrap(),
+        version_edit(content, tasks[2].location, "0", "3").unwrap(),
+        value_edit(
+            content,
+            tasks[2].input_locations["versionSpec"],
+            "5.x",
+            "6.x",
+        )
+        .unwrap(),
+    ];
+
+    let updated = apply_edits(content, &edits).unwrap();
+    assert_eq!(
+        updated,
+        "\
+steps:
+  # pinned until the next release
+  - task: PowerShell@3 # keep inline
+    inputs:
+      targetType: inline
+  - { task: \"CopyFiles@2\", inputs: { Contents: '**' } }
+  - task: gitversion/setup@3
+    inputs:
+      versionSpec: '6.x'   # trailing comment
+"
+    );
+}
+
+#[test]
+fn test_leaves_parameterised_versions_alone() {
+    let content = "\
+steps:
+  - task: UseNode@${{ parameters.nodeVersion }}
+  - task: NuGetCommand@12
+";
+    let tasks = extract_tasks(content).unwrap();
+    assert!(version_edit(content, tasks[0].location, "1", "2").is_err());
+    // A version that only matches the end of another number is not replaced
+    assert!(version_edit(content, tasks[1].location, "2", "3").is_err());
+
+    let edit = version_edit(content, tasks[1].location, "12", "13").unwrap();
+    assert!(apply_edits("steps: []\n", &[edit]).is_err());
+}
+
+#[test]
+fn test_patches_per_repository() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let repo = temp_dir.path().join("app");
+    fs::create_dir_all(repo.join("ci"))?;
+    let path = repo.join("ci/build.yml");
+    let content = "steps:\n  - task: PowerShell@1\n";
+    fs::write(&path, content)?;
+
+    let task = &extract_tasks(content)?[0];
+    let plan = plan_edits(vec![Fix {
+        task_name: "PowerShell".to_string(),
+        repo_name: "app".to_string(),
+        path: path.clone(),
+        location: task.location,
+        current: "1".to_string(),
+        target: "2".to_string(),
+        input: None,
+    }]);
+
+    let repo_paths = BTreeMap::from([("app".to_string(), repo)]);
+    let patches = patches(&plan, &repo_paths)?;
+    assert_eq!(
+        patches["app"],
+        "\
+diff --git a/ci/build.yml b/ci/build.yml
+--- a/ci/build.yml
++++ b/ci/build.yml
+@@ -1,2 +1,2 @@
+ steps:
+-  - task: PowerShell@1
++  - task: PowerShell@2
+"
+    );
+    // Nothing is written
+    assert_eq!(fs::read_to_string(&path)?, content);
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use anyhow::Result;
+use gavin::gitlab::{expand, parse_file, split_image, ReferenceKind};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn test_parses_includes_and_images() {
+    let content = "\
+include:
+  - project: platform/ci-templates
+    ref: v2.1.0
+    file:
+      - /build.yml
+      - /test.yml
+  - component: $CI_SERVER_FQDN/security/scanners/secret-detection@1.4
+  - remote: https://example.com/ci/lint.yml
+  - local: /ci/deploy.yml
+  - ci/common.yml
+image: ruby:3.2
+default:
+  services:
+    - postgres:16
+    - name: redis@sha256:0123abcd
+      alias: cache
+build:
+  image:
+    name: registry.example.com:5000/tools/builder
+  script: make
+";
+    let file = parse_file(content).unwrap();
+    assert_eq!(file.local_includes, ["/ci/deploy.yml", "ci/common.yml"]);
+
+    let refs = &file.references;
+    assert_eq!(refs.len(), 7);
+
+    assert_eq!(refs[0].kind, ReferenceKind::Project);
+    assert_eq!(refs[0].task_name(), "gitlab:project:platform/ci-templates");
+    assert_eq!(refs[0].version, "v2.1.0");
+    assert_eq!(refs[0].location.line, 3);
+    assert_eq!(&content[refs[0].location.offset..][..6], "v2.1.0");
+
+    assert_eq!(
+        refs[1].task_name(),
+        "gitlab:component:$CI_SERVER_FQDN/security/scanners/secret-detection"
+    );
+    assert_eq!(refs[1].version, "1.4");
+
+    assert_eq!(refs[2].task_name(), "gitlab:remote");
+    assert_eq!(refs[2].version, "https://example.com/ci/lint.yml");
+
+    let images: Vec<_> = refs[3..]
+        .iter()
+        .map(|r| (r.task_name(), r.version.as_str(), r.location.line))
+        .collect();
+    assert_eq!(
+        images,
+        [
+            ("gitlab:image:ruby".to_string(), "3.2", 11),
+            ("gitlab:image:postgres".to_string(), "16", 14),
+            ("gitlab:image:redis".to_string(), "sha256:0123abcd", 15),
+            (
+                "gitlab:image:registry.example.com:5000/tools/builder".to_string(),
+                "latest",
+                19
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_split_image() {
+    assert_eq!(split_image("node"), ("node", "latest"));
+    assert_eq!(split_image("node:20-alpine"), ("node", "20-alpine"));
+    assert_eq!(split_image("node:20@sha256:abc"), ("node", "20@sha256:abc"));
+    assert_eq!(
+        split_image("localhost:5000/team/app:1.2"),
+        ("localhost:5000/team/app", "1.2")
+    );
+}
+
+#[test]
+fn test_follows_local_includes() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let repo = temp_dir.path();
+    fs::create_dir_all(repo.join("ci"))?;
+    fs::write(
+        repo.join(".gitlab-ci.yml"),
+        "include:\n  - local: ci/jobs.yml\nimage: alpine:3.19\n",
+    )?;
+    fs::write(
+        repo.join("ci/jobs.yml"),
+        "include: /.gitlab-ci.yml\ntest:\n  image: python:3.12\n",
+    )?;
+
+    let tasks = expand(repo, &repo.join(".gitlab-ci.yml"))?;
+    assert_eq!(tasks.len(), 2);
+
+    assert_eq!(tasks[0].task.name, "gitlab:image:alpine");
+    assert!(tasks[0].include_chain.is_empty());
+
+    assert_eq!(tasks[1].task.name, "gitlab:image:python");
+    assert_eq!(tasks[1].task.version, "3.12");
+    assert_eq!(tasks[1].source, repo.join("ci/jobs.yml"));
+    assert_eq!(tasks[1].task.location.line, 3);
+    assert_eq!(tasks[1].include_chain, vec![repo.join("ci/jobs.yml")]);
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use anyhow::Result;
+use gavin::metadata::{parse_csv, parse_yaml, RepoMetadata, RepoSelector};
+use gavin::Database;
+use tempfile::tempdir;
+
+#[test]
+fn test_parses_csv_and_yaml_metadata() -> Result<()> {
+    let csv = "url,owner,tags,groups\n\
+               https://dev.azure.com/org/Platform/_git/api,payments,dotnet;critical,backend\n\
+               https://dev.azure.com/org/Platform/_git/web,,,\n";
+    let from_csv = parse_csv(csv)?;
+    assert_eq!(
+        from_csv[0],
+        RepoMetadata {
+            url: "https://dev.azure.com/org/Platform/_git/api".to_string(),
+            owner: Some("payments".to_string()),
+            tags: vec!["dotnet".to_string(), "critical".to_string()],
+            groups: vec!["backend".to_string()],
+        }
+    );
+    assert_eq!(from_csv[1].owner, None);
+    assert!(from_csv[1].tags.is_empty());
+
+    let yaml = r#"
+- url: https://dev.azure.com/org/Platform/_git/api
+  owner: payments
+  tags: [dotnet, critical]
+  groups: [backend]
+- url: https://dev.azure.com/org/Platform/_git/web
+"#;
+    assert_eq!(parse_yaml(yaml)?, from_csv);
+    assert!(parse_csv("owner,tags\npayments,dotnet\n").is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_selects_repositories_by_group_and_tag() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let db = Database::in_memory(temp_dir.path())?;
+
+    let api = "https://dev.azure.com/org/Platform/_git/api";
+    let web = "https://dev.azure.com/org/Platform/_git/web";
+    db.add_repository_sync(api)?;
+    db.add_repository_sync(web)?;
+    db.set_repository_metadata(&RepoMetadata {
+        url: api.to_string(),
+        owner: Some("payments".to_string()),
+        tags: vec!["dotnet".to_string()],
+        groups: vec!["backend".to_string()],
+    })?;
+    db.set_repository_metadata(&RepoMetadata {
+        url: web.to_string(),
+        owner: None,
+        tags: vec!["node".to_string()],
+        groups: vec!["backend".to_string(), "frontend".to_string()],
+    })?;
+
+    // Re-adding a repository keeps its metadata
+    db.add_repository_sync(api)?;
+    assert_eq!(
+        db.repository_metadata(api)?.owner.as_deref(),
+        Some("payments")
+    );
+
+    let select = |groups: &[&str], tags: &[&str]| {
+        db.select_repositories(&RepoSelector {
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+        })
+    };
+    assert_eq!(select(&[], &[])?.len(), 2);
+    assert_eq!(select(&["Frontend"], &[])?, vec![web.to_string()]);
+    assert_eq!(select(&["backend"], &["dotnet"])?, vec![api.to_string()]);
+    assert!(select(&["frontend"], &["dotnet"])?.is_empty());
+
+    assert!(db
+        .set_repository_metadata(&RepoMetadata {
+            url: "https://example.com/unknown".to_string(),
+            ..RepoMetadata::default()
+        })
+        .is_err());
+    Ok(())
+}
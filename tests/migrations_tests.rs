@@ -0,0 +1,66 @@
+use anyhow::Result;
+use gavin::migrations::{migrate, schema_version, SCHEMA_VERSION};
+use rusqlite::Connection;
+
+/// The schema of databases written before any columns were added.
+const ORIGINAL_SCHEMA: &str = "
+    CREATE TABLE repositories (id INTEGER PRIMARY KEY, url TEXT NOT NULL UNIQUE);
+    CREATE TABLE git_credentials (id INTEGER PRIMARY KEY, username TEXT NOT NULL, token BLOB NOT NULL);
+    CREATE TABLE valid_states (id INTEGER PRIMARY KEY, task TEXT NOT NULL, state_json TEXT NOT NULL);
+    INSERT INTO repositories (url) VALUES ('https://contoso.visualstudio.com/Platform/_git/api');
+    INSERT INTO valid_states (task, state_json) VALUES ('powershell', '{\"Default\":\"2\"}');
+";
+
+fn columns(conn: &Connection, table: &str) -> Result<Vec<String>> {
+    Ok(conn
+        .prepare(&format!("SELECT name FROM pragma_table_info('{}')", table))?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?)
+}
+
+#[test]
+fn test_upgrades_databases_from_older_schemas() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+
+    // Unversioned, as every database was before migrations
+    let mut original = Connection::open(dir.path().join("original.db"))?;
+    original.execute_batch(ORIGINAL_SCHEMA)?;
+    migrate(&mut original)?;
+    assert_eq!(schema_version(&original)?, SCHEMA_VERSION);
+    let canonical: String =
+        original.query_row("SELECT canonical FROM repositories", [], |row| row.get(0))?;
+    assert_eq!(canonical, "dev.azure.com/contoso/Platform/api");
+    let states: i64 =
+        original.query_row("SELECT COUNT(*) FROM valid_states", [], |row| row.get(0))?;
+    assert_eq!(states, 1);
+
+    // Unversioned but with some later columns and tables already in place
+    let mut partial = Connection::open(dir.path().join("partial.db"))?;
+    partial.execute_batch(ORIGINAL_SCHEMA)?;
+    partial.execute_batch(
+        "ALTER TABLE repositories ADD COLUMN host TEXT;
+         UPDATE repositories SET host = 'azure-devops';
+         CREATE TABLE repository_tags (repo_url TEXT NOT NULL, tag TEXT NOT NULL, PRIMARY KEY (repo_url, tag));",
+    )?;
+    migrate(&mut partial)?;
+    assert_eq!(schema_version(&partial)?, SCHEMA_VERSION);
+    assert_eq!(
+        columns(&partial, "repositories")?,
+        vec!["id", "url", "host", "owner", "canonical"]
+    );
+    assert!(columns(&partial, "git_credentials")?.contains(&"ssh_key".to_string()));
+
+    // Migrating again changes nothing
+    migrate(&mut partial)?;
+    assert_eq!(schema_version(&partial)?, SCHEMA_VERSION);
+    Ok(())
+}
+
+#[test]
+fn test_refuses_databases_from_newer_versions() -> Result<()> {
+    let mut conn = Connection::open_in_memory()?;
+    conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1)?;
+    let error = migrate(&mut conn).unwrap_err().to_string();
+    assert!(error.contains("Upgrade gavin"), "{}", error);
+    Ok(())
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use anyhow::Result;
+use common::{check_tasks, committed_checkout, git, test_database};
+use gavin::Database;
+use std::fs;
+
+async fn scanned_versions(db: &Database) -> Result<Vec<String>> {
+    check_tasks(db, &[]).await?;
+    let scan = db.latest_scan()?.expect("a scan was recorded");
+    Ok(db
+        .scan_occurrences(scan.id)?
+        .into_iter()
+        .map(|o| format!("{}@{}", o.task, o.version))
+        .collect())
+}
+
+#[tokio::test]
+async fn test_only_changed_files_are_parsed_again() -> Result<()> {
+    let (_dir, db) = test_database()?;
+    let url = "https://dev.azure.com/contoso/Platform/_git/web";
+    let head = committed_checkout(
+        &db,
+        url,
+        &[
+            ("azure-pipelines.yml", "steps:\n  - task: PowerShell@2\n"),
+            ("build/ci.yml", "steps:\n  - template: npm.yml\n"),
+            ("build/npm.yml", "steps:\n  - task: Npm@1\n"),
+        ],
+    )?;
+    let checkout = db.workspace().checkout_dir(url);
+    let pipeline = checkout.join("azure-pipelines.yml");
+    let template = checkout.join("build/npm.yml");
+
+    assert_eq!(scanned_versions(&db).await?, ["Npm@1", "PowerShell@2"]);
+    let mut parsed = db.parsed_repositories()?;
+    let mut cached = parsed.remove(url).expect("the parse was stored");
+    assert_eq!(cached.head_sha, head);
+    let stored = cached
+        .files
+        .iter()
+        .find(|file| file.path == pipeline)
+        .expect("the pipeline was stored");
+    assert_eq!(
+        stored.blob_sha,
+        git(&checkout, &["hash-object", "azure-pipelines.yml"])
+    );
+
+    // Nothing moved, so the stored parses are used as they are
+    for file in &mut cached.files {
+        for pipeline in &mut file.pipelines {
+            for resolved in &mut pipeline.tasks {
+                resolved.task.version = "9".to_string();
+            }
+        }
+    }
+    db.save_parsed_repositories(&[(url.to_string(), cached)])?;
+    assert_eq!(scanned_versions(&db).await?, ["Npm@9", "PowerShell@9"]);
+
+    // Only the changed file is parsed again, even though HEAD did not move
+    fs::write(&pipeline, "steps:\n  - task: PowerShell@3\n")?;
+    assert_eq!(scanned_versions(&db).await?, ["Npm@9", "PowerShell@3"]);
+
+    // A changed template is parsed again with the pipelines including it
+    fs::write(&template, "steps:\n  - task: Npm@2\n")?;
+    assert_eq!(scanned_versions(&db).await?, ["Npm@2", "PowerShell@3"]);
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use gavin::pipeline::{extract_steps, extract_tasks, Step};
+
+#[test]
+fn test_extracts_tasks_with_locations() {
+    let content = "\
+steps:
+  - task: PowerShell@2
+    inputs:
+      targetType: inline
+  - script: |
+      echo \"task: Fake@9\"
+  - task: 'UseNode@1'
+";
+    let tasks = extract_tasks(content).unwrap();
+    assert_eq!(tasks.len(), 2);
+
+    assert_eq!(tasks[0].name, "PowerShell");
+    assert_eq!(tasks[0].version, "2");
+    assert_eq!(tasks[0].inputs["targetType"], "inline");
+    assert_eq!(tasks[0].location.line, 2);
+    assert_eq!(tasks[0].location.column, 11);
+    assert_eq!(&content[tasks[0].location.offset..][..12], "PowerShell@2");
+
+    assert_eq!(tasks[1].name, "UseNode");
+    assert_eq!(tasks[1].version, "1");
+    assert_eq!(tasks[1].location.line, 7);
+    assert_eq!(&content[tasks[1].location.offset..][..10], "'UseNode@1");
+}
+
+#[test]
+fn test_flow_mappings_and_block_scalars() {
+    let content = "\
+steps:
+  - { task: \"CopyFiles@2\", inputs: { Contents: '**' } }
+  - task: >
+      DotNetCoreCLI@2
+";
+    let tasks = extract_tasks(content).unwrap();
+    assert_eq!(tasks.len(), 2);
+    assert_eq!(tasks[0].name, "CopyFiles");
+    assert_eq!(tasks[0].inputs["Contents"], "**");
+    assert_eq!(tasks[0].location.line, 2);
+    assert_eq!(tasks[1].name, "DotNetCoreCLI");
+    assert_eq!(tasks[1].version, "2");
+    assert_eq!(tasks[1].location.line, 4);
+}
+
+#[test]
+fn test_anchors_resolve_to_definition() {
+    let content = "\
+jobs:
+  - job: A
+    steps:
+      - &restore
+        task: NuGetCommand@2
+  - job: B
+    steps:
+      - *restore
+";
+    let tasks = extract_tasks(content).unwrap();
+    assert_eq!(tasks.len(), 2);
+    assert!(tasks.iter().all(|t| t.name == "NuGetCommand"));
+    assert!(tasks.iter().all(|t| t.location.line == 5));
+}
+
+#[test]
+fn test_stages_deployments_and_expressions() {
+    let content = "\
+stages:
+  - stage: Build
+    jobs:
+      - job: Build
+        steps:
+          - ${{ if eq(parameters.sign, true) }}:
+            - task: gitversion/setup@3
+              inputs:
+                versionSpec: '6.0.3'
+  - stage: Deploy
+    jobs:
+      - deployment: Prod
+        strategy:
+          runOnce:
+            deploy:
+              steps:
+                - checkout: self
+                - task: AzureCLI@2
+            on:
+              failure:
+                steps:
+                  - task: PowerShell@2
+";
+    let steps = extract_steps(content).unwrap();
+    assert_eq!(steps.len(), 4);
+    assert!(matches!(steps[1].step, Step::Other(_)));
+    assert_eq!(steps[1].location.line, 17);
+
+    let tasks = extract_tasks(content).unwrap();
+    let names: Vec<_> = tasks.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(names, ["gitversion/setup", "AzureCLI", "PowerShell"]);
+    assert_eq!(tasks[0].inputs["versionSpec"], "6.0.3");
+    assert_eq!(tasks[0].location.line, 7);
+    assert_eq!(tasks[2].location.line, 22);
+}
+
+#[test]
+fn test_non_pipeline_yaml() {
+    assert!(extract_tasks("key: value\nlist: [1, 2]\n")
+        .unwrap()
+        .is_empty());
+    assert!(extract_tasks("").unwrap().is_empty());
+    assert!(extract_tasks("steps: ${{ parameters.steps }}\n")
+        .unwrap()
+        .is_empty());
+    assert!(extract_tasks("steps:\n  - task: [unclosed\n").is_err());
+}
@@ -0,0 +1,63 @@
+use anyhow::Result;
+use gavin::platform::{for_task, sparse_patterns, PLATFORMS};
+use gavin::TemplateResolver;
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn test_task_names_map_to_platforms() {
+    assert_eq!(for_task("PowerShell").name(), "Azure DevOps");
+    assert_eq!(for_task("gitversion/setup").name(), "Azure DevOps");
+    assert_eq!(for_task("github:actions/checkout").name(), "GitHub Actions");
+    assert_eq!(for_task("gitlab:image:node").name(), "GitLab CI");
+
+    let patterns = sparse_patterns();
+    assert!(patterns.contains(&"**/azure-pipelines.yml"));
+    assert!(patterns.contains(&".github/workflows/*.yml"));
+    assert!(patterns.contains(&".gitlab-ci.yml"));
+}
+
+#[test]
+fn test_each_platform_reads_its_own_files() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let repo = temp_dir.path();
+    fs::create_dir_all(repo.join(".github/workflows"))?;
+    fs::write(
+        repo.join("azure-pipelines.yml"),
+        "steps:\n  - task: PowerShell@2\n",
+    )?;
+    fs::write(
+        repo.join(".github/workflows/ci.yml"),
+        "on: push\njobs:\n  build:\n    steps:\n      - uses: actions/checkout@v4\n",
+    )?;
+    fs::write(
+        repo.join(".gitlab-ci.yml"),
+        "stages: [build]\nimage: node:20\n",
+    )?;
+
+    let resolver = TemplateResolver::new(Vec::new());
+    let mut found = Vec::new();
+    for platform in PLATFORMS {
+        let files = platform.find_files(repo);
+        assert_eq!(files.len(), 1, "{} files: {:?}", platform.name(), files);
+
+        for pipeline in platform.pipeline_tasks(&resolver, repo, &files) {
+            for resolved in pipeline.tasks {
+                assert_eq!(for_task(&resolved.task.name).name(), platform.name());
+                found.push(format!("{}@{}", resolved.task.name, resolved.task.version));
+            }
+        }
+    }
+
+    found.sort();
+    assert_eq!(
+        found,
+        [
+            "PowerShell@2",
+            "github:actions/checkout@v4",
+            "gitlab:image:node@20"
+        ]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use anyhow::Result;
+use common::{check_tasks, committed_checkout, test_database};
+use gavin::policy::Severity;
+use std::fs;
+
+const CONFIG: &str = r#"
+policies:
+  forbidden:
+    - task: CmdLine
+      use_instead: Bash
+  deprecated:
+    - task: PowerShell
+      versions: ["1"]
+      sunset: "2000-01-01"
+    - task: PowerShell
+      versions: [">=2"]
+      sunset: "2999-01-01"
+      use_instead: Pwsh
+  required:
+    - task: CredScan
+"#;
+
+#[tokio::test]
+async fn test_check_tasks_reports_policy_violations() -> Result<()> {
+    let (dir, db) = test_database()?;
+    committed_checkout(
+        &db,
+        "https://dev.azure.com/contoso/Platform/_git/api",
+        &[(
+            "azure-pipelines.yml",
+            "steps:\n  - task: CmdLine@2\n  - task: PowerShell@1\n  - task: PowerShell@2\n",
+        )],
+    )?;
+    let config_path = dir.path().join("gavinconfig.yml");
+    fs::write(&config_path, CONFIG)?;
+
+    check_tasks(&db, &["--config", &config_path.to_string_lossy()]).await?;
+
+    let policies = db.task_policies()?;
+    assert_eq!(
+        (
+            policies.forbidden.len(),
+            policies.deprecated.len(),
+            policies.required.len()
+        ),
+        (1, 2, 1)
+    );
+
+    let scan = db.latest_scan()?.expect("a scan was recorded");
+    let findings: Vec<(Severity, String)> = db
+        .scan_policy_findings(scan.id)?
+        .into_iter()
+        .map(|finding| (finding.severity, finding.message))
+        .collect();
+    assert_eq!(
+        findings,
+        vec![
+            (
+                Severity::Error,
+                "CmdLine is forbidden, use Bash instead (azure-pipelines.yml:2)".to_string()
+            ),
+            (
+                Severity::Error,
+                "Pipeline does not run the required task CredScan (azure-pipelines.yml)"
+                    .to_string()
+            ),
+            (
+                Severity::Error,
+                "PowerShell@1 was sunset on 2000-01-01 (azure-pipelines.yml:3)".to_string()
+            ),
+            (
+                Severity::Warning,
+                "PowerShell@2 is deprecated and sunset on 2999-01-01, use Pwsh instead \
+                 (azure-pipelines.yml:4)"
+                    .to_string()
+            ),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_rejects_invalid_sunset_dates() -> Result<()> {
+    let (_dir, db) = test_database()?;
+    let policies =
+        serde_yaml::from_str("deprecated:\n  - task: PowerShell\n    sunset: next year\n")?;
+    assert!(db.replace_task_policies(&policies).is_err());
+    Ok(())
+}
@@ -0,0 +1,269 @@
+use anyhow::Result;
+use gavin::azure_devops::AzureDevOpsClient;
+use gavin::github_api::{GithubClient, GithubRepo};
+use gavin::gitlab_api::{GitlabClient, GitlabProject};
+use gavin::pull_request::{NewPullRequest, PullRequestClients, PullRequestOutcome};
+use serde_json::json;
+use wiremock::matchers::{body_json, header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn clients(server: &MockServer) -> PullRequestClients {
+    PullRequestClients {
+        azure: AzureDevOpsClient::new(&server.uri(), "user", "token"),
+        github: GithubClient::new(&format!("{}/api/v3", server.uri()), "token"),
+        gitlab: GitlabClient::new("https://gitlab.example.com/api/v4", "token"),
+    }
+}
+
+fn fix_pull_request(reviewers: &[&str]) -> NewPullRequest {
+    NewPullRequest {
+        source_branch: "gavin/update-task-versions-20240131".to_string(),
+        title: "Update github:actions/checkout to the standard version".to_string(),
+        description: "- `github:actions/checkout @v3 → @v4`\n".to_string(),
+        reviewers: reviewers.iter().map(|r| r.to_string()).collect(),
+        work_items: vec!["12".to_string()],
+    }
+}
+
+#[test]
+fn test_repositories_are_matched_to_their_host() {
+    let github = GithubClient::new("https://github.example.com/api/v3", "token");
+    assert_eq!(github.clone_host(), "github.example.com");
+    assert_eq!(
+        GithubRepo::parse(
+            "https://github.example.com/platform/api.git",
+            "github.example.com"
+        ),
+        Some(GithubRepo {
+            owner: "platform".to_string(),
+            name: "api".to_string()
+        })
+    );
+    assert_eq!(
+        GithubClient::new("https://api.github.com", "token").clone_host(),
+        "github.com"
+    );
+
+    assert_eq!(
+        GitlabProject::parse("https://gitlab.com/group/sub/api.git", "gitlab.com"),
+        Some(GitlabProject {
+            path: "group/sub/api".to_string()
+        })
+    );
+    assert_eq!(
+        GitlabProject::parse("https://github.com/group/api", "gitlab.com"),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_opens_github_pull_request_with_reviewers() -> Result<()> {
+    let server = MockServer::start().await;
+    let repo = "/api/v3/repos/platform/api";
+    Mock::given(method("GET"))
+        .and(path(format!("{}/pulls", repo)))
+        .and(query_param("state", "open"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(repo))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "default_branch": "main" })))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(format!("{}/pulls", repo)))
+        .and(header("authorization", "Bearer token"))
+        .and(body_json(json!({
+            "title": "Update github:actions/checkout to the standard version",
+            "head": "gavin/update-task-versions-20240131",
+            "base": "main",
+            "body": "- `github:actions/checkout @v3 → @v4`\n\nRelated to #12\n"
+        })))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+            "number": 5,
+            "html_url": "https://github.example.com/platform/api/pull/5",
+            "head": { "ref": "gavin/update-task-versions-20240131" }
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(format!("{}/pulls/5/requested_reviewers", repo)))
+        .and(body_json(json!({ "reviewers": ["octocat"] })))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({ "number": 5 })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    // The stub server stands in for the GitHub host
+    let repo_url = format!("{}/platform/api.git", server.uri());
+    let outcome = clients(&server)
+        .open_fix_pull_request(&repo_url, &fix_pull_request(&["octocat"]), "gavin/")
+        .await?;
+
+    match outcome {
+        PullRequestOutcome::Created(pr) => {
+            assert_eq!(pr.id, 5);
+            assert_eq!(pr.web_url, "https://github.example.com/platform/api/pull/5");
+        }
+        other => panic!("Expected a new pull request, got {:?}", other),
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_github_finds_gavin_pull_requests_past_the_first_page() -> Result<()> {
+    let server = MockServer::start().await;
+    let repo = "/api/v3/repos/platform/api";
+    let pull = |number: u64, branch: &str, head_repo: &str| {
+        json!({
+            "number": number,
+            "html_url": format!("https://github.example.com/platform/api/pull/{}", number),
+            "head": { "ref": branch, "repo": { "full_name": head_repo } }
+        })
+    };
+    Mock::given(method("GET"))
+        .and(path(format!("{}/pulls", repo)))
+        .and(query_param(
+            "head",
+            "platform:gavin/update-task-versions-20240131",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+        .mount(&server)
+        .await;
+    let first_page: Vec<_> = (1..=100)
+        .map(|number| pull(number, &format!("feature/{}", number), "platform/api"))
+        .collect();
+    Mock::given(method("GET"))
+        .and(path(format!("{}/pulls", repo)))
+        .and(query_param("page", "1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(first_page))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!("{}/pulls", repo)))
+        .and(query_param("page", "2"))
+        // A fork's branch of the same name is not ours
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+            pull(101, "gavin/update-task-versions-20240131", "someone/api"),
+            pull(102, "gavin/update-task-versions-20240101", "platform/api"),
+        ])))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let repo_url = format!("{}/platform/api.git", server.uri());
+    let outcome = clients(&server)
+        .open_fix_pull_request(&repo_url, &fix_pull_request(&[]), "gavin/")
+        .await?;
+
+    match outcome {
+        PullRequestOutcome::Existing(pr) => assert_eq!(pr.id, 102),
+        other => panic!("Expected the open pull request, got {:?}", other),
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_gitlab_finds_gavin_merge_requests_past_the_first_page() -> Result<()> {
+    let server = MockServer::start().await;
+    let merge_requests = "/api/v4/projects/group%2Fapi/merge_requests";
+    let merge_request = |iid: u64, branch: &str| {
+        json!({
+            "iid": iid,
+            "source_branch": branch,
+            "web_url": format!("https://gitlab.example.com/group/api/-/merge_requests/{}", iid)
+        })
+    };
+    Mock::given(method("GET"))
+        .and(path(merge_requests))
+        .and(query_param(
+            "source_branch",
+            "gavin/update-task-versions-20240131",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+        .mount(&server)
+        .await;
+    let first_page: Vec<_> = (1..=100)
+        .map(|iid| merge_request(iid, &format!("feature/{}", iid)))
+        .collect();
+    Mock::given(method("GET"))
+        .and(path(merge_requests))
+        .and(query_param("page", "1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(first_page))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(merge_requests))
+        .and(query_param("page", "2"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!([merge_request(
+                101,
+                "gavin/update-task-versions-20240101"
+            )])),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let client = GitlabClient::new(&format!("{}/api/v4", server.uri()), "token");
+    let project = GitlabProject {
+        path: "group/api".to_string(),
+    };
+    let outcome = client
+        .open_fix_pull_request(&project, &fix_pull_request(&[]), "gavin/")
+        .await?;
+
+    match outcome {
+        PullRequestOutcome::Existing(mr) => assert_eq!(mr.id, 101),
+        other => panic!("Expected the open merge request, got {:?}", other),
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_gitlab_reuses_open_merge_request_from_same_branch() -> Result<()> {
+    let server = MockServer::start().await;
+    let project = "/api/v4/projects/group%2Fsub%2Fapi";
+    Mock::given(method("GET"))
+        .and(path(format!("{}/merge_requests", project)))
+        .and(header("private-token", "token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([{
+            "iid": 3,
+            "source_branch": "gavin/update-task-versions-20240131",
+            "web_url": "https://gitlab.example.com/group/sub/api/-/merge_requests/3"
+        }])))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let client = GitlabClient::new(&format!("{}/api/v4", server.uri()), "token");
+    let gitlab_project = GitlabProject {
+        path: "group/sub/api".to_string(),
+    };
+    // No shared prefix, so only the same branch counts
+    let outcome = client
+        .open_fix_pull_request(&gitlab_project, &fix_pull_request(&[]), "")
+        .await?;
+    assert!(matches!(outcome, PullRequestOutcome::Existing(mr) if mr.id == 3));
+
+    // Reviewers must be numeric user IDs
+    let created = client
+        .create_merge_request(&gitlab_project, "main", &fix_pull_request(&["octocat"]))
+        .await;
+    assert!(created.is_err());
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use anyhow::Result;
+use gavin::scan::{self, ScanRef, ScannedRepository, TaskOccurrence, TrendFormat};
+use gavin::Database;
+use tempfile::tempdir;
+
+const API: &str = "https://github.com/contoso/api";
+const WEB: &str = "https://github.com/contoso/web";
+
+fn repository(url: &str) -> ScannedRepository {
+    ScannedRepository {
+        url: url.to_string(),
+        commit_sha: None,
+    }
+}
+
+fn occurrence(repo_url: &str, task: &str, version: &str, line: usize) -> TaskOccurrence {
+    TaskOccurrence {
+        repo_url: repo_url.to_string(),
+        task: task.to_string(),
+        version: version.to_string(),
+        pipeline: "azure-pipelines.yml".to_string(),
+        file: "azure-pipelines.yml".to_string(),
+        line,
+        issue: (version == "1").then(|| format!("1 is not a valid version of {}", task)),
+    }
+}
+
+#[test]
+fn test_compares_scans_and_tracks_compliance() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let db = Database::in_memory(temp_dir.path())?;
+
+    let first = db.record_scan(
+        &[repository(API)],
+        &[
+            occurrence(API, "PowerShell", "1", 3),
+            occurrence(API, "CopyFiles", "2", 5),
+        ],
+        &[],
+    )?;
+    // Lines moved, PowerShell was upgraded and CopyFiles downgraded
+    let second = db.record_scan(
+        &[repository(API), repository(WEB)],
+        &[
+            occurrence(API, "PowerShell", "2", 4),
+            occurrence(API, "CopyFiles", "1", 6),
+            occurrence(WEB, "Npm", "1", 2),
+        ],
+        &[],
+    )?;
+
+    let diff = scan::diff(&db.scan_results(first.id)?, &db.scan_results(second.id)?);
+    let describe = |occurrences: &[TaskOccurrence]| -> Vec<(String, String)> {
+        occurrences
+            .iter()
+            .map(|o| (o.task.clone(), o.version.clone()))
+            .collect()
+    };
+    assert_eq!(
+        describe(&diff.newly_invalid),
+        [("CopyFiles".to_string(), "1".to_string())]
+    );
+    assert_eq!(
+        describe(&diff.fixed),
+        [("PowerShell".to_string(), "1".to_string())]
+    );
+    assert_eq!(diff.added_repositories, [WEB]);
+    assert_eq!(diff.version_changes.len(), 2);
+    assert_eq!(
+        (
+            diff.version_changes[1].from.clone(),
+            diff.version_changes[1].to.clone()
+        ),
+        (vec!["1".to_string()], vec!["2".to_string()])
+    );
+
+    let today = ScanRef::Date(chrono::Utc::now().date_naive());
+    assert_eq!(db.find_scan(today)?, Some(second.clone()));
+    assert_eq!(db.find_scan("2000-01-01".parse().unwrap())?, None);
+
+    let csv = scan::trend_table(&db.list_scans()?, TrendFormat::Csv);
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "Scan,Date,Repositories,Occurrences,Valid,Compliance (%)"
+    );
+    assert!(lines[1].ends_with(",1,2,1,50.0"));
+    assert!(lines[2].ends_with(",2,3,1,33.3"));
+    Ok(())
+}
@@ -0,0 +1,155 @@
+mod common;
+
+use anyhow::Result;
+use clap::Parser;
+use common::{check_tasks, committed_checkout, test_database};
+use gavin::{handle_cli_args, Cli, GitVersionState, SupportedTask, TaskValidState};
+use std::fs;
+
+#[tokio::test]
+async fn test_check_tasks_records_a_scan() -> Result<()> {
+    let (_dir, db) = test_database()?;
+    db.add_valid_state(
+        &SupportedTask::Default("PowerShell".to_string()),
+        &TaskValidState::Default("2".to_string()),
+    )?;
+    let url = "https://dev.azure.com/contoso/Platform/_git/api";
+    let head = committed_checkout(
+        &db,
+        url,
+        &[(
+            "azure-pipelines.yml",
+            "steps:\n  - task: PowerShell@2\n  - task: CopyFiles@1\n",
+        )],
+    )?;
+
+    check_tasks(&db, &[]).await?;
+
+    let scans = db.list_scans()?;
+    assert_eq!(scans.len(), 1);
+    assert_eq!((scans[0].occurrences, scans[0].invalid), (2, 1));
+
+    let repositories = db.scan_repositories(scans[0].id)?;
+    assert_eq!(repositories[0].commit_sha.as_deref(), Some(head.as_str()));
+
+    let occurrences = db.scan_occurrences(scans[0].id)?;
+    let copy_files = &occurrences[0];
+    assert_eq!(
+        (
+            copy_files.task.as_str(),
+            copy_files.file.as_str(),
+            copy_files.line
+        ),
+        ("CopyFiles", "azure-pipelines.yml", 3)
+    );
+    assert_eq!(
+        copy_files.issue.as_deref(),
+        Some("No valid states are set for CopyFiles")
+    );
+    assert!(occurrences[1].is_valid());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_scan_marks_every_task_of_an_invalid_gitversion_pair() -> Result<()> {
+    let (_dir, db) = test_database()?;
+    db.add_valid_state(
+        &SupportedTask::Gitversion,
+        &TaskValidState::Gitversion(GitVersionState::new("3", "3", "5.x")),
+    )?;
+    committed_checkout(
+        &db,
+        "https://dev.azure.com/contoso/Platform/_git/api",
+        &[(
+            "azure-pipelines.yml",
+            "steps:\n  - task: gitversion/setup@0\n    inputs:\n      versionSpec: '5.x'\n  - task: gitversion/execute@0\n",
+        )],
+    )?;
+
+    check_tasks(&db, &[]).await?;
+
+    let scan = db.latest_scan()?.expect("a scan was recorded");
+    assert_eq!((scan.occurrences, scan.invalid), (2, 2));
+    let occurrences = db.scan_occurrences(scan.id)?;
+    let tasks: Vec<&str> = occurrences.iter().map(|o| o.task.as_str()).collect();
+    assert_eq!(tasks, ["gitversion/execute", "gitversion/setup"]);
+    for occurrence in &occurrences {
+        assert_eq!(
+            occurrence.issue.as_deref(),
+            Some("The GitVersion setup, execute and versionSpec combination is not a valid state")
+        );
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_gitversion_tasks_are_checked_and_fixed_whatever_their_case() -> Result<()> {
+    let (_dir, db) = test_database()?;
+    db.add_valid_state(
+        &SupportedTask::Gitversion,
+        &TaskValidState::Gitversion(GitVersionState::new("3", "3", "5.12.0")),
+    )?;
+    let url = "https://dev.azure.com/contoso/Platform/_git/api";
+    committed_checkout(
+        &db,
+        url,
+        &[(
+            "azure-pipelines.yml",
+            "steps:\n  - task: GitVersion/setup@0\n    inputs:\n      versionSpec: '5.x'\n  - task: GitVersion/Execute@0\n",
+        )],
+    )?;
+
+    check_tasks(&db, &[]).await?;
+
+    let scan = db.latest_scan()?.expect("a scan was recorded");
+    let occurrences = db.scan_occurrences(scan.id)?;
+    let tasks: Vec<&str> = occurrences.iter().map(|o| o.task.as_str()).collect();
+    assert_eq!(tasks, ["gitversion/execute", "gitversion/setup"]);
+    assert!(occurrences
+        .iter()
+        .all(|occurrence| occurrence.issue.as_deref()
+            == Some(
+                "The GitVersion setup, execute and versionSpec combination is not a valid state"
+            )));
+
+    let cli = Cli::parse_from(["gavin", "--fix", "--no-update"]);
+    handle_cli_args(&cli, &db).await?;
+    let fixed = fs::read_to_string(db.get_local_path(url).join("azure-pipelines.yml"))?;
+    assert_eq!(
+        fixed,
+        "steps:\n  - task: GitVersion/setup@3\n    inputs:\n      versionSpec: '5.12.0'\n  - task: GitVersion/Execute@3\n"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_scan_keeps_same_named_repositories_on_different_hosts_apart() -> Result<()> {
+    let (_dir, db) = test_database()?;
+    db.add_valid_state(
+        &SupportedTask::Default("PowerShell".to_string()),
+        &TaskValidState::Default("2".to_string()),
+    )?;
+    let github = "https://github.com/contoso/api";
+    let gitlab = "https://gitlab.com/contoso/api";
+    for (url, version) in [(github, "2"), (gitlab, "1")] {
+        let pipeline = format!("steps:\n  - task: PowerShell@{}\n", version);
+        committed_checkout(&db, url, &[("azure-pipelines.yml", &pipeline)])?;
+    }
+
+    check_tasks(&db, &[]).await?;
+
+    let scan = db.latest_scan()?.expect("a scan was recorded");
+    let occurrences: Vec<(String, String, bool)> = db
+        .scan_occurrences(scan.id)?
+        .into_iter()
+        .map(|o| (o.repo_url.clone(), o.version.clone(), o.is_valid()))
+        .collect();
+    assert_eq!(
+        occurrences,
+        [
+            (github.to_string(), "2".to_string(), true),
+            (gitlab.to_string(), "1".to_string(), false),
+        ]
+    );
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use anyhow::Result;
+use gavin::secrets::{
+    CredentialScope, CredentialSource, KeySource, SecretKey, DEFAULT_KEY_FILE, KEY_VAR,
+};
+use gavin::workspace::Environment;
+use gavin::{Database, RepoId, Workspace};
+use rusqlite::{params, Connection};
+use tempfile::tempdir;
+
+#[test]
+fn test_encrypts_stored_tokens_and_migrates_legacy_ones() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let workspace = Workspace::new(temp_dir.path()).with_environment(Environment::fixed(&[]));
+    let db = Database::open(&workspace)?;
+    let db_path = temp_dir.path().join("gavin.db");
+
+    // A token XORed with 0xFF, as older versions stored it
+    let legacy: Vec<u8> = b"old-token".iter().map(|b| b ^ 0xFF).collect();
+    Connection::open(&db_path)?.execute(
+        "INSERT INTO git_credentials (username, token) VALUES (?1, ?2)",
+        params!["user", legacy],
+    )?;
+
+    let url = "https://dev.azure.com/contoso/Platform/_git/api";
+    let key_file = temp_dir.path().join(DEFAULT_KEY_FILE);
+    // Reading it changes nothing until it is encrypted on request
+    let error = db.credentials_for(url).unwrap_err().to_string();
+    assert!(error.contains("--encrypt-credentials"), "{}", error);
+    assert!(!key_file.exists());
+    assert_eq!(
+        db.encrypt_legacy_tokens()?,
+        Some((1, KeySource::File(key_file.clone())))
+    );
+    assert_eq!(db.encrypt_legacy_tokens()?, None);
+
+    let credentials = db.credentials_for(url)?.unwrap();
+    assert_eq!(credentials.token, "old-token");
+    assert_eq!(
+        credentials.source,
+        CredentialSource::Database(Some(KeySource::File(key_file.clone())))
+    );
+    assert!(key_file.exists());
+
+    db.set_git_credentials("user:s3cr3t", &CredentialScope::default())?;
+    let stored: Vec<u8> =
+        Connection::open(&db_path)?
+            .query_row("SELECT token FROM git_credentials", [], |row| row.get(0))?;
+    assert!(!String::from_utf8_lossy(&stored).contains("s3cr3t"));
+    assert_eq!(db.credentials_for(url)?.unwrap().token, "s3cr3t");
+
+    // Without the key the token cannot be read
+    std::fs::remove_file(&key_file)?;
+    assert!(db.credentials_for(url).is_err());
+
+    let db = Database::open(
+        &workspace.with_environment(Environment::fixed(&[("GAVIN_TOKEN", "from-env")])),
+    )?;
+    let credentials = db.credentials_for(url)?.unwrap();
+    assert_eq!(credentials.token, "from-env");
+    assert_eq!(
+        credentials.source,
+        CredentialSource::Environment("GAVIN_TOKEN")
+    );
+
+    // Credentials for the organization win over the environment, elsewhere not
+    db.set_git_credentials(
+        "ci:scoped",
+        &CredentialScope::parse("dev.azure.com/contoso"),
+    )?;
+    assert_eq!(db.credentials_for(url)?.unwrap().token, "scoped");
+    let github = db.credentials_for("https://github.com/contoso/api")?;
+    assert_eq!(github.unwrap().token, "from-env");
+    Ok(())
+}
+
+#[test]
+fn test_loads_the_key_from_the_environment_first() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let key_file = temp_dir.path().join(DEFAULT_KEY_FILE);
+    // 32 zero bytes
+    let environment = Environment::fixed(&[(KEY_VAR, &format!("{}=", "A".repeat(43)))]);
+
+    let key = SecretKey::load(&key_file, true, &environment)?.unwrap();
+    assert_eq!(key.source, KeySource::Environment);
+    assert!(!key_file.exists());
+    assert!(SecretKey::load(&key_file, false, &Environment::fixed(&[]))?.is_none());
+    Ok(())
+}
+
+#[test]
+fn test_credential_scopes_match_by_host_and_path() {
+    let repo = RepoId::parse("git@ssh.dev.azure.com:v3/contoso/Platform/api");
+    let specificity = |scope: &str| CredentialScope::parse(scope).specificity(&repo);
+
+    assert_eq!(CredentialScope::default().specificity(&repo), Some(0));
+    assert_eq!(specificity("dev.azure.com"), Some(1));
+    assert_eq!(specificity("https://dev.azure.com/Contoso/"), Some(2));
+    assert_eq!(specificity("dev.azure.com/contoso/platform"), Some(3));
+    assert_eq!(specificity("dev.azure.com/contoso/Payments"), None);
+    assert_eq!(specificity("dev.azure.com/cont"), None);
+    assert_eq!(specificity("github.com"), None);
+    assert_eq!(
+        CredentialScope::parse("dev.azure.com/contoso").to_string(),
+        "dev.azure.com/contoso"
+    );
+}
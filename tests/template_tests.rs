@@ -0,0 +1,171 @@
+use anyhow::Result;
+use gavin::TemplateResolver;
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn write(root: &Path, path: &str, content: &str) -> Result<()> {
+    let path = root.join(path);
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, content)?;
+    Ok(())
+}
+
+#[test]
+fn test_local_templates_are_attributed_to_pipeline() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let repo = temp_dir.path().join("app");
+    write(
+        &repo,
+        "azure-pipelines.yml",
+        "\
+steps:
+  - template: templates/build.yml
+    parameters:
+      nodeVersion: 2
+",
+    )?;
+    write(
+        &repo,
+        "templates/build.yml",
+        "\
+parameters:
+  - name: nodeVersion
+    default: 1
+steps:
+  - task: UseNode@${{ parameters.nodeVersion }}
+  - template: ../templates/test.yml
+",
+    )?;
+    write(&repo, "templates/test.yml", "steps:\n  - task: VSTest@2\n")?;
+
+    let files = vec![
+        repo.join("azure-pipelines.yml"),
+        repo.join("templates/build.yml"),
+        repo.join("templates/test.yml"),
+    ];
+    let resolver = TemplateResolver::new(Vec::new());
+    let pipelines = resolver.pipeline_tasks(&repo, &files);
+
+    assert_eq!(pipelines.len(), 1);
+    assert_eq!(pipelines[0].pipeline, repo.join("azure-pipelines.yml"));
+
+    let tasks = &pipelines[0].tasks;
+    assert_eq!(tasks.len(), 2);
+
+    assert_eq!(tasks[0].task.name, "UseNode");
+    assert_eq!(tasks[0].task.version, "2");
+    assert_eq!(tasks[0].source, repo.join("templates/build.yml"));
+    assert_eq!(tasks[0].task.location.line, 5);
+    assert_eq!(
+        tasks[0].include_chain,
+        vec![repo.join("templates/build.yml")]
+    );
+
+    assert_eq!(tasks[1].task.name, "VSTest");
+    assert_eq!(
+        tasks[1].include_chain,
+        vec![
+            repo.join("templates/build.yml"),
+            repo.join("templates/test.yml")
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_extends_repository_alias_and_step_list_parameters() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let app = temp_dir.path().join("app");
+    let templates = temp_dir.path().join("templates");
+    write(
+        &app,
+        "ci.yml",
+        "\
+resources:
+  repositories:
+    - repository: shared
+      type: git
+      name: Platform/Pipeline%20Templates
+extends:
+  template: stages/build.yml@shared
+  parameters:
+    environment: prod
+    buildSteps:
+      - task: DotNetCoreCLI@2
+",
+    )?;
+    write(
+        &templates,
+        "stages/build.yml",
+        "\
+parameters:
+  - name: environment
+    default: dev
+  - name: buildSteps
+    type: stepList
+    default: []
+stages:
+  - stage: Build
+    jobs:
+      - job: Build
+        steps:
+          - task: gitversion/setup@3
+          - ${{ parameters.buildSteps }}
+          - ${{ if eq(parameters.environment, 'prod') }}:
+            - task: SignTool@1
+          - ${{ else }}:
+            - task: SkipSigning@1
+",
+    )?;
+
+    let resolver = TemplateResolver::new(vec![(
+        "https://dev.azure.com/org/Platform/_git/Pipeline Templates".to_string(),
+        templates.clone(),
+    )]);
+    let tasks = resolver.expand(&app, &app.join("ci.yml"))?;
+
+    let names: Vec<_> = tasks.iter().map(|t| t.task.name.as_str()).collect();
+    assert_eq!(names, ["gitversion/setup", "DotNetCoreCLI", "SignTool"]);
+
+    // Tasks passed in as a step list are written in the pipeline itself
+    assert_eq!(tasks[1].source, app.join("ci.yml"));
+    assert_eq!(tasks[1].task.location.line, 11);
+    assert_eq!(
+        tasks[1].include_chain,
+        vec![templates.join("stages/build.yml")]
+    );
+
+    assert_eq!(tasks[2].source, templates.join("stages/build.yml"));
+    assert_eq!(tasks[2].task.location.line, 15);
+
+    Ok(())
+}
+
+#[test]
+fn test_unresolvable_templates_are_skipped() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let repo = temp_dir.path().join("app");
+    write(
+        &repo,
+        "pipeline.yml",
+        "\
+steps:
+  - template: missing.yml
+  - template: build.yml@unknown
+  - template: self.yml
+  - task: PowerShell@2
+",
+    )?;
+    write(&repo, "self.yml", "steps:\n  - template: pipeline.yml\n")?;
+
+    let resolver = TemplateResolver::new(Vec::new());
+    let tasks = resolver.expand(&repo, &repo.join("pipeline.yml"))?;
+
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].task.name, "PowerShell");
+    assert!(tasks[0].include_chain.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use anyhow::Result;
+use gavin::platform;
+use gavin::version_spec::{is_range, matches};
+use gavin::{GitVersionState, SupportedTask, TaskValidState};
+
+#[test]
+fn test_ranges_and_wildcards_match_versions() {
+    assert!(matches("2", "2"));
+    assert!(matches("2", "2.0.0"));
+    assert!(!matches("2", "3"));
+
+    for version in ["2", "3", "3.9.1"] {
+        assert!(matches(">=2, <4", version), "{} is in >=2, <4", version);
+    }
+    assert!(!matches(">=2, <4", "4"));
+    assert!(!matches(">=2, <4", "1"));
+
+    assert!(matches("^6.0", "6.0.3"));
+    assert!(matches("6.x", "6.2.0"));
+    assert!(!matches("6.x", "5.12.0"));
+    // A versionSpec that is itself a wildcard only matches the same text
+    assert!(matches("6.x", "6.x"));
+    assert!(!matches("^6.0", "6.x"));
+
+    assert!(is_range("6.x") && is_range(">=2, <4") && !is_range("6.0.3"));
+}
+
+#[test]
+fn test_state_values_accept_ranges() -> Result<()> {
+    let gitversion = GitVersionState::from_string("setup:>=3, <5,execute:3,spec:>=6.0, <7")
+        .map_err(anyhow::Error::msg)?;
+    assert_eq!(
+        gitversion,
+        GitVersionState::new(">=3, <5", "3", ">=6.0, <7")
+    );
+
+    let azure = platform::for_task("powershell");
+    let task = SupportedTask::Default("powershell".to_string());
+    let state = azure.parse_state(&task, ">=2, <4")?;
+    assert!(state.has_range());
+    assert!(azure.version_matches(&state, "3"));
+    assert_eq!(
+        azure.target_version(&[state, TaskValidState::Default("2".to_string())]),
+        Some("2".to_string())
+    );
+    assert!(azure.parse_state(&task, "latest").is_err());
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use gavin::platform::for_task;
+use gavin::workflow::extract_actions;
+use gavin::{parse_task_name, GitRef, SupportedTask, TaskValidState};
+
+#[test]
+fn test_extracts_actions_and_reusable_workflows() {
+    let content = "\
+on: [push]
+jobs:
+  build:
+    runs-on: ubuntu-latest
+    steps:
+      - uses: actions/checkout@v4
+      - uses: ./.github/actions/local
+      - name: Node
+        uses: \"actions/setup-node@1d0ff469b7ec7b3cb9d8673fde0c81c44821de2a\"
+        with:
+          node-version: 20
+      - run: 'echo uses: fake/action@v1'
+  deploy:
+    uses: octo-org/shared/.github/workflows/deploy.yml@main
+";
+    let actions = extract_actions(content).unwrap();
+    assert_eq!(actions.len(), 3);
+
+    assert_eq!(actions[0].name, "actions/checkout");
+    assert_eq!(actions[0].git_ref, "v4");
+    assert_eq!(actions[0].location.line, 6);
+    assert_eq!(actions[0].location.column, 15);
+    assert_eq!(
+        &content[actions[0].location.offset..][..19],
+        "actions/checkout@v4"
+    );
+
+    assert_eq!(actions[1].name, "actions/setup-node");
+    assert_eq!(actions[1].with["node-version"], "20");
+    assert_eq!(actions[1].location.line, 9);
+
+    assert_eq!(
+        actions[2].name,
+        "octo-org/shared/.github/workflows/deploy.yml"
+    );
+    assert_eq!(actions[2].git_ref, "main");
+
+    let task = actions[0].clone().into_task_reference();
+    assert_eq!(task.name, "github:actions/checkout");
+    assert_eq!(
+        parse_task_name(&task.name).unwrap(),
+        SupportedTask::GithubAction("actions/checkout".to_string())
+    );
+
+    // Azure pipelines are not workflows
+    assert!(extract_actions("steps:\n  - task: PowerShell@2\n")
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_git_ref_states() {
+    let sha = "1d0ff469b7ec7b3cb9d8673fde0c81c44821de2a";
+    let github = for_task("github:actions/checkout");
+    assert_eq!(github.name(), "GitHub Actions");
+    let matches = |state: &TaskValidState, version| github.version_matches(state, version);
+
+    let tag = TaskValidState::GitRef(GitRef::from_string("v4").unwrap());
+    assert!(matches(&tag, "v4"));
+    assert!(matches(&tag, "v4.0.0"));
+    assert!(!matches(&tag, "v3"));
+    assert!(!matches(&tag, sha));
+
+    let branch = TaskValidState::GitRef(GitRef::from_string("branch:main").unwrap());
+    assert!(matches(&branch, "main"));
+    assert!(!matches(&branch, "master"));
+
+    let commit = GitRef::from_string(&sha.to_uppercase()).unwrap();
+    assert!(matches!(commit, GitRef::Sha(_)));
+    assert!(matches(&TaskValidState::GitRef(commit), sha));
+
+    assert!(GitRef::from_string("sha:1d0ff46").is_err());
+    assert!(GitRef::from_string("branch:").is_err());
+
+    let json = serde_json::to_string(&branch).unwrap();
+    let deserialized: TaskValidState = serde_json::from_str(&json).unwrap();
+    assert_eq!(branch, deserialized);
+}
@@ -0,0 +1,42 @@
+use anyhow::Result;
+use gavin::workspace::{Environment, HOME_VAR};
+use gavin::{Database, Workspace};
+use tempfile::tempdir;
+
+#[test]
+fn test_resolves_data_directory_and_shares_in_memory_databases() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let config = temp_dir.path().join("gavinconfig.yml");
+    std::fs::write(&config, "data_dir: from-config\n")?;
+    let config = config.to_str();
+
+    let workspace = Workspace::resolve(None, config, Environment::fixed(&[]))?;
+    assert_eq!(workspace.root(), temp_dir.path().join("from-config"));
+    assert!(workspace.root().is_dir());
+
+    let home = temp_dir.path().join("home");
+    let environment = Environment::fixed(&[(HOME_VAR, &home.to_string_lossy())]);
+    let workspace = Workspace::resolve(None, config, environment.clone())?;
+    assert_eq!(workspace.root(), temp_dir.path().join("home"));
+
+    let flag = temp_dir.path().join("flag");
+    let workspace = Workspace::resolve(flag.to_str(), config, environment)?;
+    assert_eq!(workspace.database_path(), flag.join("gavin.db"));
+    assert_eq!(
+        workspace.checkout_dir("https://github.com/contoso/api"),
+        flag.join("temp_repos/github.com/contoso/api")
+    );
+
+    // Connections to an in-memory workspace see the same data, other ones do not
+    let db = Database::in_memory(temp_dir.path())?;
+    db.add_repository_sync("https://github.com/contoso/api")?;
+    assert_eq!(
+        Database::open(db.workspace())?.list_repositories()?.len(),
+        1
+    );
+    assert!(Database::in_memory(temp_dir.path())?
+        .list_repositories()?
+        .is_empty());
+    assert!(!temp_dir.path().join("gavin.db").exists());
+    Ok(())
+}